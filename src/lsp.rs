@@ -1,46 +1,71 @@
-use std::io::Read;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
 use std::process::ChildStdin;
+use std::rc::Rc;
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::task::Poll;
+use std::time::{Duration, Instant};
 use std::{
     io::Write,
-    process::{self, Child, ChildStdout, Stdio},
+    process::{self, Child, Stdio},
     thread::JoinHandle,
 };
 
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::widget::symbols::SymbolLocation;
+
+/// How long an answer may take before whoever asked makes do without it
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// The id of the initialize request, the first one sent
+const INITIALIZE_ID: u32 = 0;
 
 pub struct LSP {
     process: Child,
     read_thread: JoinHandle<()>,
     stdin: ChildStdin,
+    /// Results of requests by id, sent by the read thread
+    responses: Receiver<(u32, Value)>,
+    /// Results that came in but weren't picked up yet
+    answers: HashMap<u32, Value>,
+    /// Requests nobody waits for anymore, whose results are dropped when they come
+    abandoned: HashSet<u32>,
+    /// Requests made before the server answered the initialize request, sent once it did
+    queued: Option<Vec<String>>,
+    next_id: u32,
+    /// The read thread ended, so no more answers will come
+    stopped: bool,
 }
 
 impl LSP {
-    pub fn new() -> Self {
+    /// Start the server without waiting for it to initialize, requests being queued until it did
+    pub fn new() -> io::Result<Self> {
         let lsp_name = "rust-analyzer";
 
-        let mut process = process::Command::new(lsp_name)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()
-            .expect("Failed to start lsp");
-
-        let mut stdin = process.stdin.take().expect("Failed to get stdin");
-        let stdout = process.stdout.take().expect("Failed to get stdout");
-
-        let read_thread = std::thread::spawn(|| handle_read_thread(stdout));
-
         let pid = std::process::id();
         let uri = lsp_types::Uri::from_str(&format!(
             "file:///{}",
-            std::env::current_dir()
-                .unwrap()
+            std::env::current_dir()?
                 .display()
                 .to_string()
                 .replace("\\", "/")
         ))
-        .unwrap();
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error.to_string()))?;
+
+        let mut process = process::Command::new(lsp_name)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let stdin = process.stdin.take().expect("Failed to get stdin");
+        let stdout = process.stdout.take().expect("Failed to get stdout");
+
+        let (sender, responses) = mpsc::channel();
+        let read_thread = std::thread::spawn(|| handle_read_thread(BufReader::new(stdout), sender));
         let folder = lsp_types::WorkspaceFolder {
             uri,
             name: "Main".to_string(),
@@ -51,18 +76,291 @@ impl LSP {
             ..Default::default()
         };
 
-        write_content(&mut stdin, &request(0, "initialize", &params));
-        write_content(
-            &mut stdin,
-            &notification("initialized", &lsp_types::InitializedParams {}),
-        );
-
-        Self {
+        let mut lsp = Self {
             process,
             read_thread,
             stdin,
+            responses,
+            answers: HashMap::new(),
+            abandoned: HashSet::new(),
+            queued: Some(Vec::new()),
+            next_id: INITIALIZE_ID + 1,
+            stopped: false,
+        };
+
+        // Nothing but the initialize request may be sent until the server answered it
+        write_content(
+            &mut lsp.stdin,
+            &request(INITIALIZE_ID, "initialize", &params),
+        )?;
+
+        Ok(lsp)
+    }
+
+    /// Send a request, or queue it until the server initialized, returning its id. The result is
+    /// picked up with [`LSP::take_answer`].
+    fn send<P>(&mut self, method: &str, params: &P) -> Option<u32>
+    where
+        P: ?Sized + Serialize,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        let message = request(id, method, params);
+        match &mut self.queued {
+            Some(queued) => queued.push(message),
+            None => write_content(&mut self.stdin, &message).ok()?,
+        }
+        Some(id)
+    }
+
+    /// The result of request `id` if it came
+    fn take_answer(&mut self, id: u32) -> Option<Value> {
+        self.receive();
+        self.answers.remove(&id)
+    }
+
+    /// Stop waiting for request `id`
+    fn forget(&mut self, id: u32) {
+        if self.answers.remove(&id).is_none() {
+            self.abandoned.insert(id);
+        }
+    }
+
+    /// Keep what the read thread got so far
+    fn receive(&mut self) {
+        loop {
+            match self.responses.try_recv() {
+                Ok((id, result)) => {
+                    if id == INITIALIZE_ID {
+                        self.initialized();
+                    } else if !self.abandoned.remove(&id) {
+                        self.answers.insert(id, result);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.stopped = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Tell the server we got its answer to initialize, and send what waited for it
+    fn initialized(&mut self) {
+        let queued = self.queued.take().unwrap_or_default();
+        let notification = notification("initialized", &lsp_types::InitializedParams {});
+        for message in std::iter::once(notification).chain(queued) {
+            if let Err(error) = write_content(&mut self.stdin, &message) {
+                tracing::warn!("Can't write to the language server: {error}");
+            }
+        }
+    }
+}
+
+impl Drop for LSP {
+    fn drop(&mut self) {
+        // The read thread ends by itself once the server's output closes
+        if let Err(error) = self.process.kill() {
+            tracing::warn!("Can't stop the language server: {error}");
+        }
+        let _ = self.process.wait();
+    }
+}
+
+/// A request sent to the language server, whose result [`Pending::poll`] picks up when it comes.
+/// Dropping it forgets the request.
+pub struct Pending<T> {
+    server: LanguageServer,
+    id: u32,
+    sent: Instant,
+    /// Makes what was asked for of the result
+    parse: fn(Value) -> Option<T>,
+    answered: bool,
+}
+
+impl<T> Pending<T> {
+    /// The result once the server answered, with `None` in it if the request failed, took too
+    /// long or the server stopped. A result is only given once.
+    pub fn poll(&mut self) -> Poll<Option<T>> {
+        if self.answered {
+            return Poll::Ready(None);
+        }
+        let mut server = self.server.0.borrow_mut();
+        match server.take_answer(self.id) {
+            Some(result) => {
+                self.answered = true;
+                Poll::Ready((self.parse)(result))
+            }
+            None if server.stopped || self.sent.elapsed() > REQUEST_TIMEOUT => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Pending<T> {
+    fn drop(&mut self) {
+        if !self.answered {
+            self.server.0.borrow_mut().forget(self.id);
+        }
+    }
+}
+
+/// A running language server, shared by the widgets that ask it things
+#[derive(Clone)]
+pub struct LanguageServer(Rc<RefCell<LSP>>);
+
+impl LanguageServer {
+    pub fn new(lsp: LSP) -> Self {
+        Self(Rc::new(RefCell::new(lsp)))
+    }
+
+    fn request<P, T>(
+        &self,
+        method: &str,
+        params: &P,
+        parse: fn(Value) -> Option<T>,
+    ) -> Option<Pending<T>>
+    where
+        P: ?Sized + Serialize,
+    {
+        let id = self.0.borrow_mut().send(method, params)?;
+        Some(Pending {
+            server: self.clone(),
+            id,
+            sent: Instant::now(),
+            parse,
+            answered: false,
+        })
+    }
+
+    /// Symbols of the whole workspace matching `query`, by name with where they are
+    pub fn workspace_symbols(&self, query: &str) -> Option<Pending<Vec<SymbolLocation>>> {
+        let params = lsp_types::WorkspaceSymbolParams {
+            // rust-analyzer only looks for types unless the query ends with #
+            query: format!("{query}#"),
+            ..Default::default()
+        };
+        self.request("workspace/symbol", &params, |result| {
+            let response: Option<lsp_types::WorkspaceSymbolResponse> = parse(result)?;
+            let symbols = match response? {
+                lsp_types::WorkspaceSymbolResponse::Flat(symbols) => symbols
+                    .into_iter()
+                    .filter_map(|symbol| {
+                        Some(SymbolLocation {
+                            path: uri_to_path(&symbol.location.uri)?,
+                            line: symbol.location.range.start.line as usize,
+                            name: symbol.name,
+                        })
+                    })
+                    .collect(),
+                lsp_types::WorkspaceSymbolResponse::Nested(symbols) => symbols
+                    .into_iter()
+                    .filter_map(|symbol| match symbol.location {
+                        lsp_types::OneOf::Left(location) => Some(SymbolLocation {
+                            path: uri_to_path(&location.uri)?,
+                            line: location.range.start.line as usize,
+                            name: symbol.name,
+                        }),
+                        lsp_types::OneOf::Right(_) => None,
+                    })
+                    .collect(),
+            };
+            Some(symbols)
+        })
+    }
+
+    /// The ranges a selection at `position` of the file at `path` grows through, innermost first
+    pub fn selection_ranges(
+        &self,
+        path: &Path,
+        position: lsp_types::Position,
+    ) -> Option<Pending<Vec<lsp_types::Range>>> {
+        let params = lsp_types::SelectionRangeParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: path_to_uri(path)?,
+            },
+            positions: vec![position],
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        self.request("textDocument/selectionRange", &params, |result| {
+            let response: Option<Vec<lsp_types::SelectionRange>> = parse(result)?;
+            let mut ranges = Vec::new();
+            let mut next = response?.into_iter().next();
+            while let Some(range) = next {
+                ranges.push(range.range);
+                next = range.parent.map(|parent| *parent);
+            }
+            Some(ranges)
+        })
+    }
+
+    /// The regions of the file at `path` that can fold
+    pub fn folding_ranges(&self, path: &Path) -> Option<Pending<Vec<lsp_types::FoldingRange>>> {
+        let params = lsp_types::FoldingRangeParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: path_to_uri(path)?,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        self.request("textDocument/foldingRange", &params, |result| {
+            parse(result)?
+        })
+    }
+}
+
+fn parse<R: DeserializeOwned>(result: Value) -> Option<R> {
+    serde_json::from_value(result).ok()
+}
+
+/// The `file://` uri of `path`, relative paths being in the current directory
+fn path_to_uri(path: &Path) -> Option<lsp_types::Uri> {
+    let path = std::env::current_dir().ok()?.join(path);
+    let path = path.to_str()?.replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/:-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    lsp_types::Uri::from_str(&uri).ok()
+}
+
+/// The path of a `file://` uri
+fn uri_to_path(uri: &lsp_types::Uri) -> Option<PathBuf> {
+    let path = uri.as_str().strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| std::str::from_utf8(tail.get(..2)?).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
         }
     }
+    let path = String::from_utf8(bytes).ok()?;
+    // Windows paths look like /C:/...
+    let path = match path.get(2..3) {
+        Some(":") if path.starts_with('/') => path[1..].to_string(),
+        _ => path,
+    };
+    Some(PathBuf::from(path))
 }
 
 const CONTENT_LENGTH: &str = "Content-Length: ";
@@ -93,30 +391,21 @@ where
     .to_string()
 }
 
-fn write_content(output: &mut impl Write, message: &str) {
+fn write_content(output: &mut impl Write, message: &str) -> io::Result<()> {
     let len = message.len();
 
-    write!(output, "{}{}\r\n\r\n{}", CONTENT_LENGTH, len, message).unwrap();
-    println!("{}{}\r\n\r\n{}", CONTENT_LENGTH, len, message);
+    write!(output, "{}{}\r\n\r\n{}", CONTENT_LENGTH, len, message)?;
+    output.flush()?;
+    tracing::trace!("{}{}\r\n\r\n{}", CONTENT_LENGTH, len, message);
+    Ok(())
 }
 
-fn read_single_line(input: &mut impl Read) -> String {
+fn read_single_line(input: &mut impl Read) -> io::Result<String> {
     let mut line: Vec<u8> = Vec::new();
 
     let mut buf: [u8; 1] = [0];
     loop {
-        loop {
-            match input.read_exact(&mut buf) {
-                Ok(()) => {
-                    break;
-                }
-                Err(x) => {
-                    println!("{x:?}");
-                    std::thread::sleep(Duration::from_millis(1000))
-                }
-            }
-        }
-
+        input.read_exact(&mut buf)?;
         match buf[0] {
             b'\r' => {} //skip
             b'\n' => {
@@ -126,44 +415,120 @@ fn read_single_line(input: &mut impl Read) -> String {
         }
     }
 
-    String::from_utf8(line).expect("Header must be utf8 string")
+    String::from_utf8(line).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
 }
 
-fn handle_read_thread(mut input: ChildStdout) {
+/// Pass the server's answers on until its output ends, or until nobody listens anymore
+fn handle_read_thread(mut input: impl Read, responses: Sender<(u32, Value)>) {
     loop {
-        read_message(&mut input);
+        let content = match read_message(&mut input) {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                tracing::debug!("The language server closed its output");
+                break;
+            }
+            Err(error) => {
+                tracing::warn!("Can't read from the language server: {error}");
+                break;
+            }
+        };
+        let Ok(message) = serde_json::from_str::<Value>(&content) else {
+            continue;
+        };
+        // Requests and notifications from the server have a method, responses don't
+        if message.get("method").is_some() {
+            continue;
+        }
+        let Some(id) = message["id"].as_u64() else {
+            continue;
+        };
+        if let Some(error) = message.get("error") {
+            tracing::warn!("Language server error: {error}");
+        }
+        if responses
+            .send((id as u32, message["result"].clone()))
+            .is_err()
+        {
+            break;
+        }
     }
 }
 
-fn read_message(input: &mut ChildStdout) {
+fn read_message(input: &mut impl Read) -> io::Result<String> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
     let mut content_length: Option<usize> = None;
     let mut content_type: Option<String> = None;
 
     loop {
-        let line = read_single_line(input);
-        println!("read line: {line}");
+        let line = read_single_line(input)?;
+        tracing::trace!("read line: {line}");
         if line.is_empty() {
             break;
         }
 
-        if line.starts_with(CONTENT_LENGTH) {
-            let content_length_str = &line[CONTENT_LENGTH.len()..];
-            content_length = Some(content_length_str.parse().unwrap());
-        } else if line.starts_with(CONTENT_TYPE) {
-            content_type = Some(line[CONTENT_TYPE.len()..].to_string());
+        if let Some(content_length_str) = line.strip_prefix(CONTENT_LENGTH) {
+            let length = content_length_str.trim().parse().map_err(|error| {
+                invalid(format!(
+                    "bad content length `{content_length_str}`: {error}"
+                ))
+            })?;
+            content_length = Some(length);
+        } else if let Some(content_type_str) = line.strip_prefix(CONTENT_TYPE) {
+            content_type = Some(content_type_str.to_string());
         }
     }
 
-    let content_length = content_length.unwrap();
-    println!("Content-Length: {content_length}");
+    let content_length =
+        content_length.ok_or_else(|| invalid("message without a content length".to_string()))?;
     // TODO: We assume content type is utf8
-    println!("Content-Type  : {content_type:?}");
+    tracing::trace!("Content-Type  : {content_type:?}");
 
     let mut content = vec![0u8; content_length];
 
-    input.read_exact(&mut content).unwrap();
+    input.read_exact(&mut content)?;
+
+    let content = String::from_utf8(content)
+        .map_err(|error| invalid(format!("content isn't utf8: {error}")))?;
+
+    tracing::trace!("Content: {content}");
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let content = String::from_utf8(content).unwrap();
+    #[test]
+    fn reads_messages() {
+        let mut input: &[u8] =
+            b"Content-Length: 2\r\nContent-Type: utf-8\r\n\r\n{}Content-Length: 4\r\n\r\nnull";
+        assert_eq!(read_message(&mut input).unwrap(), "{}");
+        assert_eq!(read_message(&mut input).unwrap(), "null");
+        let end = read_message(&mut input).unwrap_err();
+        assert_eq!(end.kind(), io::ErrorKind::UnexpectedEof);
+    }
 
-    println!("Content: {content}");
+    #[test]
+    fn rejects_bad_headers() {
+        for message in [
+            &b"\r\n{}"[..],
+            b"Content-Length: two\r\n\r\n{}",
+            b"Content-Length: 2\r\n\r\n\xff\xfe",
+        ] {
+            let error = read_message(&mut &message[..]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        // Cut off in the middle of the content
+        let error = read_message(&mut &b"Content-Length: 10\r\n\r\n{}"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_thread_ends_with_the_output() {
+        let (sender, responses) = mpsc::channel();
+        let input: &[u8] = b"Content-Length: 23\r\n\r\n{\"id\":3,\"result\":[1,2]}";
+        handle_read_thread(input, sender);
+        assert_eq!(responses.recv().unwrap(), (3, serde_json::json!([1, 2])));
+        assert!(responses.recv().is_err());
+    }
 }
@@ -13,28 +13,59 @@ use widget::*;
 
 mod lsp;
 
-struct Driver;
+struct Driver {
+    /// Shared with the panel, which runs the commands on the next event it gets
+    commands: commands::Commands,
+}
 
 impl AppDriver for Driver {
-    fn on_action(&mut self, _ctx: &mut DriverCtx<'_>, _widget_id: WidgetId, action: Action) {
+    fn on_action(&mut self, _ctx: &mut DriverCtx<'_>, widget_id: WidgetId, action: Action) {
         match action {
-            Action::ButtonPressed(_) => {
-                println!("Hello!");
-            }
-            _ => {
-                todo!();
-            }
+            Action::Other(action) => match action.downcast::<commands::Command>() {
+                Ok(command) => self.commands.submit(*command),
+                Err(_) => tracing::warn!("Unknown action from {widget_id:?}"),
+            },
+            _ => tracing::debug!("Unhandled action from {widget_id:?}"),
         }
     }
 }
 
 fn main() {
-    // let lsp = lsp::LSP::new();
+    let language_server = match lsp::LSP::new() {
+        Ok(lsp) => Some(lsp::LanguageServer::new(lsp)),
+        Err(err) => {
+            tracing::warn!("Running without a language server: {err}");
+            None
+        }
+    };
+    let settings_path = std::path::Path::new("playground.settings.toml");
+    let settings = settings::Settings::load(settings_path).unwrap_or_else(|err| {
+        tracing::warn!("Using the default settings, {err}");
+        settings::Settings::default()
+    });
+    let registry = commands::CommandRegistry::default();
+    let keymap_path = std::path::Path::new("playground.keymap.toml");
+    let keymap = keymap::Keymap::load(keymap_path, &registry).unwrap_or_else(|err| {
+        tracing::warn!("Using the default keymap, {err}");
+        keymap::Keymap::new(&registry)
+    });
+    let commands = commands::Commands::default();
+    let code = |text: String, path: &str| {
+        let code = CodeBlock::new(text)
+            .with_keymap(keymap.clone())
+            .with_commands(commands.clone())
+            .with_rainbow_brackets(settings.rainbow_brackets)
+            .with_vim(settings.vim_mode);
+        match &language_server {
+            Some(server) => code.with_language_server(server.clone(), SourceLocation::file(path)),
+            None => code,
+        }
+    };
 
     let file_contents = std::fs::read_to_string("src/widget/code.rs").unwrap();
     let file_contents2 = std::fs::read_to_string("src/main.rs").unwrap();
 
-    let child = masonry::widget::Portal::new(CodeBlock::new(file_contents))
+    let child = masonry::widget::Portal::new(code(file_contents, "src/widget/code.rs"))
         .constrain_vertical(true)
         .constrain_horizontal(true);
 
@@ -42,14 +73,47 @@ fn main() {
         // panels::Child::new(Point::ORIGIN, text("Testing panel asdf \n another line")),
         // panels::Child::new(Point::new(100., 100.), text("and another panel")),
         // panels::Child::new(
-        panels::Child::new(Point::new(50., 50.), child),
-        panels::Child::new(Point::new(100., 50.), CodeBlock::new(file_contents2)),
+        panels::Child::new(Point::new(50., 50.), child).with_path("src/widget/code.rs"),
+        panels::Child::new(Point::new(100., 50.), code(file_contents2, "src/main.rs"))
+            .with_path("src/main.rs")
+            .with_symbol("main"),
         //     Point::new(-100., 500.),
         //     Flex::column().with_child(Button::new("HI")),
         // ),
     ];
 
-    let main_widget = Canvas::new(Panel::new(children));
+    let tour = vec![
+        tour::Bookmark::new(tour::Viewport::new(Point::new(400., 300.), 1.0))
+            .with_note("# Overview\nThe `Canvas` holds every panel."),
+        tour::Bookmark::new(tour::Viewport::new(Point::new(200., 200.), 2.0))
+            .with_note("# Code blocks\n**CodeBlock** highlights with tree-sitter."),
+    ];
+
+    let view = canvas::CanvasView::default();
+    let edges = vec![edges::Edge::new(1, 0, edges::EdgeKind::References).with_label("reads")];
+    let panel = Panel::new(children)
+        .with_edges(edges)
+        .with_workspace_file("playground.workspace.json")
+        .with_commands(commands.clone())
+        .with_keymap(keymap.clone())
+        .with_settings(settings.clone())
+        .with_view(view.clone());
+    let panel = match &language_server {
+        Some(server) => panel.with_language_server(server.clone()),
+        None => panel,
+    };
+    let main_widget = Canvas::new(panel)
+        .with_view(view)
+        .with_keymap(keymap)
+        .with_scroll_mode(settings.scroll_mode)
+        .with_bookmarks(tour)
+        .with_index(index::WorkspaceIndex::new(
+            std::env::current_dir().unwrap_or_default(),
+        ));
+    let main_widget = match language_server {
+        Some(server) => main_widget.with_language_server(server),
+        None => main_widget,
+    };
 
     // {
     // let file_contents = std::fs::read_to_string("src/widget/canvas.rs").unwrap();
@@ -77,7 +141,7 @@ fn main() {
         masonry::event_loop_runner::EventLoop::with_user_event(),
         window_attributes,
         RootWidget::new(main_widget),
-        Driver,
+        Driver { commands },
     )
     .unwrap();
 }
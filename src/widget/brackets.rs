@@ -0,0 +1,106 @@
+// Pairs of brackets, found with tree-sitter so those in strings and comments don't count
+
+use tree_sitter::{Node, Tree};
+
+/// The opening bracket of each kind of pair, with its closing one
+const PAIRS: &[(&str, &str)] = &[("(", ")"), ("[", "]"), ("{", "}")];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BracketPair {
+    /// Byte of the opening bracket
+    pub open: usize,
+    /// Byte of the closing bracket
+    pub close: usize,
+    /// How many pairs are around this one
+    pub depth: usize,
+}
+
+impl BracketPair {
+    /// The pair with a bracket at `offset`, or right before it like when the caret is after
+    /// a closing bracket
+    pub fn at(pairs: &[BracketPair], offset: usize) -> Option<BracketPair> {
+        let on = |offset: usize| pairs.iter().find(|p| p.open == offset || p.close == offset);
+        on(offset)
+            .or_else(|| offset.checked_sub(1).and_then(on))
+            .copied()
+    }
+}
+
+/// The matched bracket pairs of the tree, in the order they open. Brackets are tokens of the
+/// tree, so there are none inside string literals or comments, and unmatched ones are left out.
+pub fn bracket_pairs(tree: &Tree) -> Vec<BracketPair> {
+    let mut pairs = Vec::new();
+    collect(tree.root_node(), &mut pairs);
+    pairs.sort_by_key(|pair| pair.open);
+
+    // Each pair is around those that open before it closes
+    let mut enclosing: Vec<usize> = Vec::new();
+    for pair in &mut pairs {
+        while enclosing.last().is_some_and(|&close| close < pair.open) {
+            enclosing.pop();
+        }
+        pair.depth = enclosing.len();
+        enclosing.push(pair.close);
+    }
+    pairs
+}
+
+/// Brackets pair up with siblings, like the braces of a block or the parentheses of arguments
+fn collect(node: Node, pairs: &mut Vec<BracketPair>) {
+    let mut cursor = node.walk();
+    let mut open: Vec<(&str, usize)> = Vec::new();
+    for child in node.children(&mut cursor) {
+        if child.is_named() {
+            collect(child, pairs);
+            continue;
+        }
+        let kind = child.kind();
+        if let Some((_, close)) = PAIRS.iter().find(|(open, _)| *open == kind) {
+            open.push((close, child.start_byte()));
+        } else if PAIRS.iter().any(|(_, close)| *close == kind) {
+            // A closing bracket that doesn't match drops the brackets opened since its own
+            if let Some(index) = open.iter().rposition(|(close, _)| *close == kind) {
+                let (_, start) = open[index];
+                open.truncate(index);
+                pairs.push(BracketPair {
+                    open: start,
+                    close: child.start_byte(),
+                    depth: 0,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Tree {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_rust::language()).unwrap();
+        parser.parse(source, None).unwrap()
+    }
+
+    #[test]
+    fn skips_brackets_in_strings_and_comments() {
+        let source = "fn f(a: [u8; 2]) { let s = \"([\"; /* { */ g(s) } // )";
+        let pairs = bracket_pairs(&parse(source));
+        let spans: Vec<(&str, usize)> = pairs
+            .iter()
+            .map(|pair| (&source[pair.open..=pair.close], pair.depth))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("(a: [u8; 2])", 0),
+                ("[u8; 2]", 1),
+                ("{ let s = \"([\"; /* { */ g(s) }", 0),
+                ("(s)", 1),
+            ]
+        );
+        // Right after a closing bracket is on its pair too
+        let arguments = source.find("(s)").unwrap();
+        assert_eq!(BracketPair::at(&pairs, arguments + 3), Some(pairs[3]));
+    }
+}
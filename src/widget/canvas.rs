@@ -1,16 +1,230 @@
 use accesskit::Role;
 use masonry::{
-    paint_scene_helpers::stroke,
+    kurbo::Line,
+    paint_scene_helpers::{fill_color, stroke},
     vello::{peniko::BlendMode, Scene},
     AccessCtx, AccessEvent, Affine, BoxConstraints, Color, CursorIcon, EventCtx, LayoutCtx,
     LifeCycle, LifeCycleCtx, PaintCtx, Point, PointerEvent, PointerState, Rect, Size, StatusChange,
     TextEvent, Vec2, Widget, WidgetId, WidgetPod,
 };
 use smallvec::{smallvec, SmallVec};
+use std::{cell::Cell, rc::Rc};
 use tracing::{trace_span, Span};
-use winit::dpi::{LogicalPosition, PhysicalPosition};
+use winit::{
+    dpi::{LogicalPosition, PhysicalPosition},
+    event::ElementState,
+    keyboard::{Key, ModifiersState, NamedKey},
+};
+
+use super::colors::palette;
+use super::commands::{Command, CommandRegistry};
+use super::history::{EditClock, History, Step};
+use super::index::WorkspaceIndex;
+use super::keymap::{KeyPress, Keymap};
+use super::palette::{Palette, PaletteResponse};
+use super::tour::{Bookmark, Presentation, Viewport, ViewportAnimation};
+use crate::lsp::LanguageServer;
+
+/// Zoom per wheel line, as a fraction of the current scale
+const ZOOM_SENSITIVITY: f64 = 0.1;
+/// Zoom per pixel of a precise (touchpad) scroll, used as an exponent
+const PIXEL_ZOOM_SENSITIVITY: f64 = 0.005;
+const MIN_SCALE: f64 = 0.05;
+const MAX_SCALE: f64 = 20.0;
+/// How far one wheel line pans the canvas, in screen pixels
+const LINE_PAN_DISTANCE: f64 = 40.0;
+/// How far one arrow key press pans the canvas, in screen pixels
+const KEY_PAN_DISTANCE: f64 = 60.0;
+
+const DEFAULT_GRID_SPACING: f64 = 40.0;
+/// Grid lines closer than this on screen are thinned out, in pixels
+const MIN_GRID_SCREEN_SPACING: f64 = 12.0;
+
+const NOTE_MAX_WIDTH: f64 = 600.0;
+const NOTE_MARGIN: f64 = 24.0;
+const NOTE_PADDING: f64 = 12.0;
+
+/// How far one wheel event may zoom in or out, as a factor
+const MAX_ZOOM_STEP: f64 = 2.0;
+
+/// Whole-number deltas up to this many lines come from a wheel rather than a touchpad
+const MAX_WHEEL_LINES: f64 = 10.0;
+
+/// What scrolling does on the canvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollMode {
+    /// Tell wheels from touchpads by each event's delta
+    Auto,
+    /// The wheel zooms, the auxiliary button pans
+    Mouse,
+    /// Two-finger scrolling pans, pinch or ctrl+scroll zooms
+    Touchpad,
+}
+
+impl ScrollMode {
+    /// The mode called `auto`, `mouse` or `touchpad`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(ScrollMode::Auto),
+            "mouse" => Some(ScrollMode::Mouse),
+            "touchpad" => Some(ScrollMode::Touchpad),
+            _ => None,
+        }
+    }
+}
+
+/// Which way the view moves when panned from the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScrollDelta {
+    Lines(Vec2),
+    Pixels(Vec2),
+}
+
+impl ScrollDelta {
+    /// Masonry flattens winit's line and pixel deltas into one value. Wheels move by a few
+    /// whole lines, touchpads by fractional or larger pixel amounts, unless `mode` says which
+    /// device is scrolling.
+    pub fn new(delta: Vec2, mode: ScrollMode) -> Self {
+        match mode {
+            ScrollMode::Mouse => ScrollDelta::Lines(delta),
+            ScrollMode::Touchpad => ScrollDelta::Pixels(delta),
+            ScrollMode::Auto => {
+                let whole_lines = |d: f64| d.fract() == 0.0 && d.abs() <= MAX_WHEEL_LINES;
+                if delta != Vec2::ZERO && whole_lines(delta.x) && whole_lines(delta.y) {
+                    ScrollDelta::Lines(delta)
+                } else {
+                    ScrollDelta::Pixels(delta)
+                }
+            }
+        }
+    }
+
+    /// How much this delta zooms by, limited to [`MAX_ZOOM_STEP`] either way
+    pub fn zoom_factor(&self) -> f64 {
+        let factor = match self {
+            ScrollDelta::Lines(lines) => (1.0 + ZOOM_SENSITIVITY).powf(lines.y),
+            ScrollDelta::Pixels(pixels) => (pixels.y * PIXEL_ZOOM_SENSITIVITY).exp(),
+        };
+        factor.clamp(1.0 / MAX_ZOOM_STEP, MAX_ZOOM_STEP)
+    }
+}
+
+/// The canvas transform and size, shared with the canvas content so it can place things in
+/// screen space
+#[derive(Clone)]
+pub struct CanvasView {
+    state: Rc<Cell<ViewState>>,
+    /// Orders the edits of the canvas and its content for undo
+    clock: EditClock,
+}
+
+#[derive(Clone, Copy)]
+struct ViewState {
+    transform: Affine,
+    size: Size,
+    /// Where the content wants its layout origin, in world space
+    content_origin: Point,
+    /// Spacing of the grid in world space, if it is shown
+    grid: Option<f64>,
+    /// Numbers of the viewport changes undo and redo would revert, see [`History`]
+    viewport_undo: Option<u64>,
+    viewport_redo: Option<u64>,
+}
 
-const ZOOM_SENSITIVITY: f64 = 0.05;
+impl Default for CanvasView {
+    fn default() -> Self {
+        Self {
+            state: Rc::new(Cell::new(ViewState {
+                transform: Affine::IDENTITY,
+                size: Size::ZERO,
+                content_origin: Point::ORIGIN,
+                grid: None,
+                viewport_undo: None,
+                viewport_redo: None,
+            })),
+            clock: EditClock::default(),
+        }
+    }
+}
+
+impl CanvasView {
+    pub fn transform(&self) -> Affine {
+        self.state.get().transform
+    }
+
+    pub fn size(&self) -> Size {
+        self.state.get().size
+    }
+
+    /// The part of the world that is on screen
+    pub fn visible_rect(&self) -> Rect {
+        self.transform()
+            .inverse()
+            .transform_rect_bbox(self.size().to_rect())
+    }
+
+    pub fn grid(&self) -> Option<f64> {
+        self.state.get().grid
+    }
+
+    pub fn content_origin(&self) -> Point {
+        self.state.get().content_origin
+    }
+
+    /// Content that extends into negative world coordinates sets its origin here during layout, so
+    /// the canvas places it there
+    pub fn set_content_origin(&self, origin: Point) {
+        let mut state = self.state.get();
+        state.content_origin = origin;
+        self.state.set(state);
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        Viewport::from_transform(self.transform(), self.size())
+    }
+
+    pub fn world_to_screen(&self, point: Point) -> Point {
+        self.transform() * point
+    }
+
+    pub fn screen_to_world(&self, point: Point) -> Point {
+        self.transform().inverse() * point
+    }
+
+    pub fn clock(&self) -> EditClock {
+        self.clock.clone()
+    }
+
+    /// Numbers of the viewport changes undo and redo would revert, so the content can tell whether
+    /// its own edits come first
+    pub fn viewport_edits(&self) -> (Option<u64>, Option<u64>) {
+        let state = self.state.get();
+        (state.viewport_undo, state.viewport_redo)
+    }
+
+    fn set_viewport_edits(&self, undo: Option<u64>, redo: Option<u64>) {
+        let mut state = self.state.get();
+        state.viewport_undo = undo;
+        state.viewport_redo = redo;
+        self.state.set(state);
+    }
+
+    fn set(&self, transform: Affine, size: Size, grid: Option<f64>) {
+        let mut state = self.state.get();
+        state.transform = transform;
+        state.size = size;
+        state.grid = grid;
+        self.state.set(state);
+    }
+}
 
 enum DraggingState {
     NotDragging,
@@ -23,6 +237,25 @@ pub struct Canvas<W: Widget> {
     child: WidgetPod<W>,
     transform: Affine,
     dragging_state: DraggingState,
+    scroll_mode: ScrollMode,
+    view: CanvasView,
+    size: Size,
+    bookmarks: Vec<Bookmark>,
+    presentation: Option<Presentation>,
+    animation: Option<ViewportAnimation>,
+    grid_spacing: f64,
+    show_grid: bool,
+    /// Viewports to go back to
+    history: History<Viewport>,
+    registry: CommandRegistry,
+    palette: Option<Palette>,
+    /// Resolves the keys, shared with the content which runs most of the commands
+    keymap: Keymap,
+    /// Answers the symbol search, which falls back to the index without one
+    language_server: Option<LanguageServer>,
+    /// The files and symbols of the workspace, built the first time a search needs it if it
+    /// wasn't given
+    index: Option<WorkspaceIndex>,
 }
 
 impl<W: Widget> Canvas<W> {
@@ -31,9 +264,341 @@ impl<W: Widget> Canvas<W> {
             child: WidgetPod::new(child),
             transform: Affine::IDENTITY,
             dragging_state: DraggingState::NotDragging,
+            scroll_mode: ScrollMode::Auto,
+            view: CanvasView::default(),
+            size: Size::ZERO,
+            bookmarks: Vec::new(),
+            presentation: None,
+            animation: None,
+            grid_spacing: DEFAULT_GRID_SPACING,
+            show_grid: false,
+            history: History::new(EditClock::default()),
+            registry: CommandRegistry::default(),
+            palette: None,
+            keymap: Keymap::default(),
+            language_server: None,
+            index: None,
+        }
+    }
+
+    pub fn with_scroll_mode(mut self, scroll_mode: ScrollMode) -> Self {
+        self.scroll_mode = scroll_mode;
+        self
+    }
+
+    /// Share the canvas transform with the content, see [`CanvasView`]
+    pub fn with_view(mut self, view: CanvasView) -> Self {
+        self.history = History::new(view.clock());
+        self.view = view;
+        self
+    }
+
+    pub fn with_bookmarks(mut self, bookmarks: Vec<Bookmark>) -> Self {
+        self.bookmarks = bookmarks;
+        self
+    }
+
+    /// Share `keymap` with the content, which reports its contexts and gets the commands
+    pub fn with_keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    pub fn with_language_server(mut self, server: LanguageServer) -> Self {
+        self.language_server = Some(server);
+        self
+    }
+
+    /// Search `index` in the file finder and the symbol search
+    pub fn with_index(mut self, index: WorkspaceIndex) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Show a grid with `spacing` in world space, which panels snap to
+    pub fn with_grid(mut self, spacing: f64) -> Self {
+        self.grid_spacing = spacing;
+        self.show_grid = true;
+        self
+    }
+
+    fn grid(&self) -> Option<f64> {
+        self.show_grid.then_some(self.grid_spacing)
+    }
+
+    fn viewport(&self) -> Viewport {
+        Viewport::from_transform(self.transform, self.size)
+    }
+
+    /// Save the current viewport as the last stop of the tour
+    fn add_bookmark(&mut self) {
+        self.bookmarks.push(Bookmark::new(self.viewport()));
+    }
+
+    /// Record the viewport for undo before changing it. Changes with the same `key` in quick
+    /// succession, like the steps of a drag, are undone together
+    fn record_viewport(&mut self, key: &'static str) {
+        let viewport = self.viewport();
+        self.history.record_coalesced(key, || viewport);
+    }
+
+    /// Undo or redo the latest viewport change, returning whether there was one
+    fn step_history(&mut self, ctx: &mut EventCtx, step: Step) -> bool {
+        let popped = match step {
+            Step::Undo => self.history.pop_undo(),
+            Step::Redo => self.history.pop_redo(),
+        };
+        let Some((number, target)) = popped else {
+            return false;
+        };
+
+        let current = match &self.animation {
+            Some(animation) => animation.target(),
+            None => self.viewport(),
+        };
+        match step {
+            Step::Undo => self.history.push_redo(number, current),
+            Step::Redo => self.history.push_undo(number, current),
+        }
+        self.animate_to(ctx, target);
+        ctx.request_layout();
+        ctx.request_paint();
+        true
+    }
+
+    fn animate_to(&mut self, ctx: &mut EventCtx, target: Viewport) {
+        self.animation = Some(ViewportAnimation::new(self.viewport(), target));
+        ctx.request_anim_frame();
+    }
+
+    fn start_presentation(&mut self, ctx: &mut EventCtx) {
+        let Some(first) = self.bookmarks.first() else {
+            return;
+        };
+        let target = first.viewport;
+        self.presentation = Some(Presentation::new());
+        self.animate_to(ctx, target);
+        ctx.request_layout();
+        ctx.request_paint();
+    }
+
+    fn stop_presentation(&mut self, ctx: &mut EventCtx) {
+        self.presentation = None;
+        ctx.request_paint();
+    }
+
+    /// Move the presentation `offset` stops forward (or backwards if negative)
+    fn step_presentation(&mut self, ctx: &mut EventCtx, offset: isize) {
+        let stops = self.bookmarks.len();
+        let Some(presentation) = &mut self.presentation else {
+            return;
+        };
+        let index = presentation.current.saturating_add_signed(offset);
+        if presentation.go_to(index, stops) {
+            let target = self.bookmarks[presentation.current].viewport;
+            self.animate_to(ctx, target);
+            ctx.request_layout();
+        }
+    }
+
+    /// Run the commands that act on the view, returning whether `command` was one of them
+    fn execute(&mut self, ctx: &mut EventCtx, command: &Command) -> bool {
+        match command {
+            Command::ToggleGrid => {
+                self.show_grid = !self.show_grid;
+                ctx.request_paint();
+            }
+            Command::AddBookmark => self.add_bookmark(),
+            Command::Present => {
+                if self.presentation.is_some() || self.bookmarks.is_empty() {
+                    return false;
+                }
+                self.start_presentation(ctx);
+            }
+            Command::Pan(direction) => self.pan_step(ctx, *direction),
+            Command::ZoomIn => return self.zoom_step(ctx, 1.0),
+            Command::ZoomOut => return self.zoom_step(ctx, -1.0),
+            Command::CommandPalette => {
+                let palette = Palette::commands(&self.registry, &self.keymap);
+                self.open_palette(ctx, palette);
+            }
+            Command::QuickOpen => {
+                let palette = Palette::files(self.index());
+                self.open_palette(ctx, palette);
+            }
+            Command::SymbolSearch => {
+                let palette = Palette::symbols(self.index(), self.language_server.clone());
+                self.open_palette(ctx, palette);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Look a pressed key up in the keymap. Our commands run right away, the others go to the
+    /// content along with the key.
+    fn press_key(&mut self, ctx: &mut EventCtx, key: &Key, mods: ModifiersState) {
+        let contexts = self.keymap.contexts(false);
+        match self.keymap.press(key, mods, &contexts) {
+            KeyPress::Unbound => {}
+            KeyPress::Pending | KeyPress::Cancelled => ctx.set_handled(),
+            KeyPress::Run(command) => {
+                if self.run(ctx, command) {
+                    ctx.set_handled();
+                }
+            }
+        }
+    }
+
+    /// Run `command` here, or hand it to the content along with the current event. Returns
+    /// whether it ran.
+    fn run(&mut self, ctx: &mut EventCtx, command: Command) -> bool {
+        if self.execute(ctx, &command) {
+            return true;
+        }
+        self.keymap.hand_over(command);
+        false
+    }
+
+    /// The content undoes its own edits first if they are more recent than ours
+    fn share_viewport_edits(&self) {
+        let redo = self.history.redo_number();
+        self.view
+            .set_viewport_edits(self.history.undo_number(), redo);
+    }
+
+    /// Run what the content left of the command handed to it, like undo when our viewport edit
+    /// is more recent. Returns whether there was a command.
+    fn run_left_over(&mut self, ctx: &mut EventCtx) -> bool {
+        let Some(command) = self.keymap.take() else {
+            return false;
+        };
+        match command {
+            Command::Undo => self.step_history(ctx, Step::Undo),
+            Command::Redo => self.step_history(ctx, Step::Redo),
+            command => {
+                tracing::debug!("Command {} did nothing", command.name());
+                false
+            }
+        };
+        true
+    }
+
+    fn index(&mut self) -> WorkspaceIndex {
+        self.index
+            .get_or_insert_with(|| WorkspaceIndex::new(std::env::current_dir().unwrap_or_default()))
+            .clone()
+    }
+
+    fn open_palette(&mut self, ctx: &mut EventCtx, palette: Palette) {
+        self.palette = Some(palette);
+        self.watch_palette(ctx);
+        ctx.request_layout();
+        ctx.request_paint();
+    }
+
+    /// Check every frame on items the palette is waiting for
+    fn watch_palette(&self, ctx: &mut EventCtx) {
+        if self.palette.as_ref().is_some_and(Palette::is_pending) {
+            ctx.request_anim_frame();
+        }
+    }
+
+    fn respond_to_palette(&mut self, ctx: &mut EventCtx, response: PaletteResponse) {
+        match response {
+            PaletteResponse::Handled => {}
+            PaletteResponse::Close => self.palette = None,
+            PaletteResponse::Run(command) => {
+                self.palette = None;
+                self.run(ctx, command);
+            }
+            PaletteResponse::Submit(line) => match self.registry.parse(&line) {
+                Ok(command) => {
+                    self.palette = None;
+                    self.run(ctx, command);
+                }
+                Err(err) => tracing::warn!("{err}"),
+            },
+        }
+        self.watch_palette(ctx);
+        ctx.request_layout();
+        ctx.request_paint();
+    }
+
+    /// Move the view a step in `direction`
+    fn pan_step(&mut self, ctx: &mut EventCtx, direction: PanDirection) {
+        self.animation = None;
+        self.record_viewport("keys");
+        // The content moves the other way
+        let pan = match direction {
+            PanDirection::Left => Vec2::new(1.0, 0.0),
+            PanDirection::Right => Vec2::new(-1.0, 0.0),
+            PanDirection::Up => Vec2::new(0.0, 1.0),
+            PanDirection::Down => Vec2::new(0.0, -1.0),
+        };
+        self.transform = self.transform.then_translate(pan * KEY_PAN_DISTANCE);
+        ctx.request_layout();
+        ctx.request_paint();
+    }
+
+    /// Zoom `steps` wheel lines around the center of the viewport
+    fn zoom_step(&mut self, ctx: &mut EventCtx, steps: f64) -> bool {
+        self.animation = None;
+        self.record_viewport("keys");
+        let center = ctx.to_window(Point::ZERO) + self.size.to_vec2() / 2.0;
+        let new_scale = self.transform.determinant().sqrt() * (1.0 + ZOOM_SENSITIVITY).powf(steps);
+        self.zoom_to(ctx, center, new_scale);
+        ctx.request_layout();
+        ctx.request_paint();
+        true
+    }
+
+    /// Paint the grid in screen space, doubling its spacing while the lines would be too dense
+    fn paint_grid(&self, scene: &mut Scene) {
+        let Some(mut spacing) = self.grid() else {
+            return;
+        };
+        let scale = self.transform.determinant().sqrt();
+        while spacing * scale < MIN_GRID_SCREEN_SPACING {
+            spacing *= 2.0;
+        }
+
+        let color = palette()["vscContext"];
+        let visible = self
+            .transform
+            .inverse()
+            .transform_rect_bbox(self.size.to_rect());
+
+        let mut x = (visible.x0 / spacing).floor() * spacing;
+        while x <= visible.x1 {
+            let screen_x = (self.transform * Point::new(x, 0.0)).x;
+            let line = Line::new((screen_x, 0.0), (screen_x, self.size.height));
+            stroke(scene, &line, color, 1.0);
+            x += spacing;
+        }
+
+        let mut y = (visible.y0 / spacing).floor() * spacing;
+        while y <= visible.y1 {
+            let screen_y = (self.transform * Point::new(0.0, y)).y;
+            let line = Line::new((0.0, screen_y), (self.size.width, screen_y));
+            stroke(scene, &line, color, 1.0);
+            y += spacing;
         }
     }
 
+    /// Screen space rect of the note panel of the current stop
+    fn note_rect(&self) -> Option<Rect> {
+        let note = self.presentation.as_ref()?.note.as_ref()?;
+        let note_size = note.size();
+        let width = note_size.width + 2.0 * NOTE_PADDING;
+        let height = note_size.height + 2.0 * NOTE_PADDING;
+        let origin = Point::new(
+            (self.size.width - width) / 2.0,
+            self.size.height - height - NOTE_MARGIN,
+        );
+        Some(Rect::from_origin_size(origin, Size::new(width, height)))
+    }
+
     /// Where this point would be if the canvas had no transforms (used for passing to children)
     fn point_to_mock_point(&self, ctx: &EventCtx, position: Point) -> Point {
         let position = position - ctx.to_window(Point::ZERO);
@@ -50,12 +615,65 @@ impl<W: Widget> Canvas<W> {
         let position = self.transform.inverse() * position;
         position
     }
+
+    /// Zoom to `new_scale` keeping the screen point `position` fixed
+    fn zoom_to(&mut self, ctx: &EventCtx, position: Point, new_scale: f64) {
+        let focus_point = self.point_to_local_space(ctx, position).to_vec2();
+
+        let new_scale = new_scale.clamp(MIN_SCALE, MAX_SCALE);
+        self.transform = Affine::IDENTITY
+            .then_scale(new_scale)
+            .then_translate(self.transform.translation());
+
+        let focus_point_end = self.point_to_local_space(ctx, position).to_vec2();
+
+        // adjust for the fact that we zoom in on the origin
+        let delta = focus_point_end - focus_point;
+        self.transform = self.transform.pre_translate(delta);
+    }
+}
+
+/// The same event, happening with a different pointer state, e.g. at another position
+pub(crate) fn with_pointer_state(event: &PointerEvent, state: PointerState) -> PointerEvent {
+    match event {
+        PointerEvent::PointerDown(button, _) => PointerEvent::PointerDown(*button, state),
+        PointerEvent::PointerUp(button, _) => PointerEvent::PointerUp(*button, state),
+        PointerEvent::PointerMove(_) => PointerEvent::PointerMove(state),
+        PointerEvent::PointerEnter(_) => PointerEvent::PointerEnter(state),
+        PointerEvent::PointerLeave(_) => PointerEvent::PointerLeave(state),
+        PointerEvent::MouseWheel(button, _) => PointerEvent::MouseWheel(*button, state),
+        // TODO: Don't clone here
+        PointerEvent::HoverFile(path_buf, _) => PointerEvent::HoverFile(path_buf.clone(), state),
+        PointerEvent::DropFile(path_buf, _) => PointerEvent::DropFile(path_buf.clone(), state),
+        PointerEvent::HoverFileCancel(_) => PointerEvent::HoverFileCancel(state),
+    }
 }
 
 impl<W: Widget> Widget for Canvas<W> {
     fn on_pointer_event(&mut self, ctx: &mut EventCtx, event: &PointerEvent) {
+        if let PointerEvent::PointerDown(_, _) = event {
+            // So we receive keyboard events for bookmarks and presenting
+            ctx.request_focus();
+        }
+
+        if let (Some(palette), PointerEvent::PointerDown(_, state)) = (&mut self.palette, event) {
+            let position = Point::new(state.position.x, state.position.y);
+            let local = (position - ctx.to_window(Point::ZERO)).to_point();
+            let response = palette.click(local, self.size);
+            self.respond_to_palette(ctx, response);
+            // The content runs what was picked with this click
+            if self.keymap.is_handing_over() {
+                self.share_viewport_edits();
+                self.child.on_pointer_event(ctx, event);
+                self.run_left_over(ctx);
+            }
+            ctx.set_handled();
+            return;
+        }
+
         match event {
-            PointerEvent::PointerDown(masonry::PointerButton::Auxiliary, state) => {
+            PointerEvent::PointerDown(button, state) if *button == self.keymap.mouse().pan => {
+                self.animation = None;
                 ctx.set_active(true);
                 ctx.set_handled();
                 ctx.set_cursor(&CursorIcon::Grabbing);
@@ -64,7 +682,7 @@ impl<W: Widget> Widget for Canvas<W> {
                     previous_screen_position: state.position,
                 };
             }
-            PointerEvent::PointerUp(masonry::PointerButton::Auxiliary, state) => {
+            PointerEvent::PointerUp(button, state) if *button == self.keymap.mouse().pan => {
                 self.dragging_state = DraggingState::NotDragging;
 
                 if ctx.is_active() {
@@ -84,6 +702,7 @@ impl<W: Widget> Widget for Canvas<W> {
                             state.position.y - previous_position.y,
                         );
 
+                        self.record_viewport("drag");
                         self.transform = self.transform.then_translate(delta);
                         self.dragging_state = DraggingState::Dragging {
                             previous_screen_position: state.position,
@@ -94,29 +713,6 @@ impl<W: Widget> Widget for Canvas<W> {
                     }
                 }
             }
-            PointerEvent::MouseWheel(delta, state) => {
-                let focus_point = self
-                    .point_to_local_space(ctx, Point::new(state.position.x, state.position.y))
-                    .to_vec2();
-
-                let initial_scale = self.transform.determinant().sqrt();
-                let new_scale = initial_scale + delta.y * ZOOM_SENSITIVITY;
-                let new_scale = new_scale.max(ZOOM_SENSITIVITY);
-
-                self.transform = Affine::IDENTITY
-                    .then_scale(new_scale)
-                    .then_translate(self.transform.translation());
-
-                let focus_point_end = self
-                    .point_to_local_space(ctx, Point::new(state.position.x, state.position.y))
-                    .to_vec2();
-
-                // adjust for the fact that we zoom in on the origin
-                let delta = focus_point_end - focus_point;
-                self.transform = self.transform.pre_translate(delta);
-
-                ctx.request_paint();
-            }
             _ => {}
         }
 
@@ -142,25 +738,117 @@ impl<W: Widget> Widget for Canvas<W> {
             focus: event.pointer_state().focus,
         };
 
-        let new_event = match event {
-            PointerEvent::PointerDown(button, _) => PointerEvent::PointerDown(*button, state),
-            PointerEvent::PointerUp(button, _) => PointerEvent::PointerUp(*button, state),
-            PointerEvent::PointerMove(_) => PointerEvent::PointerMove(state),
-            PointerEvent::PointerEnter(_) => PointerEvent::PointerEnter(state),
-            PointerEvent::PointerLeave(_) => PointerEvent::PointerLeave(state),
-            PointerEvent::MouseWheel(button, _) => PointerEvent::MouseWheel(*button, state),
-            // TODO: Don't clone here
-            PointerEvent::HoverFile(path_buf, _) => {
-                PointerEvent::HoverFile(path_buf.clone(), state)
+        let new_event = with_pointer_state(event, state);
+
+        // While presenting the canvas is read-only
+        if self.presentation.is_none() {
+            self.child.on_pointer_event(ctx, &new_event);
+        }
+
+        // Children get the first chance at the wheel, e.g. to scroll their content
+        if ctx.is_handled() {
+            return;
+        }
+
+        if let PointerEvent::MouseWheel(delta, state) = event {
+            self.animation = None;
+            self.record_viewport("wheel");
+
+            let position = Point::new(state.position.x, state.position.y);
+            let delta = ScrollDelta::new(Vec2::new(delta.x, delta.y), self.scroll_mode);
+            // Wheels zoom, touchpads pan
+            let zoom = state.mods.state().control_key() || matches!(delta, ScrollDelta::Lines(_));
+
+            if zoom {
+                // Pinch gestures arrive as ctrl+wheel on most platforms
+                let new_scale = self.transform.determinant().sqrt() * delta.zoom_factor();
+                self.zoom_to(ctx, position, new_scale);
+            } else {
+                let mut pan = match delta {
+                    ScrollDelta::Lines(lines) => lines * LINE_PAN_DISTANCE,
+                    ScrollDelta::Pixels(pixels) => pixels,
+                };
+                if state.mods.state().shift_key() && pan.x == 0.0 {
+                    pan = Vec2::new(pan.y, 0.0);
+                }
+                self.transform = self.transform.then_translate(pan);
             }
-            PointerEvent::DropFile(path_buf, _) => PointerEvent::DropFile(path_buf.clone(), state),
-            PointerEvent::HoverFileCancel(_) => todo!(),
-        };
-        self.child.on_pointer_event(ctx, &new_event);
+
+            ctx.request_layout();
+            ctx.request_paint();
+        }
     }
 
     fn on_text_event(&mut self, ctx: &mut EventCtx, event: &TextEvent) {
+        // The palette takes all typing while open
+        if self.palette.is_some() {
+            if let TextEvent::KeyboardKey(key, mods) = event {
+                if key.state == ElementState::Pressed {
+                    let contexts = self.keymap.contexts(true);
+                    match self.keymap.press(&key.logical_key, *mods, &contexts) {
+                        KeyPress::Run(command) => {
+                            self.run(ctx, command);
+                        }
+                        KeyPress::Pending | KeyPress::Cancelled => {}
+                        KeyPress::Unbound => {
+                            if let Some(palette) = &mut self.palette {
+                                let response = palette.key(&key.logical_key, *mods);
+                                self.respond_to_palette(ctx, response);
+                            }
+                        }
+                    }
+                }
+            }
+            // The content runs what was picked with this key
+            if self.keymap.is_handing_over() {
+                self.share_viewport_edits();
+                self.child.on_text_event(ctx, event);
+                self.run_left_over(ctx);
+            }
+            ctx.set_handled();
+            return;
+        }
+
+        if let TextEvent::KeyboardKey(key, mods) = event {
+            if key.state == ElementState::Pressed {
+                let presenting = self.presentation.is_some();
+                match &key.logical_key {
+                    Key::Named(NamedKey::Escape) if presenting => {
+                        self.stop_presentation(ctx);
+                        ctx.set_handled();
+                    }
+                    Key::Named(
+                        NamedKey::ArrowRight
+                        | NamedKey::ArrowDown
+                        | NamedKey::PageDown
+                        | NamedKey::Space,
+                    ) if presenting => {
+                        self.step_presentation(ctx, 1);
+                        ctx.set_handled();
+                    }
+                    Key::Named(NamedKey::ArrowLeft | NamedKey::ArrowUp | NamedKey::PageUp)
+                        if presenting =>
+                    {
+                        self.step_presentation(ctx, -1);
+                        ctx.set_handled();
+                    }
+                    key => self.press_key(ctx, key, *mods),
+                }
+            }
+        }
+
+        // While presenting the canvas is read-only
+        if self.presentation.is_some() || ctx.is_handled() {
+            self.keymap.take();
+            return;
+        }
+
+        self.share_viewport_edits();
         self.child.on_text_event(ctx, event);
+
+        if self.run_left_over(ctx) {
+            ctx.set_handled();
+        }
     }
 
     fn on_access_event(&mut self, ctx: &mut EventCtx, event: &AccessEvent) {
@@ -168,36 +856,122 @@ impl<W: Widget> Widget for Canvas<W> {
     }
 
     fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle) {
+        if let LifeCycle::RequestPanToChild(target) = event {
+            // `target` is in world space since the child is placed at its content origin
+            let visible = Rect::from_origin_size(Point::ORIGIN, self.size);
+            let target_on_screen = self.transform.transform_rect_bbox(*target);
+            if visible.intersect(target_on_screen) != target_on_screen {
+                let viewport = Viewport::new(target.center(), self.viewport().scale);
+                self.record_viewport("follow");
+                self.animation = Some(ViewportAnimation::new(self.viewport(), viewport));
+                ctx.request_anim_frame();
+            }
+        }
+
+        if let LifeCycle::AnimFrame(interval) = event {
+            if let Some(animation) = &mut self.animation {
+                let (viewport, done) = animation.advance(*interval);
+                self.transform = viewport.to_transform(self.size);
+                if done {
+                    self.animation = None;
+                } else {
+                    ctx.request_anim_frame();
+                }
+                // The content may need to grow to cover what comes into view
+                ctx.request_layout();
+                ctx.request_paint();
+            }
+
+            if let Some(palette) = &mut self.palette {
+                if palette.poll() {
+                    ctx.request_layout();
+                    ctx.request_paint();
+                }
+                if palette.is_pending() {
+                    ctx.request_anim_frame();
+                }
+            }
+        }
+
         self.child.lifecycle(ctx, event);
     }
 
     fn on_status_change(&mut self, _ctx: &mut LifeCycleCtx, _event: &StatusChange) {}
 
     fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints) -> Size {
-        self.child.layout(ctx, &BoxConstraints::UNBOUNDED);
-        ctx.place_child(&mut self.child, Point::ORIGIN);
-
-        if bc.is_width_bounded() && bc.is_height_bounded() {
+        self.size = if bc.is_width_bounded() && bc.is_height_bounded() {
             bc.max()
         } else {
             let size = Size::new(100.0, 100.0);
             bc.constrain(size)
+        };
+        self.view.set(self.transform, self.size, self.grid());
+
+        self.child.layout(ctx, &BoxConstraints::UNBOUNDED);
+        ctx.place_child(&mut self.child, self.view.content_origin());
+
+        if let Some(palette) = &mut self.palette {
+            let (font_ctx, layout_ctx) = ctx.text_contexts();
+            palette.rebuild(font_ctx, layout_ctx);
         }
+
+        if let Some(presentation) = &mut self.presentation {
+            if presentation.note_dirty {
+                let max_width = (self.size.width - 2.0 * (NOTE_MARGIN + NOTE_PADDING))
+                    .min(NOTE_MAX_WIDTH)
+                    .max(0.0);
+                let (font_ctx, layout_ctx) = ctx.text_contexts();
+                presentation.rebuild_note(
+                    &self.bookmarks[presentation.current],
+                    max_width,
+                    font_ctx,
+                    layout_ctx,
+                );
+            }
+        }
+
+        self.size
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, parent_scene: &mut Scene) {
+        self.view.set(self.transform, self.size, self.grid());
+
         let mut scene = Scene::new();
 
         let clip = Rect::from_origin_size(Point::ORIGIN, ctx.size());
 
-        stroke(parent_scene, &clip, Color::RED, 1.0);
+        // Editing chrome is hidden while presenting
+        if self.presentation.is_none() {
+            stroke(parent_scene, &clip, Color::RED, 1.0);
+        }
 
         // fill_color(parent_scene, &clip, Color::ORANGE);
 
         self.child.paint(ctx, &mut scene);
 
         parent_scene.push_layer(BlendMode::default(), 1.0, Affine::IDENTITY, &clip);
+        if self.presentation.is_none() {
+            self.paint_grid(parent_scene);
+        }
         parent_scene.append(&scene, Some(self.transform));
+
+        if let Some(note_rect) = self.note_rect() {
+            let colors = palette();
+            let panel = note_rect.to_rounded_rect(6.0);
+            fill_color(parent_scene, &panel, colors["vscPopupBack"]);
+            stroke(parent_scene, &panel, colors["vscSplitDark"], 1.0);
+            if let Some(note) = self.presentation.as_mut().and_then(|p| p.note.as_mut()) {
+                note.draw(
+                    parent_scene,
+                    note_rect.origin() + Vec2::new(NOTE_PADDING, NOTE_PADDING),
+                );
+            }
+        }
+
+        if let Some(palette) = &mut self.palette {
+            palette.paint(parent_scene, self.size);
+        }
+
         parent_scene.pop_layer();
     }
 
@@ -217,3 +991,34 @@ impl<W: Widget> Widget for Canvas<W> {
         trace_span!("Canvas")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_wheels_from_touchpads() {
+        let new = |x, y| ScrollDelta::new(Vec2::new(x, y), ScrollMode::Auto);
+        assert_eq!(new(0.0, -1.0), ScrollDelta::Lines(Vec2::new(0.0, -1.0)));
+        assert_eq!(new(0.0, 3.0), ScrollDelta::Lines(Vec2::new(0.0, 3.0)));
+        assert_eq!(new(0.0, 0.5), ScrollDelta::Pixels(Vec2::new(0.0, 0.5)));
+        assert_eq!(new(0.0, 24.0), ScrollDelta::Pixels(Vec2::new(0.0, 24.0)));
+        assert_eq!(new(1.0, 2.25), ScrollDelta::Pixels(Vec2::new(1.0, 2.25)));
+
+        // The setting overrides what the delta looks like
+        let touchpad = ScrollDelta::new(Vec2::new(0.0, 1.0), ScrollMode::Touchpad);
+        assert_eq!(touchpad, ScrollDelta::Pixels(Vec2::new(0.0, 1.0)));
+        let mouse = ScrollDelta::new(Vec2::new(0.0, 0.5), ScrollMode::Mouse);
+        assert_eq!(mouse, ScrollDelta::Lines(Vec2::new(0.0, 0.5)));
+    }
+
+    #[test]
+    fn zoom_steps_are_clamped() {
+        let lines = ScrollDelta::Lines(Vec2::new(0.0, 1.0));
+        assert!((lines.zoom_factor() - (1.0 + ZOOM_SENSITIVITY)).abs() < 1e-9);
+        let flung = ScrollDelta::Pixels(Vec2::new(0.0, 10_000.0));
+        assert_eq!(flung.zoom_factor(), MAX_ZOOM_STEP);
+        let flung = ScrollDelta::Lines(Vec2::new(0.0, -500.0));
+        assert_eq!(flung.zoom_factor(), 1.0 / MAX_ZOOM_STEP);
+    }
+}
@@ -3,19 +3,32 @@ use masonry::{
         style::{FontStack, StyleProperty},
         FontContext, LayoutContext,
     },
+    kurbo::BezPath,
     text::TextLayout,
     vello::{peniko::BlendMode, Scene},
-    AccessCtx, AccessEvent, Affine, BoxConstraints, Color, EventCtx, LayoutCtx, LifeCycle,
-    LifeCycleCtx, PaintCtx, Point, PointerEvent, Size, StatusChange, TextEvent, Widget, WidgetId,
+    paint_scene_helpers::{fill_color, stroke},
+    AccessCtx, AccessEvent, Action, Affine, BoxConstraints, Color, EventCtx, LayoutCtx, LifeCycle,
+    LifeCycleCtx, PaintCtx, Point, PointerButton, PointerEvent, Size, StatusChange, TextEvent,
+    Vec2, Widget, WidgetId,
 };
 use smallvec::SmallVec;
-use std::sync::Arc;
+use std::{ops::Range, path::PathBuf, sync::Arc, task::Poll};
 use tracing::trace;
-use tree_sitter::Parser;
+use tree_sitter::{Node, Parser, Tree};
 use tree_sitter_highlight::HighlightConfiguration;
 use accesskit::Role;
+use winit::{
+    event::ElementState,
+    keyboard::{Key, ModifiersState},
+};
 
-use super::colors::get_colors;
+use super::brackets::{self, BracketPair};
+use super::colors::{bracket_colors, get_colors, palette};
+use super::commands::{Command, Commands};
+use super::folding::{self, Fold, FoldedText};
+use super::keymap::Keymap;
+use super::vim::{Vim, VimResponse};
+use crate::lsp::{LanguageServer, Pending};
 
 // From label.rs
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -29,10 +42,69 @@ enum LineBreaking {
 }
 
 const LABEL_X_PADDING: f64 = 2.0;
+/// Width of the gutter left of the text, with the fold markers
+const GUTTER_WIDTH: f64 = 12.0;
+/// Half the height of a fold marker
+const FOLD_MARKER_SIZE: f64 = 3.5;
+
+/// Where the text of a code block starts in a file, to ask the language server about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub path: PathBuf,
+    pub position: lsp_types::Position,
+}
+
+impl SourceLocation {
+    /// The whole file at `path`
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            position: lsp_types::Position::new(0, 0),
+        }
+    }
+}
 
 pub struct CodeBlock {
+    /// The code, of which the layout shows what isn't folded
+    text: Arc<str>,
     text_layout: TextLayout<Arc<str>>,
     line_break_mode: LineBreaking,
+    /// The color of the bytes of the text, from highlighting
+    highlights: Vec<(Range<usize>, Color)>,
+    /// The regions that can fold, outer ones first
+    folds: Vec<Fold>,
+    /// What the layout shows
+    folded: FoldedText,
+    /// The matched brackets, in the order they open
+    brackets: Vec<BracketPair>,
+    /// Whether bracket pairs are colored by how deep they're nested
+    rainbow_brackets: bool,
+    /// The syntax tree of the text, for selecting whole nodes
+    tree: Tree,
+    /// The selected bytes, empty for just a caret
+    selection: Option<Range<usize>>,
+    /// The selections before each expansion, to shrink back to
+    expansions: Vec<Range<usize>>,
+    /// Hands us the commands for the focused code along with their events
+    keymap: Option<Keymap>,
+    /// Where ctrl+click submits go to definition, run by the panel in the same event
+    commands: Option<Commands>,
+    /// Asked for the ranges to expand the selection to where the syntax tree has errors
+    language_server: Option<(LanguageServer, SourceLocation)>,
+    /// The regions that fold, asked of the language server when the syntax tree has errors
+    server_folds: Option<Pending<Vec<lsp_types::FoldingRange>>>,
+    /// An expansion of the selection waiting for the language server
+    expanding: Option<Expansion>,
+    /// Vim keys for moving around and selecting, if turned on in the settings
+    vim: Option<Vim>,
+}
+
+/// Where the selection grows to once the language server answered
+struct Expansion {
+    from: Range<usize>,
+    /// Where the syntax tree would grow it, if the server can't say
+    fallback: Option<Range<usize>>,
+    ranges: Pending<Vec<lsp_types::Range>>,
 }
 
 static FONT: FontStack = FontStack::Source("Source Code Pro");
@@ -79,6 +151,23 @@ impl CodeBlock {
         //     .captures(&fn_query, tree.root_node(), text.as_bytes())
         //     .map(|(m, _)| m.captures[0].node);
 
+        let mut spans = Vec::new();
+        let mut color = Color::WHITE;
+        for h in highlights {
+            match h.unwrap() {
+                tree_sitter_highlight::HighlightEvent::Source { start, end } => {
+                    spans.push((start..end, color));
+                }
+                tree_sitter_highlight::HighlightEvent::HighlightStart(s) => {
+                    color = highlight_config[s.0].1;
+                }
+                tree_sitter_highlight::HighlightEvent::HighlightEnd => {
+                    color = Color::WHITE;
+                }
+            }
+        }
+        let folds = folding::folds(&tree, &text);
+
         let mut text_layout = TextLayout::new(text.clone(), FONT_SIZE);
         text_layout.set_font(FONT);
 
@@ -104,19 +193,8 @@ impl CodeBlock {
                 //     );
                 // }
 
-                let mut color = Color::WHITE;
-                for h in highlights {
-                    match h.unwrap() {
-                        tree_sitter_highlight::HighlightEvent::Source { start, end } => {
-                            x.push(&StyleProperty::Brush(color.into()), start..end);
-                        }
-                        tree_sitter_highlight::HighlightEvent::HighlightStart(s) => {
-                            color = highlight_config[s.0].1;
-                        }
-                        tree_sitter_highlight::HighlightEvent::HighlightEnd => {
-                            color = Color::WHITE;
-                        }
-                    }
+                for (range, color) in &spans {
+                    x.push(&StyleProperty::Brush((*color).into()), range.clone());
                 }
 
                 // for a in fns {
@@ -132,23 +210,494 @@ impl CodeBlock {
         );
 
         Self {
+            folded: FoldedText::new(&text, &folds),
+            text,
             text_layout,
             line_break_mode: LineBreaking::Clip,
+            highlights: spans,
+            folds,
+            brackets: brackets::bracket_pairs(&tree),
+            rainbow_brackets: false,
+            tree,
+            selection: None,
+            expansions: Vec::new(),
+            keymap: None,
+            commands: None,
+            language_server: None,
+            server_folds: None,
+            expanding: None,
+            vim: None,
+        }
+    }
+
+    /// Whether to color nested bracket pairs by depth, cycling through the theme's bracket colors
+    pub fn with_rainbow_brackets(mut self, rainbow: bool) -> Self {
+        self.rainbow_brackets = rainbow;
+        self.refold();
+        self
+    }
+
+    /// Move around and select with Vim keys while focused
+    pub fn with_vim(mut self, vim: bool) -> Self {
+        self.vim = vim.then(Vim::default);
+        self
+    }
+
+    /// Run the commands `keymap` hands over for the focused code, like expanding the selection
+    pub fn with_keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = Some(keymap);
+        self
+    }
+
+    /// Submit the commands we emit to `commands` instead of the app driver
+    pub fn with_commands(mut self, commands: Commands) -> Self {
+        self.commands = Some(commands);
+        self
+    }
+
+    /// Fall back to `server` to expand the selection and find the regions that fold, the text
+    /// being the file at `source`
+    pub fn with_language_server(mut self, server: LanguageServer, source: SourceLocation) -> Self {
+        // The syntax tree can't be trusted to find the regions around errors
+        if self.tree.root_node().has_error() {
+            self.server_folds = server.folding_ranges(&source.path);
         }
+        self.language_server = Some((server, source));
+        self
     }
 
     pub fn text(&self) -> &Arc<str> {
-        self.text_layout.text()
+        &self.text
+    }
+
+    /// The identifier at byte `offset`, if any
+    fn word_at(&self, offset: usize) -> Option<&str> {
+        let text = self.text().as_ref();
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let start = text[..offset]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| is_word(c))
+            .last()
+            .map_or(offset, |(i, _)| i);
+        let end = text[offset..]
+            .char_indices()
+            .find(|&(_, c)| !is_word(c))
+            .map_or(text.len(), |(i, _)| offset + i);
+        let word = &text[start..end];
+        (!word.is_empty() && !word.starts_with(|c: char| c.is_numeric())).then_some(word)
+    }
+
+    /// Submit `command` to `commands`, or as an action without them
+    fn submit(&self, ctx: &mut EventCtx, command: Command) {
+        match &self.commands {
+            Some(commands) => commands.submit(command),
+            None => ctx.submit_action(Action::Other(Box::new(command))),
+        }
+    }
+
+    /// Move or select with a Vim key, if Vim keys are on. Returns whether the key was used.
+    fn vim_key(&mut self, ctx: &mut EventCtx, key: &Key, mods: ModifiersState) -> bool {
+        let Some(vim) = &mut self.vim else {
+            return false;
+        };
+        // Clicks and expansions move the selection behind Vim's back
+        let selection = self.selection.clone().unwrap_or(0..0);
+        if selection != vim.selection(&self.text) {
+            vim.select(&self.text, selection.clone());
+        }
+        let command = match vim.key(&self.text, key, mods) {
+            VimResponse::Ignored => return false,
+            VimResponse::Handled => None,
+            VimResponse::Run(command) => Some(command),
+        };
+        let moved = vim.selection(&self.text);
+        let cursor = vim.cursor();
+        if moved != selection {
+            self.selection = Some(moved);
+            self.expansions.clear();
+            self.expanding = None;
+        }
+        // Like Vim, open the folds the cursor goes into
+        if !self.folded.is_shown(cursor) {
+            for fold in self.folds.iter_mut() {
+                if fold.folded && fold.hidden.contains(&cursor) {
+                    fold.folded = false;
+                }
+            }
+            self.refold();
+        }
+        if let Some(command) = command {
+            self.submit(ctx, command);
+        }
+        ctx.request_layout();
+        ctx.request_paint();
+        true
+    }
+
+    /// Run the command handed over with the current event if it's for us. Returns whether the
+    /// event came with a command, which means it wasn't meant for us otherwise.
+    fn run_handed_over(&mut self, ctx: &mut EventCtx) -> bool {
+        let Some(keymap) = self.keymap.clone() else {
+            return false;
+        };
+        match keymap.take() {
+            Some(Command::ExpandSelection) => self.expand_selection(),
+            Some(Command::ShrinkSelection) => self.shrink_selection(),
+            Some(Command::Fold) => self.fold_at_caret(),
+            Some(Command::Unfold) => self.unfold_at_caret(),
+            Some(command) => {
+                keymap.hand_over(command);
+                return true;
+            }
+            None => return false,
+        }
+        if self.waits_for_server() {
+            ctx.request_anim_frame();
+        }
+        ctx.set_handled();
+        ctx.request_layout();
+        ctx.request_paint();
+        true
+    }
+
+    /// Select the smallest syntax node around the selection, asking the language server instead
+    /// where the tree has errors, in which case the selection grows once it answered
+    fn expand_selection(&mut self) {
+        let selection = self.selection.clone().unwrap_or(0..0);
+        let node = self.enclosing_node(&selection);
+        let from_tree = node.map(|node| node.byte_range());
+        if node.map_or(true, |node| node.is_error()) {
+            if let Some(ranges) = self.ask_enclosing_ranges(&selection) {
+                self.expanding = Some(Expansion {
+                    from: selection,
+                    fallback: from_tree,
+                    ranges,
+                });
+                return;
+            }
+        }
+        if let Some(expanded) = from_tree {
+            self.expansions.push(selection);
+            self.selection = Some(expanded);
+        }
+    }
+
+    fn shrink_selection(&mut self) {
+        if let Some(previous) = self.expansions.pop() {
+            self.selection = Some(previous);
+        }
+    }
+
+    /// The smallest named node that contains `range` and is larger than it
+    fn enclosing_node(&self, range: &Range<usize>) -> Option<Node<'_>> {
+        let mut node = self
+            .tree
+            .root_node()
+            .descendant_for_byte_range(range.start, range.end)?;
+        while !node.is_named() || node.byte_range() == *range {
+            node = node.parent()?;
+        }
+        Some(node)
+    }
+
+    fn ask_enclosing_ranges(
+        &self,
+        range: &Range<usize>,
+    ) -> Option<Pending<Vec<lsp_types::Range>>> {
+        let (server, source) = self.language_server.as_ref()?;
+        let position = self.lsp_position(source, range.start);
+        server.selection_ranges(&source.path, position)
+    }
+
+    /// The smallest of the server's `ranges` that contains `range` and is larger than it
+    fn enclosing_range_from_server(
+        &self,
+        range: &Range<usize>,
+        ranges: Vec<lsp_types::Range>,
+    ) -> Option<Range<usize>> {
+        let (_, source) = self.language_server.as_ref()?;
+        ranges
+            .into_iter()
+            .filter_map(|r| Some(self.offset_of(source, r.start)?..self.offset_of(source, r.end)?))
+            .find(|r| r.start <= range.start && r.end >= range.end && r != range)
+    }
+
+    fn waits_for_server(&self) -> bool {
+        self.server_folds.is_some() || self.expanding.is_some()
+    }
+
+    /// Use what the language server answered, returning whether anything changed
+    fn receive_from_server(&mut self) -> bool {
+        let mut changed = false;
+        if let Some(request) = &mut self.server_folds {
+            if let Poll::Ready(ranges) = request.poll() {
+                self.server_folds = None;
+                if let Some(ranges) = ranges {
+                    self.folds = self.folds_from_server(ranges);
+                    self.refold();
+                    changed = true;
+                }
+            }
+        }
+        if let Some(expansion) = &mut self.expanding {
+            if let Poll::Ready(ranges) = expansion.ranges.poll() {
+                let expansion = self.expanding.take().expect("the expansion was just polled");
+                let from_server = ranges
+                    .and_then(|ranges| self.enclosing_range_from_server(&expansion.from, ranges));
+                if let Some(expanded) = from_server.or(expansion.fallback) {
+                    self.expansions.push(expansion.from);
+                    self.selection = Some(expanded);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Where byte `offset` is in the file, as the language server counts
+    fn lsp_position(&self, source: &SourceLocation, offset: usize) -> lsp_types::Position {
+        let before = &self.text()[..offset];
+        let line = before.matches('\n').count() as u32;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let mut character = before[line_start..].encode_utf16().count() as u32;
+        if line == 0 {
+            character += source.position.character;
+        }
+        lsp_types::Position::new(source.position.line + line, character)
+    }
+
+    /// The byte at `position` of the file, if it's in this block
+    fn offset_of(&self, source: &SourceLocation, position: lsp_types::Position) -> Option<usize> {
+        let text = self.text().as_ref();
+        let line = position.line.checked_sub(source.position.line)? as usize;
+        let character = match line {
+            0 => position.character.checked_sub(source.position.character)?,
+            _ => position.character,
+        } as usize;
+        let line_start = match line {
+            0 => 0,
+            _ => text.match_indices('\n').nth(line - 1)?.0 + 1,
+        };
+        let mut units = 0;
+        for (i, c) in text[line_start..].char_indices() {
+            if units >= character || c == '\n' {
+                return Some(line_start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(text.len())
+    }
+
+    /// The fold regions of the language server's `ranges`, for when the syntax tree has errors
+    fn folds_from_server(&self, ranges: Vec<lsp_types::FoldingRange>) -> Vec<Fold> {
+        let Some((_, source)) = self.language_server.as_ref() else {
+            return Vec::new();
+        };
+        let mut folds: Vec<Fold> = ranges
+            .into_iter()
+            .filter_map(|range| {
+                // Without characters the range is whole lines. The first stays shown, and so does
+                // the last, which has the closing bracket.
+                let start = lsp_types::Position::new(
+                    range.start_line,
+                    range.start_character.unwrap_or(u32::MAX),
+                );
+                let end =
+                    lsp_types::Position::new(range.end_line, range.end_character.unwrap_or(0));
+                let hidden = self.offset_of(source, start)?..self.offset_of(source, end)?;
+                let placeholder = match range.kind {
+                    Some(lsp_types::FoldingRangeKind::Comment)
+                    | Some(lsp_types::FoldingRangeKind::Imports) => folding::LINES_PLACEHOLDER,
+                    _ => folding::BRACED_PLACEHOLDER,
+                };
+                let multiline = self.text.get(hidden.clone())?.contains('\n');
+                multiline.then(|| Fold::new(hidden, placeholder))
+            })
+            .collect();
+        folding::sort(&mut folds);
+        folds
+    }
+
+    /// The line `offset` is on, without its newline
+    fn line_around(&self, offset: usize) -> Range<usize> {
+        let start = self.text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.text[offset..]
+            .find('\n')
+            .map_or(self.text.len(), |i| offset + i);
+        start..end
+    }
+
+    /// The lines a fold region covers, from the one it starts on
+    fn fold_lines(&self, fold: &Fold) -> Range<usize> {
+        self.line_around(fold.hidden.start).start..fold.hidden.end
+    }
+
+    fn caret(&self) -> usize {
+        self.selection.as_ref().map_or(0, |selection| selection.start)
+    }
+
+    /// Fold the innermost open region around the caret, which moves to where it folded
+    fn fold_at_caret(&mut self) {
+        let caret = self.caret();
+        let innermost = self
+            .folds
+            .iter()
+            .rposition(|fold| !fold.folded && self.fold_lines(fold).contains(&caret));
+        if let Some(index) = innermost {
+            let fold = &mut self.folds[index];
+            fold.folded = true;
+            if fold.hidden.contains(&caret) {
+                self.selection = Some(fold.hidden.start..fold.hidden.start);
+                self.expansions.clear();
+            }
+            self.refold();
+        }
+    }
+
+    /// Unfold the outermost folded region around the caret
+    fn unfold_at_caret(&mut self) {
+        let caret = self.caret();
+        let outermost = self
+            .folds
+            .iter()
+            .position(|fold| fold.folded && self.fold_lines(fold).contains(&caret));
+        if let Some(index) = outermost {
+            self.folds[index].folded = false;
+            self.refold();
+        }
+    }
+
+    /// Fold or unfold the outermost visible region starting on the line `offset` is on
+    fn toggle_fold_on_line(&mut self, offset: usize) -> bool {
+        let line = self.line_around(offset);
+        let index = self.folds.iter().position(|fold| {
+            // Comment and `use` regions start at the end of the line
+            (line.start..=line.end).contains(&fold.hidden.start)
+                && !self.folded.is_hidden(fold.hidden.start)
+        });
+        let Some(index) = index else {
+            return false;
+        };
+        self.folds[index].folded = !self.folds[index].folded;
+        self.refold();
+        true
+    }
+
+    /// Show the text with the folded regions replaced by their placeholders
+    fn refold(&mut self) {
+        self.folded = FoldedText::new(&self.text, &self.folds);
+        self.text_layout.set_text(self.folded.text.as_str().into());
+    }
+
+    /// The colors of the bytes shown, with placeholders dimmed
+    fn display_spans(&self) -> Vec<(Range<usize>, Color)> {
+        let mut spans: Vec<(Range<usize>, Color)> = self
+            .highlights
+            .iter()
+            .map(|(range, color)| {
+                let start = self.folded.to_display(range.start);
+                (start..self.folded.to_display(range.end), *color)
+            })
+            .filter(|(range, _)| !range.is_empty())
+            .collect();
+        if self.rainbow_brackets {
+            let cycle = bracket_colors();
+            for pair in &self.brackets {
+                let color = cycle[pair.depth % cycle.len()];
+                for bracket in [pair.open, pair.close] {
+                    if self.folded.is_shown(bracket) {
+                        let start = self.folded.to_display(bracket);
+                        spans.push((start..start + 1, color));
+                    }
+                }
+            }
+        }
+        let dimmed = palette()["vscGray"];
+        spans.extend(self.folded.placeholders().map(|range| (range, dimmed)));
+        spans
+    }
+
+    /// Where the text is drawn, right of the gutter
+    fn text_origin() -> Vec2 {
+        Vec2::new(GUTTER_WIDTH + LABEL_X_PADDING, 0.0)
+    }
+
+    /// A triangle pointing right if folded, or down if not, centered on the line at `y`
+    fn fold_marker(y: f64, folded: bool) -> BezPath {
+        let center = Point::new(GUTTER_WIDTH / 2.0, y);
+        let size = FOLD_MARKER_SIZE;
+        let points = if folded {
+            [(-size * 0.6, -size), (size * 0.8, 0.0), (-size * 0.6, size)]
+        } else {
+            [(-size, -size * 0.6), (size, -size * 0.6), (0.0, size * 0.8)]
+        };
+        let mut path = BezPath::new();
+        path.move_to(center + Vec2::from(points[0]));
+        path.line_to(center + Vec2::from(points[1]));
+        path.line_to(center + Vec2::from(points[2]));
+        path.close_path();
+        path
     }
 }
 
 impl Widget for CodeBlock {
     fn on_pointer_event(&mut self, ctx: &mut EventCtx, event: &PointerEvent) {
-        // todo!()
+        if self.run_handed_over(ctx) {
+            return;
+        }
+        if let PointerEvent::PointerDown(PointerButton::Primary, state) = event {
+            let local = Point::new(state.position.x, state.position.y)
+                - ctx.to_window(Point::ZERO)
+                - Self::text_origin();
+            let shown = self.text_layout.cursor_for_point(local.to_point()).insert_point;
+            let offset = self.folded.to_source(shown);
+            // Clicking a fold marker or a placeholder folds or unfolds
+            let in_gutter = local.x < -LABEL_X_PADDING;
+            let placeholder = self.folded.hidden_at(shown).filter(|_| !in_gutter);
+            let toggled = if let Some(hidden) = placeholder {
+                for fold in self.folds.iter_mut().filter(|fold| fold.hidden == hidden) {
+                    fold.folded = false;
+                }
+                self.refold();
+                true
+            } else {
+                in_gutter && self.toggle_fold_on_line(offset)
+            };
+            if toggled {
+                ctx.set_handled();
+                ctx.request_layout();
+                ctx.request_paint();
+                return;
+            }
+            // Ctrl+click on a name goes to its definition
+            if state.mods.state().control_key() {
+                if let Some(symbol) = self.word_at(offset) {
+                    let command = Command::GoToDefinition {
+                        symbol: symbol.to_string(),
+                    };
+                    self.submit(ctx, command);
+                    ctx.set_handled();
+                }
+                return;
+            }
+            // Expansions start over from the caret
+            self.selection = Some(offset..offset);
+            self.expansions.clear();
+            self.expanding = None;
+            ctx.request_paint();
+        }
     }
 
     fn on_text_event(&mut self, ctx: &mut EventCtx, event: &TextEvent) {
-        // todo!()
+        if self.run_handed_over(ctx) {
+            return;
+        }
+        if let TextEvent::KeyboardKey(key, mods) = event {
+            if key.state == ElementState::Pressed && self.vim_key(ctx, &key.logical_key, *mods) {
+                ctx.set_handled();
+            }
+        }
     }
 
     fn on_access_event(&mut self, ctx: &mut EventCtx, event: &AccessEvent) {
@@ -160,7 +709,19 @@ impl Widget for CodeBlock {
     }
 
     fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle) {
-        // todo!()
+        match event {
+            LifeCycle::WidgetAdded => {}
+            LifeCycle::AnimFrame(_) => {
+                if self.receive_from_server() {
+                    ctx.request_layout();
+                    ctx.request_paint();
+                }
+            }
+            _ => return,
+        }
+        if self.waits_for_server() {
+            ctx.request_anim_frame();
+        }
     }
 
     fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints) -> masonry::Size {
@@ -168,7 +729,7 @@ impl Widget for CodeBlock {
         let max_advance = if self.line_break_mode != LineBreaking::WordWrap {
             None
         } else if bc.max().width.is_finite() {
-            Some(bc.max().width as f32 - 2. * LABEL_X_PADDING as f32)
+            Some(bc.max().width as f32 - 2. * LABEL_X_PADDING as f32 - GUTTER_WIDTH as f32)
         } else if bc.min().width.is_sign_negative() {
             Some(0.0)
         } else {
@@ -176,14 +737,21 @@ impl Widget for CodeBlock {
         };
         self.text_layout.set_max_advance(max_advance);
         if self.text_layout.needs_rebuild() {
+            let spans = self.display_spans();
             let (font_ctx, layout_ctx) = ctx.text_contexts();
-            self.text_layout.rebuild(font_ctx, layout_ctx);
+            self.text_layout
+                .rebuild_with_attributes(font_ctx, layout_ctx, |mut builder| {
+                    for (range, color) in &spans {
+                        builder.push(&StyleProperty::Brush((*color).into()), range.clone());
+                    }
+                    builder
+                });
         }
         // We ignore trailing whitespace for a label
         let text_size = self.text_layout.size();
         let label_size = Size {
             height: text_size.height,
-            width: text_size.width + 2. * LABEL_X_PADDING,
+            width: text_size.width + 2. * LABEL_X_PADDING + GUTTER_WIDTH,
         };
         let size = bc.constrain(label_size);
         trace!(
@@ -203,8 +771,61 @@ impl Widget for CodeBlock {
             let clip_rect = ctx.size().to_rect();
             scene.push_layer(BlendMode::default(), 1., Affine::IDENTITY, &clip_rect);
         }
-        self.text_layout
-            .draw(scene, Point::new(LABEL_X_PADDING, 0.0));
+        let colors = palette();
+        let origin = Self::text_origin();
+        if let Some(selection) = &self.selection {
+            let start = self.folded.to_display(selection.start);
+            if selection.is_empty() {
+                // Outline the brackets of the pair at the caret
+                if let Some(pair) = BracketPair::at(&self.brackets, selection.start) {
+                    for bracket in [pair.open, pair.close] {
+                        if !self.folded.is_shown(bracket) {
+                            continue;
+                        }
+                        let shown = self.folded.to_display(bracket);
+                        for rect in self.text_layout.rects_for_range(shown..shown + 1) {
+                            stroke(scene, &(rect + origin), colors["vscBracketMatch"], 1.0);
+                        }
+                    }
+                }
+                // Vim's cursor covers the char it's on
+                let on_char = self.text[selection.start..].chars().next().filter(|&c| c != '\n');
+                match on_char {
+                    Some(c) if self.vim.is_some() => {
+                        for rect in self.text_layout.rects_for_range(start..start + c.len_utf8()) {
+                            stroke(scene, &(rect + origin), colors["vscCursorLight"], 1.0);
+                        }
+                    }
+                    _ => {
+                        let caret = self.text_layout.cursor_line_for_text_position(start);
+                        stroke(scene, &(caret + origin), colors["vscCursorLight"], 1.0);
+                    }
+                }
+            } else {
+                let shown = start..self.folded.to_display(selection.end);
+                for rect in self.text_layout.rects_for_range(shown) {
+                    fill_color(scene, &(rect + origin), colors["vscSelection"]);
+                }
+            }
+        }
+        self.text_layout.draw(scene, origin.to_point());
+
+        // One marker per line, for the outermost region starting on it
+        let mut marked = None;
+        for fold in &self.folds {
+            if self.folded.is_hidden(fold.hidden.start) {
+                continue;
+            }
+            let line = self
+                .text_layout
+                .cursor_line_for_text_position(self.folded.to_display(fold.hidden.start));
+            let y = (line.p0.y + line.p1.y) / 2.0;
+            if marked.is_some_and(|marked_y: f64| (marked_y - y).abs() < 1.0) {
+                continue;
+            }
+            marked = Some(y);
+            fill_color(scene, &Self::fold_marker(y, fold.folded), colors["vscGray"]);
+        }
 
         if self.line_break_mode == LineBreaking::Clip {
             scene.pop_layer();
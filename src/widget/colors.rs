@@ -4,7 +4,8 @@ use std::collections::HashMap;
 
 use masonry::Color;
 
-pub fn get_colors() -> Vec<(&'static str, Color)> {
+/// The named colors of the theme, e.g. `vscFront`
+pub fn palette() -> HashMap<&'static str, Color> {
     let mut colormap = HashMap::new();
     colormap.insert("vscFront", "#D4D4D4");
     colormap.insert("vscBack", "#1F1F1F");
@@ -56,6 +57,11 @@ pub fn get_colors() -> Vec<(&'static str, Color)> {
 
     colormap.insert("vscFoldBackground", "#202d39");
 
+    colormap.insert("vscBracket1", "#FFD700");
+    colormap.insert("vscBracket2", "#DA70D6");
+    colormap.insert("vscBracket3", "#179FFF");
+    colormap.insert("vscBracketMatch", "#888888");
+
     colormap.insert("vscGray", "#808080");
     colormap.insert("vscViolet", "#646695");
     colormap.insert("vscBlue", "#569CD6");
@@ -76,10 +82,24 @@ pub fn get_colors() -> Vec<(&'static str, Color)> {
     colormap.insert("vscPink", "#C586C0");
     colormap.insert("vscDimHighlight", "#51504F");
 
-    let colormap: HashMap<_, _> = colormap
+    colormap
         .iter()
         .map(|(&i, v)| (i, Color::parse(v).unwrap()))
-        .collect();
+        .collect()
+}
+
+/// The colors nested bracket pairs cycle through, outermost first
+pub fn bracket_colors() -> Vec<Color> {
+    let colormap = palette();
+    vec![
+        colormap["vscBracket1"],
+        colormap["vscBracket2"],
+        colormap["vscBracket3"],
+    ]
+}
+
+pub fn get_colors() -> Vec<(&'static str, Color)> {
+    let colormap = palette();
 
     vec![
         ("error", colormap["vscRed"]),
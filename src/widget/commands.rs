@@ -0,0 +1,490 @@
+// Named commands with arguments. Widgets emit them as actions, keybindings and the command
+// palette look them up by name, and the panel runs them against the workspace.
+
+use std::{cell::RefCell, collections::VecDeque, fmt, path::PathBuf, rc::Rc};
+
+use super::canvas::PanDirection;
+use super::graph_layout::GraphLayout;
+use super::panels::{Alignment, Axis};
+
+/// Something to do to the workspace. Widgets emit these with
+/// `ctx.submit_action(Action::Other(Box::new(command)))`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Open a file as a code panel, or a directory as a file tree
+    OpenFile(PathBuf),
+    /// Show the panel defining `symbol`, opening the file it's in if needed
+    GoToDefinition {
+        symbol: String,
+    },
+    /// Open just the item `symbol` of a file, the one around `line` if there are several
+    OpenSymbol {
+        path: PathBuf,
+        symbol: String,
+        line: Option<usize>,
+    },
+    /// Close the panel at this index, or the focused one
+    ClosePanel(Option<usize>),
+    FocusPanel(usize),
+    SelectAll,
+    ClearSelection,
+    DeleteSelection,
+    Align(Alignment),
+    Distribute(Axis),
+    /// Give the selected panels the next background color
+    Recolor,
+    Arrange(GraphLayout),
+    /// Switch to the next way of keeping new panels from overlapping
+    CycleOverlapAvoidance,
+    /// Move the focused panel one step up the stack
+    Raise,
+    /// Move the focused panel one step down the stack
+    Lower,
+    BringToFront,
+    SendToBack,
+    /// Dissolve the active group
+    Ungroup,
+    SaveWorkspace,
+    ToggleGrid,
+    /// Add the current viewport to the tour
+    AddBookmark,
+    /// Present the tour
+    Present,
+    /// Move the view a step
+    Pan(PanDirection),
+    ZoomIn,
+    ZoomOut,
+    /// Pick a command to run by name
+    CommandPalette,
+    /// Pick a file of the workspace to open by name
+    QuickOpen,
+    /// Pick a symbol of the workspace to open by name
+    SymbolSearch,
+    /// Grow the selection of the focused code to the syntax node around it
+    ExpandSelection,
+    /// Go back to the selection before the last expansion
+    ShrinkSelection,
+    /// Fold the innermost open region of the focused code around the caret
+    Fold,
+    /// Unfold the outermost folded region of the focused code on the caret's line
+    Unfold,
+    Undo,
+    Redo,
+}
+
+impl Command {
+    /// The name the command is registered under
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::OpenFile(_) => "open",
+            Command::GoToDefinition { .. } => "definition",
+            Command::OpenSymbol { .. } => "open-symbol",
+            Command::ClosePanel(_) => "close",
+            Command::FocusPanel(_) => "focus",
+            Command::SelectAll => "select-all",
+            Command::ClearSelection => "clear-selection",
+            Command::DeleteSelection => "delete",
+            Command::Align(_) => "align",
+            Command::Distribute(_) => "distribute",
+            Command::Recolor => "recolor",
+            Command::Arrange(_) => "arrange",
+            Command::CycleOverlapAvoidance => "overlap",
+            Command::Raise => "raise",
+            Command::Lower => "lower",
+            Command::BringToFront => "front",
+            Command::SendToBack => "back",
+            Command::Ungroup => "ungroup",
+            Command::SaveWorkspace => "save",
+            Command::ToggleGrid => "grid",
+            Command::AddBookmark => "bookmark",
+            Command::Present => "present",
+            Command::Pan(_) => "pan",
+            Command::ZoomIn => "zoom-in",
+            Command::ZoomOut => "zoom-out",
+            Command::CommandPalette => "palette",
+            Command::QuickOpen => "quick-open",
+            Command::SymbolSearch => "symbols",
+            Command::ExpandSelection => "expand-selection",
+            Command::ShrinkSelection => "shrink-selection",
+            Command::Fold => "fold",
+            Command::Unfold => "unfold",
+            Command::Undo => "undo",
+            Command::Redo => "redo",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Unknown(String),
+    /// The arguments didn't fit the usage of the command
+    BadArguments {
+        name: &'static str,
+        usage: &'static str,
+    },
+    /// A `"` opened an argument that never ended
+    UnclosedQuote,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Unknown(name) => write!(f, "no command named `{name}`"),
+            CommandError::BadArguments { name, usage } => write!(f, "usage: {name} {usage}"),
+            CommandError::UnclosedQuote => write!(f, "unclosed `\"`"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// A command as found by name, with how to build it from its arguments
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The arguments, as shown in help
+    pub usage: &'static str,
+    /// How to run the command other than through the keymap, as shown in the palette
+    pub keys: Option<&'static str>,
+    parse: fn(&[&str]) -> Option<Command>,
+}
+
+impl CommandSpec {
+    pub fn new(
+        name: &'static str,
+        description: &'static str,
+        usage: &'static str,
+        parse: fn(&[&str]) -> Option<Command>,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            usage,
+            keys: None,
+            parse,
+        }
+    }
+
+    pub fn with_keys(mut self, keys: &'static str) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Whether the command needs arguments to run
+    pub fn takes_arguments(&self) -> bool {
+        (self.parse)(&[]).is_none()
+    }
+
+    pub fn build(&self, args: &[&str]) -> Result<Command, CommandError> {
+        (self.parse)(args).ok_or(CommandError::BadArguments {
+            name: self.name,
+            usage: self.usage,
+        })
+    }
+}
+
+pub struct CommandRegistry {
+    specs: Vec<CommandSpec>,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        let mut registry = Self { specs: Vec::new() };
+        let specs = [
+            CommandSpec::new(
+                "open",
+                "Open a file or directory as a panel",
+                "<path>",
+                |args| match args {
+                    [path] => Some(Command::OpenFile(path.into())),
+                    _ => None,
+                },
+            ),
+            CommandSpec::new(
+                "definition",
+                "Go to the definition of a symbol",
+                "<symbol>",
+                |args| match args {
+                    [symbol] => Some(Command::GoToDefinition {
+                        symbol: symbol.to_string(),
+                    }),
+                    _ => None,
+                },
+            )
+            .with_keys("Ctrl+Click"),
+            CommandSpec::new(
+                "open-symbol",
+                "Open just one item of a file",
+                "<path> <symbol>",
+                |args| match args {
+                    [path, symbol] => Some(Command::OpenSymbol {
+                        path: path.into(),
+                        symbol: symbol.to_string(),
+                        line: None,
+                    }),
+                    _ => None,
+                },
+            ),
+            CommandSpec::new(
+                "close",
+                "Close a panel, the focused one by default",
+                "[index]",
+                |args| match args {
+                    [] => Some(Command::ClosePanel(None)),
+                    [index] => Some(Command::ClosePanel(Some(index.parse().ok()?))),
+                    _ => None,
+                },
+            ),
+            CommandSpec::new("focus", "Focus a panel", "<index>", |args| match args {
+                [index] => Some(Command::FocusPanel(index.parse().ok()?)),
+                _ => None,
+            }),
+            CommandSpec::new("select-all", "Select every panel", "", |args| {
+                args.is_empty().then_some(Command::SelectAll)
+            }),
+            CommandSpec::new("clear-selection", "Deselect every panel", "", |args| {
+                args.is_empty().then_some(Command::ClearSelection)
+            }),
+            CommandSpec::new("delete", "Close the selected panels", "", |args| {
+                args.is_empty().then_some(Command::DeleteSelection)
+            }),
+            CommandSpec::new(
+                "align",
+                "Line up the selected panels",
+                "left|right|top|bottom|center-h|center-v",
+                |args| match args {
+                    ["left"] => Some(Command::Align(Alignment::Left)),
+                    ["right"] => Some(Command::Align(Alignment::Right)),
+                    ["top"] => Some(Command::Align(Alignment::Top)),
+                    ["bottom"] => Some(Command::Align(Alignment::Bottom)),
+                    ["center-h"] => Some(Command::Align(Alignment::CenterHorizontal)),
+                    ["center-v"] => Some(Command::Align(Alignment::CenterVertical)),
+                    _ => None,
+                },
+            ),
+            CommandSpec::new(
+                "distribute",
+                "Space the selected panels evenly",
+                "horizontal|vertical",
+                |args| match args {
+                    ["horizontal"] => Some(Command::Distribute(Axis::Horizontal)),
+                    ["vertical"] => Some(Command::Distribute(Axis::Vertical)),
+                    _ => None,
+                },
+            ),
+            CommandSpec::new("recolor", "Recolor the selected panels", "", |args| {
+                args.is_empty().then_some(Command::Recolor)
+            }),
+            CommandSpec::new(
+                "arrange",
+                "Arrange the selected panels, or all of them",
+                "force|layered|grid",
+                |args| match args {
+                    ["force"] => Some(Command::Arrange(GraphLayout::ForceDirected)),
+                    ["layered"] => Some(Command::Arrange(GraphLayout::Layered)),
+                    ["grid"] => Some(Command::Arrange(GraphLayout::Grid)),
+                    _ => None,
+                },
+            ),
+            CommandSpec::new(
+                "overlap",
+                "Change how new panels avoid overlapping",
+                "",
+                |args| args.is_empty().then_some(Command::CycleOverlapAvoidance),
+            ),
+            CommandSpec::new("raise", "Raise the focused panel", "", |args| {
+                args.is_empty().then_some(Command::Raise)
+            }),
+            CommandSpec::new("lower", "Lower the focused panel", "", |args| {
+                args.is_empty().then_some(Command::Lower)
+            }),
+            CommandSpec::new(
+                "front",
+                "Bring the focused panel to the front",
+                "",
+                |args| args.is_empty().then_some(Command::BringToFront),
+            ),
+            CommandSpec::new("back", "Send the focused panel to the back", "", |args| {
+                args.is_empty().then_some(Command::SendToBack)
+            }),
+            CommandSpec::new("ungroup", "Dissolve the active group", "", |args| {
+                args.is_empty().then_some(Command::Ungroup)
+            }),
+            CommandSpec::new("save", "Save the workspace file", "", |args| {
+                args.is_empty().then_some(Command::SaveWorkspace)
+            }),
+            CommandSpec::new("grid", "Show or hide the grid", "", |args| {
+                args.is_empty().then_some(Command::ToggleGrid)
+            }),
+            CommandSpec::new("bookmark", "Add the view to the tour", "", |args| {
+                args.is_empty().then_some(Command::AddBookmark)
+            }),
+            CommandSpec::new("present", "Present the tour", "", |args| {
+                args.is_empty().then_some(Command::Present)
+            }),
+            CommandSpec::new(
+                "pan",
+                "Move the view",
+                "left|right|up|down",
+                |args| match args {
+                    ["left"] => Some(Command::Pan(PanDirection::Left)),
+                    ["right"] => Some(Command::Pan(PanDirection::Right)),
+                    ["up"] => Some(Command::Pan(PanDirection::Up)),
+                    ["down"] => Some(Command::Pan(PanDirection::Down)),
+                    _ => None,
+                },
+            ),
+            CommandSpec::new("zoom-in", "Zoom in", "", |args| {
+                args.is_empty().then_some(Command::ZoomIn)
+            }),
+            CommandSpec::new("zoom-out", "Zoom out", "", |args| {
+                args.is_empty().then_some(Command::ZoomOut)
+            }),
+            CommandSpec::new("palette", "Run a command by name", "", |args| {
+                args.is_empty().then_some(Command::CommandPalette)
+            }),
+            CommandSpec::new("quick-open", "Open a file of the workspace", "", |args| {
+                args.is_empty().then_some(Command::QuickOpen)
+            }),
+            CommandSpec::new("symbols", "Open a symbol of the workspace", "", |args| {
+                args.is_empty().then_some(Command::SymbolSearch)
+            }),
+            CommandSpec::new(
+                "expand-selection",
+                "Select the syntax node around the selection",
+                "",
+                |args| args.is_empty().then_some(Command::ExpandSelection),
+            ),
+            CommandSpec::new(
+                "shrink-selection",
+                "Undo the last selection expansion",
+                "",
+                |args| args.is_empty().then_some(Command::ShrinkSelection),
+            ),
+            CommandSpec::new("fold", "Fold the region around the caret", "", |args| {
+                args.is_empty().then_some(Command::Fold)
+            }),
+            CommandSpec::new("unfold", "Unfold the region at the caret", "", |args| {
+                args.is_empty().then_some(Command::Unfold)
+            }),
+            CommandSpec::new("undo", "Undo the last edit", "", |args| {
+                args.is_empty().then_some(Command::Undo)
+            }),
+            CommandSpec::new("redo", "Redo the last undone edit", "", |args| {
+                args.is_empty().then_some(Command::Redo)
+            }),
+        ];
+        for spec in specs {
+            registry.register(spec);
+        }
+        registry
+    }
+}
+
+impl CommandRegistry {
+    /// Add a command, replacing any registered under the same name
+    pub fn register(&mut self, spec: CommandSpec) {
+        self.specs.retain(|s| s.name != spec.name);
+        self.specs.push(spec);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CommandSpec> {
+        self.specs.iter().find(|spec| spec.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CommandSpec> {
+        self.specs.iter()
+    }
+
+    /// Build a command from a name followed by its arguments, separated by whitespace. Arguments
+    /// with whitespace, like `open "my notes/todo.md"`, go in double quotes.
+    pub fn parse(&self, line: &str) -> Result<Command, CommandError> {
+        let words = split_words(line)?;
+        let mut words = words.iter().map(String::as_str);
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        self.get(name)
+            .ok_or_else(|| CommandError::Unknown(name.to_string()))?
+            .build(&args)
+    }
+}
+
+/// The whitespace separated words of `line`, keeping whitespace between double quotes
+fn split_words(line: &str) -> Result<Vec<String>, CommandError> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                // `""` is an empty argument
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return Err(CommandError::UnclosedQuote);
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Commands waiting to run, shared between whatever emits them and the panel that runs them
+#[derive(Clone, Default)]
+pub struct Commands(Rc<RefCell<VecDeque<Command>>>);
+
+impl Commands {
+    pub fn submit(&self, command: Command) {
+        self.0.borrow_mut().push_back(command);
+    }
+
+    pub fn take(&self) -> VecDeque<Command> {
+        std::mem::take(&mut self.0.borrow_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_arguments() {
+        let registry = CommandRegistry::default();
+        assert_eq!(
+            registry.parse("  arrange   grid "),
+            Ok(Command::Arrange(GraphLayout::Grid))
+        );
+        assert_eq!(
+            registry.parse("arrange"),
+            Err(CommandError::BadArguments {
+                name: "arrange",
+                usage: registry.get("arrange").unwrap().usage,
+            })
+        );
+        assert_eq!(
+            registry.parse("rearrange grid"),
+            Err(CommandError::Unknown("rearrange".to_string()))
+        );
+    }
+
+    #[test]
+    fn quotes_keep_whitespace() {
+        let registry = CommandRegistry::default();
+        assert_eq!(
+            registry.parse(r#"open "my notes/to do.md""#),
+            Ok(Command::OpenFile("my notes/to do.md".into()))
+        );
+        assert_eq!(
+            registry.parse(r#"open my" "notes.md"#),
+            Ok(Command::OpenFile("my notes.md".into()))
+        );
+        assert!(registry.parse("open my notes.md").is_err());
+        assert_eq!(
+            registry.parse(r#"open "my notes"#),
+            Err(CommandError::UnclosedQuote)
+        );
+    }
+}
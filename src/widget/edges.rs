@@ -0,0 +1,195 @@
+// Connections between panels, drawn from border to border
+
+use std::sync::Arc;
+
+use masonry::{
+    kurbo::{BezPath, Line, Stroke},
+    paint_scene_helpers::fill_color,
+    parley::{FontContext, LayoutContext},
+    text::{TextBrush, TextLayout},
+    vello::Scene,
+    Affine, Color, Point, Rect, Vec2,
+};
+
+use super::colors::palette;
+
+const EDGE_WIDTH: f64 = 2.0;
+const ARROW_LENGTH: f64 = 12.0;
+const ARROW_WIDTH: f64 = 9.0;
+/// How far from a panel's frame edges stop, clear of its border
+const BORDER_GAP: f64 = 18.0;
+const LABEL_FONT_SIZE: f32 = 11.0;
+const LABEL_PADDING: f64 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    Calls,
+    Defines,
+    References,
+    /// Drawn by the user
+    Manual,
+}
+
+impl EdgeKind {
+    pub fn color(&self) -> Color {
+        let colors = palette();
+        match self {
+            EdgeKind::Calls => colors["vscAccentBlue"],
+            EdgeKind::Defines => colors["vscBlueGreen"],
+            EdgeKind::References => colors["vscYellowOrange"],
+            EdgeKind::Manual => colors["vscFront"],
+        }
+    }
+
+    /// Dash pattern of the line, empty for a solid line
+    fn dashes(&self) -> &'static [f64] {
+        match self {
+            EdgeKind::References => &[6.0, 4.0],
+            _ => &[],
+        }
+    }
+}
+
+/// A directed connection from one panel to another, as indices into the panel's children
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+    label: Option<Arc<str>>,
+    label_layout: Option<TextLayout<Arc<str>>>,
+}
+
+impl Edge {
+    pub fn new(from: usize, to: usize, kind: EdgeKind) -> Self {
+        Self {
+            from,
+            to,
+            kind,
+            label: None,
+            label_layout: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<Arc<str>>) -> Self {
+        self.set_label(Some(label.into()));
+        self
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub fn set_label(&mut self, label: Option<Arc<str>>) {
+        self.label = label;
+        self.label_layout = None;
+    }
+
+    /// Whether this edge runs between `a` and `b`, in either direction
+    pub fn connects(&self, a: usize, b: usize) -> bool {
+        (self.from == a && self.to == b) || (self.from == b && self.to == a)
+    }
+
+    pub fn touches(&self, index: usize) -> bool {
+        self.from == index || self.to == index
+    }
+
+    pub(crate) fn rebuild_label(
+        &mut self,
+        font_ctx: &mut FontContext,
+        layout_ctx: &mut LayoutContext<TextBrush>,
+    ) {
+        let Some(label) = &self.label else {
+            return;
+        };
+        let layout = self.label_layout.get_or_insert_with(|| {
+            let mut layout = TextLayout::new(label.clone(), LABEL_FONT_SIZE);
+            layout.set_brush(palette()["vscPopupFront"]);
+            layout
+        });
+        if layout.needs_rebuild() {
+            layout.rebuild(font_ctx, layout_ctx);
+        }
+    }
+
+    /// Paint the edge between the frames of its ends, in the space of those frames
+    pub(crate) fn paint(&mut self, scene: &mut Scene, from: Rect, to: Rect) {
+        let Some(line) = route(from, to) else {
+            return;
+        };
+        paint_arrow(scene, line, self.kind.color(), self.kind.dashes());
+
+        if let Some(layout) = &mut self.label_layout {
+            let size = layout.size();
+            let origin = line.p0.midpoint(line.p1) - size.to_vec2() / 2.0;
+            let background =
+                Rect::from_origin_size(origin, size).inflate(LABEL_PADDING, LABEL_PADDING);
+            fill_color(scene, &background, palette()["vscPopupBack"]);
+            layout.draw(scene, origin);
+        }
+    }
+}
+
+/// The line between the borders of two frames facing each other, or `None` if they overlap
+pub fn route(from: Rect, to: Rect) -> Option<Line> {
+    let from = from.inflate(BORDER_GAP, BORDER_GAP);
+    let to = to.inflate(BORDER_GAP, BORDER_GAP);
+    if from.intersect(to).area() > 0.0 {
+        return None;
+    }
+    Some(Line::new(
+        border_point(from, to.center()),
+        border_point(to, from.center()),
+    ))
+}
+
+/// The line from the border of `from` to a loose point, for edges that are being dragged out
+pub fn route_to_point(from: Rect, to: Point) -> Line {
+    let from = from.inflate(BORDER_GAP, BORDER_GAP);
+    Line::new(border_point(from, to), to)
+}
+
+/// Where the line from the center of `rect` towards `target` crosses its border
+fn border_point(rect: Rect, target: Point) -> Point {
+    let center = rect.center();
+    let direction = target - center;
+    let along_x = rect.width() / 2.0 / direction.x.abs();
+    let along_y = rect.height() / 2.0 / direction.y.abs();
+    let t = along_x.min(along_y);
+    if t.is_finite() {
+        center + direction * t.min(1.0)
+    } else {
+        center
+    }
+}
+
+/// A line with an arrowhead at `line.p1`
+pub fn paint_arrow(scene: &mut Scene, line: Line, color: Color, dashes: &[f64]) {
+    let direction = line.p1 - line.p0;
+    if direction.hypot() < ARROW_LENGTH {
+        return;
+    }
+    let direction = direction.normalize();
+
+    // End the shaft at the base of the arrowhead so a thick line doesn't poke through its tip
+    let base = line.p1 - direction * ARROW_LENGTH;
+    let style = Stroke::new(EDGE_WIDTH).with_dashes(0.0, dashes);
+    scene.stroke(
+        &style,
+        Affine::IDENTITY,
+        color,
+        None,
+        &Line::new(line.p0, base),
+    );
+    fill_color(scene, &arrowhead(line.p1, direction), color);
+}
+
+fn arrowhead(tip: Point, direction: Vec2) -> BezPath {
+    let base = tip - direction * ARROW_LENGTH;
+    let normal = Vec2::new(-direction.y, direction.x) * (ARROW_WIDTH / 2.0);
+    let mut path = BezPath::new();
+    path.move_to(tip);
+    path.line_to(base + normal);
+    path.line_to(base - normal);
+    path.close_path();
+    path
+}
@@ -0,0 +1,217 @@
+// A directory listing that opens files as new panels
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use accesskit::Role;
+use masonry::{
+    paint_scene_helpers::fill_color, parley::style::FontStack, text::TextLayout, vello::Scene,
+    AccessCtx, AccessEvent, BoxConstraints, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx,
+    Point, PointerEvent, Rect, Size, StatusChange, TextEvent, Widget, WidgetId,
+};
+use smallvec::SmallVec;
+use tracing::{trace_span, Span};
+
+use super::colors::palette;
+use super::commands::{Command, Commands};
+
+const ROW_HEIGHT: f64 = 20.0;
+const INDENT: f64 = 14.0;
+const PADDING: f64 = 6.0;
+const FONT_SIZE: f32 = 13.0;
+static FONT: FontStack = FontStack::Source("Source Code Pro");
+/// Entries that are never worth listing
+const IGNORED: [&str; 2] = ["target", "node_modules"];
+
+/// Every file under `root` that git doesn't ignore, relative to `root` and sorted
+pub fn workspace_files(root: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = ignore::WalkBuilder::new(root)
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(|entry| Some(entry.path().strip_prefix(root).ok()?.to_path_buf()))
+        .collect();
+    files.sort();
+    files
+}
+
+struct Row {
+    path: PathBuf,
+    depth: usize,
+    is_dir: bool,
+    layout: TextLayout<Arc<str>>,
+}
+
+pub struct FileTree {
+    root: PathBuf,
+    expanded: HashSet<PathBuf>,
+    rows: Vec<Row>,
+    hovered: Option<usize>,
+    /// Where clicked files are sent to be opened
+    commands: Commands,
+}
+
+impl FileTree {
+    pub fn new(root: impl Into<PathBuf>, commands: Commands) -> Self {
+        let mut tree = Self {
+            root: root.into(),
+            expanded: HashSet::new(),
+            rows: Vec::new(),
+            hovered: None,
+            commands,
+        };
+        tree.rebuild_rows();
+        tree
+    }
+
+    fn rebuild_rows(&mut self) {
+        let mut rows = Vec::new();
+        self.list(&self.root, 0, &mut rows);
+        self.rows = rows;
+        self.hovered = None;
+    }
+
+    /// Append the entries of `dir` and of its expanded subdirectories, directories first
+    fn list(&self, dir: &Path, depth: usize, rows: &mut Vec<Row>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<(PathBuf, bool)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                !name.starts_with('.') && !IGNORED.contains(&name.as_ref())
+            })
+            .map(|entry| {
+                let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+                (entry.path(), is_dir)
+            })
+            .collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        for (path, is_dir) in entries {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let expanded = self.expanded.contains(&path);
+            let text = match (is_dir, expanded) {
+                (true, true) => format!("▾ {name}/"),
+                (true, false) => format!("▸ {name}/"),
+                (false, _) => format!("  {name}"),
+            };
+            let mut layout = TextLayout::new(text.into(), FONT_SIZE);
+            layout.set_font(FONT);
+            layout.set_brush(palette()["vscFront"]);
+            rows.push(Row {
+                path: path.clone(),
+                depth,
+                is_dir,
+                layout,
+            });
+
+            if is_dir && expanded {
+                self.list(&path, depth + 1, rows);
+            }
+        }
+    }
+
+    fn row_at(&self, ctx: &EventCtx, event: &PointerEvent) -> Option<usize> {
+        let position = event.pointer_state().position;
+        let local = Point::new(position.x, position.y) - ctx.to_window(Point::ZERO);
+        let row = ((local.y - PADDING) / ROW_HEIGHT).floor();
+        (row >= 0.0 && (row as usize) < self.rows.len()).then_some(row as usize)
+    }
+}
+
+impl Widget for FileTree {
+    fn on_pointer_event(&mut self, ctx: &mut EventCtx, event: &PointerEvent) {
+        match event {
+            PointerEvent::PointerMove(_) => {
+                let hovered = self.row_at(ctx, event);
+                if hovered != self.hovered {
+                    self.hovered = hovered;
+                    ctx.request_paint();
+                }
+            }
+            PointerEvent::PointerLeave(_) => {
+                self.hovered = None;
+                ctx.request_paint();
+            }
+            PointerEvent::PointerDown(masonry::PointerButton::Primary, _) => {
+                let Some(row) = self.row_at(ctx, event) else {
+                    return;
+                };
+                let row = &self.rows[row];
+                if row.is_dir {
+                    let path = row.path.clone();
+                    if !self.expanded.remove(&path) {
+                        self.expanded.insert(path);
+                    }
+                    self.rebuild_rows();
+                    ctx.request_layout();
+                } else {
+                    self.commands.submit(Command::OpenFile(row.path.clone()));
+                }
+                ctx.set_handled();
+                ctx.request_paint();
+            }
+            _ => {}
+        }
+    }
+
+    fn on_text_event(&mut self, _ctx: &mut EventCtx, _event: &TextEvent) {}
+
+    fn on_access_event(&mut self, _ctx: &mut EventCtx, _event: &AccessEvent) {}
+
+    fn on_status_change(&mut self, _ctx: &mut LifeCycleCtx, _event: &StatusChange) {}
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, _event: &LifeCycle) {}
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints) -> Size {
+        let mut width: f64 = 0.0;
+        for row in &mut self.rows {
+            if row.layout.needs_rebuild() {
+                let (font_ctx, layout_ctx) = ctx.text_contexts();
+                row.layout.rebuild(font_ctx, layout_ctx);
+            }
+            width = width.max(row.depth as f64 * INDENT + row.layout.size().width);
+        }
+        let height = self.rows.len() as f64 * ROW_HEIGHT;
+        bc.constrain(Size::new(width + 2.0 * PADDING, height + 2.0 * PADDING))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, scene: &mut Scene) {
+        let width = ctx.size().width;
+        for (i, row) in self.rows.iter_mut().enumerate() {
+            let y = PADDING + i as f64 * ROW_HEIGHT;
+            if self.hovered == Some(i) {
+                let highlight = Rect::new(0.0, y, width, y + ROW_HEIGHT);
+                fill_color(scene, &highlight, palette()["vscPopupHighlightGray"]);
+            }
+            let text_y = y + (ROW_HEIGHT - row.layout.size().height) / 2.0;
+            row.layout.draw(
+                scene,
+                Point::new(PADDING + row.depth as f64 * INDENT, text_y),
+            );
+        }
+    }
+
+    fn accessibility_role(&self) -> Role {
+        Role::Tree
+    }
+
+    fn accessibility(&mut self, ctx: &mut AccessCtx) {
+        ctx.current_node().set_name(self.root.display().to_string());
+    }
+
+    fn children_ids(&self) -> SmallVec<[WidgetId; 16]> {
+        SmallVec::new()
+    }
+
+    fn make_trace_span(&self) -> Span {
+        trace_span!("FileTree")
+    }
+}
@@ -0,0 +1,302 @@
+// Regions of code that fold away behind a placeholder, found with tree-sitter
+
+use std::{cmp::Reverse, ops::Range};
+
+use tree_sitter::{Node, Tree};
+
+/// Node kinds whose braces stay visible around their folded content, like `{ … }`
+const BRACED: &[&str] = &[
+    "block",
+    "declaration_list",
+    "field_declaration_list",
+    "enum_variant_list",
+    "match_block",
+    "use_list",
+    "token_tree",
+];
+
+/// Shown in place of the content of braces
+pub const BRACED_PLACEHOLDER: &str = " … ";
+/// Shown after the first line of folded comments and `use`s
+pub const LINES_PLACEHOLDER: &str = " …";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fold {
+    /// The bytes hidden while folded
+    pub hidden: Range<usize>,
+    /// Shown in their place
+    pub placeholder: &'static str,
+    pub folded: bool,
+}
+
+impl Fold {
+    pub fn new(hidden: Range<usize>, placeholder: &'static str) -> Self {
+        Self {
+            hidden,
+            placeholder,
+            folded: false,
+        }
+    }
+}
+
+/// The regions of `source` that can fold: braced bodies of items and expressions, block
+/// comments, and runs of line comments or `use`s. Only regions over several lines fold.
+pub fn folds(tree: &Tree, source: &str) -> Vec<Fold> {
+    let mut folds = Vec::new();
+    collect(tree.root_node(), source, &mut folds);
+    sort(&mut folds);
+    folds
+}
+
+/// In the order they start, outer regions first
+pub fn sort(folds: &mut Vec<Fold>) {
+    folds.sort_by_key(|fold| (fold.hidden.start, Reverse(fold.hidden.end)));
+    folds.dedup_by_key(|fold| fold.hidden.clone());
+}
+
+fn collect(node: Node, source: &str, folds: &mut Vec<Fold>) {
+    let range = node.byte_range();
+    if BRACED.contains(&node.kind()) && range.len() >= 2 {
+        push(
+            source,
+            range.start + 1..range.end - 1,
+            BRACED_PLACEHOLDER,
+            folds,
+        );
+    } else if node.kind() == "block_comment" {
+        push(
+            source,
+            line_end(source, range.start)..range.end,
+            LINES_PLACEHOLDER,
+            folds,
+        );
+    }
+
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.named_children(&mut cursor).collect();
+    // Line comments and `use`s on consecutive lines fold into the first one
+    let mut start = 0;
+    while start < children.len() {
+        let kind = children[start].kind();
+        let mut end = start + 1;
+        if matches!(kind, "line_comment" | "use_declaration") {
+            while end < children.len()
+                && children[end].kind() == kind
+                && children[end].start_position().row <= last_row(children[end - 1]) + 1
+            {
+                end += 1;
+            }
+            let first = line_end(source, children[start].start_byte());
+            let last = trim_newlines(source, children[end - 1].end_byte());
+            push(source, first..last, LINES_PLACEHOLDER, folds);
+        }
+        start = end;
+    }
+
+    for child in children {
+        collect(child, source, folds);
+    }
+}
+
+fn push(source: &str, hidden: Range<usize>, placeholder: &'static str, folds: &mut Vec<Fold>) {
+    if hidden.start < hidden.end && source[hidden.clone()].contains('\n') {
+        folds.push(Fold::new(hidden, placeholder));
+    }
+}
+
+/// The row of the last char of `node`, which for line comments is before their newline
+fn last_row(node: Node) -> usize {
+    let end = node.end_position();
+    if end.column == 0 && end.row > node.start_position().row {
+        end.row - 1
+    } else {
+        end.row
+    }
+}
+
+/// The end of the line `offset` is on, before its newline
+fn line_end(source: &str, offset: usize) -> usize {
+    source[offset..]
+        .find('\n')
+        .map_or(source.len(), |i| offset + i)
+}
+
+fn trim_newlines(source: &str, end: usize) -> usize {
+    source[..end].trim_end_matches(['\n', '\r']).len()
+}
+
+/// The text with the folded regions replaced by their placeholders, and how offsets in it map to
+/// the source
+#[derive(Debug, Clone, Default)]
+pub struct FoldedText {
+    pub text: String,
+    /// The hidden bytes of the source with the bytes of their placeholder in `text`, in order
+    segments: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl FoldedText {
+    pub fn new(source: &str, folds: &[Fold]) -> Self {
+        let mut text = String::with_capacity(source.len());
+        let mut segments: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+        let mut copied = 0;
+        // Folds are sorted outer first, so those inside a folded one are skipped
+        for fold in folds.iter().filter(|fold| fold.folded) {
+            if fold.hidden.start < copied {
+                continue;
+            }
+            text.push_str(&source[copied..fold.hidden.start]);
+            let placeholder = text.len()..text.len() + fold.placeholder.len();
+            text.push_str(fold.placeholder);
+            segments.push((fold.hidden.clone(), placeholder));
+            copied = fold.hidden.end;
+        }
+        text.push_str(&source[copied..]);
+        Self { text, segments }
+    }
+
+    /// Where source byte `offset` is shown, which is the start of the placeholder if it's hidden
+    pub fn to_display(&self, offset: usize) -> usize {
+        let mut shown = offset;
+        for (hidden, placeholder) in &self.segments {
+            if offset <= hidden.start {
+                break;
+            }
+            if offset < hidden.end {
+                return placeholder.start;
+            }
+            shown = offset - hidden.end + placeholder.end;
+        }
+        shown
+    }
+
+    /// The source byte shown at `offset`, which is the start of the hidden bytes in a placeholder
+    pub fn to_source(&self, offset: usize) -> usize {
+        let mut source = offset;
+        for (hidden, placeholder) in &self.segments {
+            if offset <= placeholder.start {
+                break;
+            }
+            if offset < placeholder.end {
+                return hidden.start;
+            }
+            source = offset - placeholder.end + hidden.end;
+        }
+        source
+    }
+
+    /// The hidden bytes behind the placeholder shown at `offset`, if it's in one
+    pub fn hidden_at(&self, offset: usize) -> Option<Range<usize>> {
+        self.segments
+            .iter()
+            .find(|(_, placeholder)| placeholder.contains(&offset))
+            .map(|(hidden, _)| hidden.clone())
+    }
+
+    /// Whether source byte `offset` is hidden, not counting the first one of a folded region
+    pub fn is_hidden(&self, offset: usize) -> bool {
+        self.segments
+            .iter()
+            .any(|(hidden, _)| hidden.start < offset && offset < hidden.end)
+    }
+
+    /// Whether source byte `offset` is in `text`
+    pub fn is_shown(&self, offset: usize) -> bool {
+        !self
+            .segments
+            .iter()
+            .any(|(hidden, _)| hidden.contains(&offset))
+    }
+
+    /// Where the placeholders are in `text`
+    pub fn placeholders(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.segments
+            .iter()
+            .map(|(_, placeholder)| placeholder.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str =
+        "fn a() {\n    if x {\n        y\n    }\n    if z {\n        w\n    }\n}\n";
+
+    /// The body of `fn a`, then those of the two `if`s
+    fn nested_folds() -> Vec<Fold> {
+        let body = |open: usize| {
+            let close = open + SOURCE[open..].find("\n    }").unwrap() + "\n    ".len();
+            open + 1..close
+        };
+        let mut folds = vec![
+            Fold::new(
+                SOURCE.find('{').unwrap() + 1..SOURCE.rfind('}').unwrap(),
+                " … ",
+            ),
+            Fold::new(body(SOURCE.find("x {").unwrap() + 2), " … "),
+            Fold::new(body(SOURCE.find("z {").unwrap() + 2), " … "),
+        ];
+        sort(&mut folds);
+        folds
+    }
+
+    fn folded(which: &[usize]) -> FoldedText {
+        let mut folds = nested_folds();
+        for &i in which {
+            folds[i].folded = true;
+        }
+        FoldedText::new(SOURCE, &folds)
+    }
+
+    /// Each shown byte maps to the same byte of the text and back, and hidden ones to the
+    /// placeholder, which maps back to the start of what it hides
+    fn assert_round_trips(folded: &FoldedText) {
+        for offset in 0..=SOURCE.len() {
+            let shown = folded.to_display(offset);
+            let source = folded.to_source(shown);
+            if folded.is_shown(offset) {
+                assert_eq!(source, offset);
+                assert_eq!(
+                    folded.text.as_bytes().get(shown),
+                    SOURCE.as_bytes().get(offset)
+                );
+            } else {
+                let (hidden, placeholder) = folded
+                    .segments
+                    .iter()
+                    .find(|(hidden, _)| hidden.contains(&offset))
+                    .unwrap();
+                assert_eq!(shown, placeholder.start);
+                assert_eq!(source, hidden.start);
+            }
+        }
+    }
+
+    #[test]
+    fn unfolded_text_is_the_source() {
+        let folded = folded(&[]);
+        assert_eq!(folded.text, SOURCE);
+        assert_round_trips(&folded);
+    }
+
+    #[test]
+    fn inner_folds_round_trip() {
+        let folded = folded(&[1, 2]);
+        assert_eq!(folded.text, "fn a() {\n    if x { … }\n    if z { … }\n}\n");
+        assert_round_trips(&folded);
+        // After both placeholders
+        let last = SOURCE.rfind('}').unwrap();
+        assert_eq!(folded.to_display(last), folded.text.rfind('}').unwrap());
+    }
+
+    #[test]
+    fn outer_fold_hides_inner_ones() {
+        let folded = folded(&[0, 1, 2]);
+        assert_eq!(folded.text, "fn a() { … }\n");
+        assert_eq!(folded.placeholders().count(), 1);
+        assert_round_trips(&folded);
+        let y = SOURCE.find('y').unwrap();
+        assert_eq!(folded.to_display(y), "fn a() {".len());
+        assert!(folded.is_hidden(y));
+    }
+}
@@ -0,0 +1,147 @@
+// Fuzzy matching of typed queries against names and paths
+
+/// Bonus for a matched char right after the previous one
+const CONSECUTIVE_BONUS: i64 = 6;
+/// Bonus for a matched char at the start of a word, like after `/`, `_` or a lower case letter
+const WORD_START_BONUS: i64 = 8;
+/// Penalty for every char skipped between two matched ones
+const GAP_PENALTY: i64 = 1;
+
+/// How well `query` matches `candidate`, higher is better, and the byte offsets of the matched
+/// chars in `candidate`. `None` unless every char of `query` appears in `candidate` in order.
+/// Case is ignored and so is whitespace in `query`.
+///
+/// Of all the ways to match, this scores the best one, so `fo` matches the second word of
+/// `afoo_foo` rather than the first `f` it finds.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let chars: Vec<(usize, char)> = candidate.char_indices().collect();
+    let bonuses: Vec<i64> = chars
+        .iter()
+        .enumerate()
+        .map(|(j, &(_, c))| {
+            let word_start = match j.checked_sub(1).map(|p| chars[p].1) {
+                None => true,
+                Some(p) => !p.is_alphanumeric() || (p.is_lowercase() && c.is_uppercase()),
+            };
+            if word_start {
+                WORD_START_BONUS
+            } else {
+                0
+            }
+        })
+        .collect();
+    let equal = |wanted: char, c: char| c.to_lowercase().eq(std::iter::once(wanted));
+
+    // best[i][j] is the best score of matching query[..=i] with query[i] at chars[j], and where
+    // query[i - 1] went
+    let mut best: Vec<Vec<Option<(i64, usize)>>> = Vec::with_capacity(query.len());
+    for (i, &wanted) in query.iter().enumerate() {
+        let mut row = vec![None; chars.len()];
+        // The best earlier match of query[i - 1] that leaves a gap, valued as if the gap ended
+        // at char 0 so that the penalty only depends on where it ends
+        let mut gapped: Option<(i64, usize)> = None;
+        for (j, &(_, c)) in chars.iter().enumerate() {
+            if i > 0 && j >= 2 {
+                if let Some((score, _)) = best[i - 1][j - 2] {
+                    let value = score + (j - 2) as i64 * GAP_PENALTY;
+                    if !matches!(gapped, Some((best, _)) if best >= value) {
+                        gapped = Some((value, j - 2));
+                    }
+                }
+            }
+            if !equal(wanted, c) {
+                continue;
+            }
+
+            row[j] = if i == 0 {
+                // Skipping to the first match is free
+                Some((bonuses[j], 0))
+            } else {
+                let consecutive = j
+                    .checked_sub(1)
+                    .and_then(|k| best[i - 1][k])
+                    .map(|(score, _)| (score + CONSECUTIVE_BONUS, j - 1));
+                let gapped = gapped.map(|(value, k)| (value - (j - 1) as i64 * GAP_PENALTY, k));
+                match (consecutive, gapped) {
+                    (Some(a), Some(b)) => Some(if b.0 > a.0 { b } else { a }),
+                    (a, b) => a.or(b),
+                }
+                .map(|(score, k)| (score + bonuses[j], k))
+            };
+        }
+        best.push(row);
+    }
+
+    // Shorter candidates are closer to what was typed
+    let length_penalty = candidate.chars().count() as i64 / 8;
+    let Some(last) = best.last() else {
+        return Some((-length_penalty, Vec::new()));
+    };
+
+    // Follow the best last match back to the first
+    let (mut j, &(score, _)) = last
+        .iter()
+        .enumerate()
+        .filter_map(|(j, entry)| Some((j, entry.as_ref()?)))
+        .max_by_key(|(j, (score, _))| (*score, std::cmp::Reverse(*j)))?;
+    let mut matched = vec![0; query.len()];
+    for i in (0..query.len()).rev() {
+        matched[i] = chars[j].0;
+        j = best[i][j].unwrap().1;
+    }
+
+    Some((score - length_penalty, matched))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(query: &str, candidate: &str) -> Option<Vec<usize>> {
+        fuzzy_match(query, candidate).map(|(_, offsets)| offsets)
+    }
+
+    fn score(query: &str, candidate: &str) -> i64 {
+        fuzzy_match(query, candidate).unwrap().0
+    }
+
+    #[test]
+    fn matches_in_order() {
+        assert_eq!(offsets("cv", "canvas.rs"), Some(vec![0, 3]));
+        assert_eq!(offsets("vc", "canvas.rs"), None);
+        assert_eq!(offsets("canvases", "canvas.rs"), None);
+        assert_eq!(offsets("", "canvas.rs"), Some(vec![]));
+    }
+
+    #[test]
+    fn ignores_case_and_query_whitespace() {
+        assert_eq!(
+            offsets("CAN vas", "canvas.rs"),
+            offsets("canvas", "canvas.rs")
+        );
+        assert_eq!(offsets("gtd", "GoToDefinition"), Some(vec![0, 2, 4]));
+    }
+
+    #[test]
+    fn prefers_word_starts() {
+        assert_eq!(offsets("fo", "afoo_foo"), Some(vec![5, 6]));
+        assert_eq!(offsets("fb", "fooBar"), Some(vec![0, 3]));
+        assert_eq!(offsets("pr", "src/widget/palette.rs"), Some(vec![11, 19]));
+        // Bytes, not chars
+        assert_eq!(offsets("b", "é/b"), Some(vec![3]));
+    }
+
+    #[test]
+    fn orders_candidates() {
+        // Consecutive beats scattered, word starts beat the middle of words, short beats long
+        assert!(score("pan", "panels.rs") > score("pan", "pxaxn.rs"));
+        assert!(score("pan", "panels.rs") > score("pan", "span.rs"));
+        assert!(score("pal", "palette.rs") > score("pal", "sepal.rs"));
+        assert!(score("keymap", "keymap.rs") > score("keymap", "src/widget/keymap.rs"));
+    }
+}
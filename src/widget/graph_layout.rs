@@ -0,0 +1,375 @@
+// Automatic arrangement of panels. Every algorithm takes the frames of the panels and the edges
+// between them (as indices into `frames`) and returns a new origin for every frame.
+
+use std::collections::HashMap;
+
+use masonry::{Point, Rect, Size, Vec2};
+
+/// Space left between panels
+const GAP: f64 = 60.0;
+
+const FORCE_ITERATIONS: usize = 300;
+/// Barycenter sweeps when ordering the layers
+const ORDERING_SWEEPS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphLayout {
+    ForceDirected,
+    /// Sugiyama style layers, good for call graphs
+    Layered,
+    Grid,
+}
+
+impl GraphLayout {
+    pub fn arrange(&self, frames: &[Rect], edges: &[(usize, usize)]) -> Vec<Point> {
+        if frames.is_empty() {
+            return Vec::new();
+        }
+
+        let origins = match self {
+            GraphLayout::ForceDirected => force_directed(frames, edges),
+            GraphLayout::Layered => layered(frames, edges),
+            GraphLayout::Grid => grid(frames),
+        };
+
+        // Keep the arrangement where the panels already were
+        let anchor = bounds(frames.iter().copied()).origin();
+        let sized = origins
+            .iter()
+            .zip(frames)
+            .map(|(origin, frame)| Rect::from_origin_size(*origin, frame.size()));
+        let offset = anchor - bounds(sized).origin();
+        origins.into_iter().map(|origin| origin + offset).collect()
+    }
+}
+
+fn bounds(frames: impl Iterator<Item = Rect>) -> Rect {
+    frames.reduce(|a, b| a.union(b)).unwrap_or(Rect::ZERO)
+}
+
+/// Fruchterman-Reingold, treating panels as points at their centers that repel based on size
+fn force_directed(frames: &[Rect], edges: &[(usize, usize)]) -> Vec<Point> {
+    let count = frames.len();
+    let mut centers: Vec<Point> = frames.iter().map(|f| f.center()).collect();
+
+    let average_size = frames
+        .iter()
+        .map(|f| (f.width() + f.height()) / 2.0)
+        .sum::<f64>()
+        / count as f64;
+    // Ideal distance between connected panels
+    let k = average_size + GAP;
+    let mut temperature = k * 2.0;
+
+    for _ in 0..FORCE_ITERATIONS {
+        let mut displacement = vec![Vec2::ZERO; count];
+
+        for a in 0..count {
+            for b in (a + 1)..count {
+                let mut delta = centers[a] - centers[b];
+                if delta.hypot() < 1e-3 {
+                    // Nudge apart panels that sit exactly on top of each other
+                    delta = Vec2::new((a as f64 - b as f64) * 0.1, 0.1);
+                }
+                let distance = delta.hypot();
+                let force = k * k / distance;
+                let push = delta / distance * force;
+                displacement[a] += push;
+                displacement[b] -= push;
+            }
+        }
+
+        for &(a, b) in edges {
+            if a == b {
+                continue;
+            }
+            let delta = centers[a] - centers[b];
+            let distance = delta.hypot().max(1e-3);
+            let force = distance * distance / k;
+            let pull = delta / distance * force;
+            displacement[a] -= pull;
+            displacement[b] += pull;
+        }
+
+        for (center, displacement) in centers.iter_mut().zip(displacement) {
+            let length = displacement.hypot();
+            if length > 0.0 {
+                *center += displacement / length * length.min(temperature);
+            }
+        }
+
+        temperature *= 0.98;
+    }
+
+    centers
+        .iter()
+        .zip(frames)
+        .map(|(center, frame)| *center - frame.size().to_vec2() / 2.0)
+        .collect()
+}
+
+/// Sugiyama style layering: break cycles, assign layers by longest path, order each layer by the
+/// barycenter of its neighbours, then stack the layers top to bottom.
+///
+/// Long edges don't get dummy nodes, so crossings are reduced but not minimised.
+fn layered(frames: &[Rect], edges: &[(usize, usize)]) -> Vec<Point> {
+    let count = frames.len();
+    let edges = acyclic_edges(count, edges);
+
+    // Longest path layering
+    let mut layer_of = vec![0; count];
+    for _ in 0..count {
+        let mut changed = false;
+        for &(from, to) in &edges {
+            if layer_of[to] < layer_of[from] + 1 {
+                layer_of[to] = layer_of[from] + 1;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let layer_count = layer_of.iter().max().map_or(0, |max| max + 1);
+    let mut layers: Vec<Vec<usize>> = vec![Vec::new(); layer_count];
+    for (node, &layer) in layer_of.iter().enumerate() {
+        layers[layer].push(node);
+    }
+
+    // Barycenter ordering, sweeping down then up
+    for sweep in 0..ORDERING_SWEEPS {
+        let downwards = sweep % 2 == 0;
+        let order: Vec<usize> = if downwards {
+            (1..layer_count).collect()
+        } else {
+            (0..layer_count.saturating_sub(1)).rev().collect()
+        };
+
+        for layer in order {
+            let neighbour_layer = if downwards { layer - 1 } else { layer + 1 };
+            let positions: HashMap<usize, usize> = layers[neighbour_layer]
+                .iter()
+                .enumerate()
+                .map(|(position, &node)| (node, position))
+                .collect();
+
+            let barycenter = |node: usize| {
+                let neighbours: Vec<f64> = edges
+                    .iter()
+                    .filter_map(|&(from, to)| {
+                        let other = if from == node {
+                            to
+                        } else if to == node {
+                            from
+                        } else {
+                            return None;
+                        };
+                        positions.get(&other).map(|&p| p as f64)
+                    })
+                    .collect();
+                if neighbours.is_empty() {
+                    None
+                } else {
+                    Some(neighbours.iter().sum::<f64>() / neighbours.len() as f64)
+                }
+            };
+
+            let current = layers[layer].clone();
+            let mut keyed: Vec<(f64, usize)> = current
+                .iter()
+                .enumerate()
+                .map(|(position, &node)| (barycenter(node).unwrap_or(position as f64), node))
+                .collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            layers[layer] = keyed.into_iter().map(|(_, node)| node).collect();
+        }
+    }
+
+    // Coordinates: every layer is a centered row
+    let widths: Vec<f64> = layers
+        .iter()
+        .map(|layer| {
+            layer.iter().map(|&n| frames[n].width()).sum::<f64>()
+                + GAP * layer.len().saturating_sub(1) as f64
+        })
+        .collect();
+    let widest = widths.iter().copied().fold(0.0, f64::max);
+
+    let mut origins = vec![Point::ZERO; count];
+    let mut y = 0.0;
+    for (layer, width) in layers.iter().zip(widths) {
+        let mut x = (widest - width) / 2.0;
+        let mut height: f64 = 0.0;
+        for &node in layer {
+            origins[node] = Point::new(x, y);
+            x += frames[node].width() + GAP;
+            height = height.max(frames[node].height());
+        }
+        y += height + GAP;
+    }
+    origins
+}
+
+/// The edges with every edge that closes a cycle reversed, found with a depth first search
+fn acyclic_edges(count: usize, edges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit {
+        New,
+        Active,
+        Done,
+    }
+
+    let mut state = vec![Visit::New; count];
+    let mut result = Vec::with_capacity(edges.len());
+
+    fn visit(
+        node: usize,
+        edges: &[(usize, usize)],
+        state: &mut [Visit],
+        result: &mut Vec<(usize, usize)>,
+    ) {
+        state[node] = Visit::Active;
+        for &(from, to) in edges.iter().filter(|(from, _)| *from == node) {
+            match state[to] {
+                Visit::New => {
+                    result.push((from, to));
+                    visit(to, edges, state, result);
+                }
+                Visit::Active => {
+                    if from != to {
+                        result.push((to, from));
+                    }
+                }
+                Visit::Done => result.push((from, to)),
+            }
+        }
+        state[node] = Visit::Done;
+    }
+
+    for node in 0..count {
+        if state[node] == Visit::New {
+            visit(node, edges, &mut state, &mut result);
+        }
+    }
+    result
+}
+
+/// Shelf packing into rows of roughly square overall proportions, tallest panels first
+fn grid(frames: &[Rect]) -> Vec<Point> {
+    let area: f64 = frames
+        .iter()
+        .map(|f| (f.width() + GAP) * (f.height() + GAP))
+        .sum();
+    let widest = frames.iter().map(|f| f.width()).fold(0.0, f64::max);
+    let row_width = area.sqrt().max(widest);
+
+    let mut order: Vec<usize> = (0..frames.len()).collect();
+    order.sort_by(|&a, &b| frames[b].height().total_cmp(&frames[a].height()));
+
+    let mut origins = vec![Point::ZERO; frames.len()];
+    let mut cursor = Point::ZERO;
+    let mut row_height: f64 = 0.0;
+    for node in order {
+        let size: Size = frames[node].size();
+        if cursor.x > 0.0 && cursor.x + size.width > row_width {
+            cursor = Point::new(0.0, cursor.y + row_height + GAP);
+            row_height = 0.0;
+        }
+        origins[node] = cursor;
+        cursor.x += size.width + GAP;
+        row_height = row_height.max(size.height);
+    }
+    origins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(sizes: &[(f64, f64)]) -> Vec<Rect> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &(w, h))| Rect::from_origin_size((i as f64 * 10.0, 0.0), (w, h)))
+            .collect()
+    }
+
+    fn placed(frames: &[Rect], origins: &[Point]) -> Vec<Rect> {
+        origins
+            .iter()
+            .zip(frames)
+            .map(|(origin, frame)| Rect::from_origin_size(*origin, frame.size()))
+            .collect()
+    }
+
+    fn assert_apart(rects: &[Rect]) {
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                assert!(a.intersect(*b).area() == 0.0, "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    /// Whether the nodes can be ordered so every edge points forwards
+    fn is_acyclic(count: usize, edges: &[(usize, usize)]) -> bool {
+        let mut incoming = vec![0; count];
+        for &(_, to) in edges {
+            incoming[to] += 1;
+        }
+        let mut ready: Vec<usize> = (0..count).filter(|&n| incoming[n] == 0).collect();
+        let mut ordered = 0;
+        while let Some(node) = ready.pop() {
+            ordered += 1;
+            for &(from, to) in edges {
+                if from == node {
+                    incoming[to] -= 1;
+                    if incoming[to] == 0 {
+                        ready.push(to);
+                    }
+                }
+            }
+        }
+        ordered == count
+    }
+
+    #[test]
+    fn cycles_are_broken() {
+        let edges = [(0, 1), (1, 2), (2, 0), (2, 3), (3, 3), (3, 1)];
+        assert!(!is_acyclic(4, &edges));
+        let acyclic = acyclic_edges(4, &edges);
+        assert!(is_acyclic(4, &acyclic));
+        // Only the self loop is dropped, the other edges are kept or reversed
+        assert_eq!(acyclic.len(), edges.len() - 1);
+        for &(from, to) in &acyclic {
+            assert!(edges.contains(&(from, to)) || edges.contains(&(to, from)));
+        }
+    }
+
+    #[test]
+    fn layers_follow_edges() {
+        let frames = frames(&[(100.0, 50.0), (80.0, 120.0), (200.0, 40.0), (60.0, 60.0)]);
+        let edges = [(0, 1), (0, 2), (1, 3), (2, 3)];
+        let rects = placed(&frames, &GraphLayout::Layered.arrange(&frames, &edges));
+        for &(from, to) in &edges {
+            assert!(rects[from].y1 < rects[to].y0, "{from} is not above {to}");
+        }
+        assert_apart(&rects);
+    }
+
+    #[test]
+    fn grid_rows_do_not_overlap() {
+        let sizes: Vec<(f64, f64)> = (0..12)
+            .map(|i| (50.0 + 30.0 * (i % 4) as f64, 40.0 + 25.0 * (i % 5) as f64))
+            .collect();
+        let frames = frames(&sizes);
+        let origins = GraphLayout::Grid.arrange(&frames, &[]);
+        let rects = placed(&frames, &origins);
+        assert_apart(&rects);
+        // The arrangement stays where the panels were
+        assert_eq!(
+            bounds(rects.into_iter()).origin(),
+            bounds(frames.into_iter()).origin()
+        );
+    }
+}
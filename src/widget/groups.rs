@@ -0,0 +1,164 @@
+// Named frames around several panels, which move and collapse together
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use masonry::{
+    kurbo::Line,
+    paint_scene_helpers::{fill_color, stroke},
+    parley::{FontContext, LayoutContext},
+    text::{TextBrush, TextLayout},
+    vello::Scene,
+    Color, Point, Rect, Size,
+};
+
+use super::colors::palette;
+
+/// Space between the members of a group and its frame
+const GROUP_PADDING: f64 = 30.0;
+const GROUP_TITLE_HEIGHT: f64 = 26.0;
+const GROUP_TITLE_PADDING: f64 = 8.0;
+const GROUP_FONT_SIZE: f32 = 14.0;
+/// Size of the box a collapsed group is shown as
+const SUMMARY_SIZE: Size = Size::new(240.0, 64.0);
+
+pub struct Group {
+    pub name: String,
+    /// Indices into the panel's children
+    pub members: BTreeSet<usize>,
+    /// Shown as one summary box instead of its members
+    pub collapsed: bool,
+    title_layout: TextLayout<Arc<str>>,
+}
+
+impl Group {
+    pub fn new(name: impl Into<String>, members: BTreeSet<usize>) -> Self {
+        let mut title_layout = TextLayout::new("".into(), GROUP_FONT_SIZE);
+        title_layout.set_brush(palette()["vscFront"]);
+
+        let mut group = Self {
+            name: name.into(),
+            members,
+            collapsed: false,
+            title_layout,
+        };
+        group.update_title();
+        group
+    }
+
+    pub fn with_collapsed(mut self, collapsed: bool) -> Self {
+        self.collapsed = collapsed;
+        self.update_title();
+        self
+    }
+
+    /// Call after changing the name, the members or whether the group is collapsed
+    pub fn update_title(&mut self) {
+        let title = if self.collapsed {
+            format!("{} ({} panels)", self.name, self.members.len())
+        } else {
+            self.name.clone()
+        };
+        self.title_layout.set_text(title.into());
+    }
+
+    /// Forget the child at `index`, shifting the members after it down
+    pub fn remove_member(&mut self, index: usize) {
+        self.members = self
+            .members
+            .iter()
+            .filter(|&&i| i != index)
+            .map(|&i| if i > index { i - 1 } else { i })
+            .collect();
+        self.update_title();
+    }
+
+    /// The frame of the group around the frames of its members, or the summary box when
+    /// collapsed. `None` if there are no members to go around.
+    pub fn frame(&self, member_frames: impl Iterator<Item = Rect>) -> Option<Rect> {
+        let bounds = member_frames.reduce(|a, b| a.union(b))?;
+        let frame = bounds.inflate(GROUP_PADDING, GROUP_PADDING);
+        let frame = Rect::new(frame.x0, frame.y0 - GROUP_TITLE_HEIGHT, frame.x1, frame.y1);
+        if self.collapsed {
+            Some(Rect::from_origin_size(frame.origin(), SUMMARY_SIZE))
+        } else {
+            Some(frame)
+        }
+    }
+
+    pub fn title_bar(frame: Rect) -> Rect {
+        Rect::new(frame.x0, frame.y0, frame.x1, frame.y0 + GROUP_TITLE_HEIGHT)
+    }
+
+    pub fn collapse_button(frame: Rect) -> Rect {
+        let title_bar = Self::title_bar(frame);
+        Rect::new(
+            title_bar.x1 - GROUP_TITLE_HEIGHT,
+            title_bar.y0,
+            title_bar.x1,
+            title_bar.y1,
+        )
+    }
+
+    pub(crate) fn rebuild_title(
+        &mut self,
+        font_ctx: &mut FontContext,
+        layout_ctx: &mut LayoutContext<TextBrush>,
+    ) {
+        if self.title_layout.needs_rebuild() {
+            self.title_layout.rebuild(font_ctx, layout_ctx);
+        }
+    }
+
+    /// Paint the group into `frame`, highlighted when `active`, with the name highlighted when
+    /// it's `name_selected`
+    pub(crate) fn paint(
+        &mut self,
+        scene: &mut Scene,
+        frame: Rect,
+        active: bool,
+        name_selected: bool,
+    ) {
+        let colors = palette();
+        let border = if active {
+            colors["vscAccentBlue"]
+        } else {
+            colors["vscSplitLight"]
+        };
+        let rounded = frame.to_rounded_rect(8.0);
+
+        if self.collapsed {
+            fill_color(scene, &rounded, colors["vscPopupBack"]);
+        } else {
+            fill_color(scene, &rounded, border.with_alpha_factor(0.08));
+        }
+        stroke(scene, &rounded, border, 2.0);
+
+        let title_bar = Self::title_bar(frame);
+        let text_y = (GROUP_TITLE_HEIGHT - self.title_layout.size().height) / 2.0;
+        let text_origin = Point::new(title_bar.x0 + GROUP_TITLE_PADDING, title_bar.y0 + text_y);
+        if name_selected {
+            let selection = Rect::from_origin_size(text_origin, self.title_layout.size());
+            fill_color(scene, &selection, colors["vscSelection"]);
+        }
+        self.title_layout.draw(scene, text_origin);
+
+        // A chevron pointing down when expanded, right when collapsed
+        let glyph = Self::collapse_button(frame).inset(-9.0);
+        let (a, b, c) = if self.collapsed {
+            (
+                Point::new(glyph.x0, glyph.y0),
+                Point::new(glyph.x1, glyph.center().y),
+                Point::new(glyph.x0, glyph.y1),
+            )
+        } else {
+            (
+                Point::new(glyph.x0, glyph.y0),
+                Point::new(glyph.center().x, glyph.y1),
+                Point::new(glyph.x1, glyph.y0),
+            )
+        };
+        let color: Color = colors["vscPopupFront"];
+        stroke(scene, &Line::new(a, b), color, 1.5);
+        stroke(scene, &Line::new(b, c), color, 1.5);
+    }
+}
@@ -0,0 +1,140 @@
+// Undo and redo. Every widget keeps its own history of edits, and a shared clock orders the
+// edits of all of them so undo steps back through them in the order they were made.
+
+use std::{cell::Cell, rc::Rc, time::Instant};
+
+/// Continuous edits with the same key closer together than this, in seconds, are undone as one
+const COALESCE_WINDOW: f64 = 0.5;
+
+/// Numbers edits across histories. Each history sharing the clock drops its redo stack when an
+/// edit is recorded in any of them.
+#[derive(Clone, Default)]
+pub struct EditClock(Rc<Cell<u64>>);
+
+impl EditClock {
+    /// The number of the latest recorded edit
+    pub fn latest(&self) -> u64 {
+        self.0.get()
+    }
+
+    fn tick(&self) -> u64 {
+        let next = self.0.get() + 1;
+        self.0.set(next);
+        next
+    }
+}
+
+/// Undo and redo stacks of edits `E`, which are whatever is needed to revert a change
+pub struct History<E> {
+    undo: Vec<(u64, E)>,
+    redo: Vec<(u64, E)>,
+    clock: EditClock,
+    /// The latest edit on the clock when `redo` was valid
+    seen: u64,
+    /// Key and time of the last recorded edit, for coalescing
+    last: Option<(&'static str, Instant)>,
+}
+
+impl<E> History<E> {
+    pub fn new(clock: EditClock) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            seen: clock.latest(),
+            clock,
+            last: None,
+        }
+    }
+
+    /// Anything recorded elsewhere since our last undo makes our redo stack stale
+    fn sync(&mut self) {
+        if self.clock.latest() != self.seen {
+            self.redo.clear();
+            self.seen = self.clock.latest();
+        }
+    }
+
+    pub fn record(&mut self, edit: E) {
+        let number = self.clock.tick();
+        self.seen = number;
+        self.redo.clear();
+        self.undo.push((number, edit));
+        self.last = None;
+    }
+
+    /// Record `edit` unless the previous edit had the same `key`, nothing else was recorded since
+    /// and it was recent, in which case it already reverts this one as well
+    pub fn record_coalesced(&mut self, key: &'static str, edit: impl FnOnce() -> E) {
+        let now = Instant::now();
+        let continues = match (self.last, self.undo.last()) {
+            (Some((last_key, time)), Some((number, _))) => {
+                last_key == key
+                    && *number == self.clock.latest()
+                    && now.duration_since(time).as_secs_f64() < COALESCE_WINDOW
+            }
+            _ => false,
+        };
+        if !continues {
+            self.record(edit());
+        }
+        self.last = Some((key, now));
+    }
+
+    /// The number of the edit undo would revert
+    pub fn undo_number(&self) -> Option<u64> {
+        self.undo.last().map(|(number, _)| *number)
+    }
+
+    /// The number of the edit redo would reapply
+    pub fn redo_number(&mut self) -> Option<u64> {
+        self.sync();
+        self.redo.last().map(|(number, _)| *number)
+    }
+
+    /// Take the edit to undo. Push what reverts the undo with [`History::push_redo`]
+    pub fn pop_undo(&mut self) -> Option<(u64, E)> {
+        self.sync();
+        self.last = None;
+        self.undo.pop()
+    }
+
+    pub fn push_redo(&mut self, number: u64, edit: E) {
+        self.redo.push((number, edit));
+    }
+
+    /// Take the edit to redo. Push what reverts the redo with [`History::push_undo`]
+    pub fn pop_redo(&mut self) -> Option<(u64, E)> {
+        self.sync();
+        self.last = None;
+        self.redo.pop()
+    }
+
+    pub fn push_undo(&mut self, number: u64, edit: E) {
+        self.undo.push((number, edit));
+    }
+}
+
+/// Whether our undo comes before `other`'s, given the numbers of the edits each would revert
+pub fn undo_first(ours: Option<u64>, other: Option<u64>) -> bool {
+    match (ours, other) {
+        (Some(ours), Some(other)) => ours > other,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// Whether our redo comes before `other`'s, given the numbers of the edits each would reapply
+pub fn redo_first(ours: Option<u64>, other: Option<u64>) -> bool {
+    match (ours, other) {
+        (Some(ours), Some(other)) => ours < other,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// Which way to go through the history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Undo,
+    Redo,
+}
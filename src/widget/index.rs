@@ -0,0 +1,104 @@
+// The files of the workspace and the symbols defined in them, found once on a background thread so
+// the file finder and the symbol search don't wait for the disk
+
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use super::file_tree::workspace_files;
+use super::symbols::{index_symbols, SymbolLocation};
+
+/// What the indexing thread found, files first
+enum Indexed {
+    Files(Arc<[PathBuf]>),
+    Symbols(Arc<[SymbolLocation]>),
+}
+
+struct Index {
+    files: Option<Arc<[PathBuf]>>,
+    symbols: Option<Arc<[SymbolLocation]>>,
+    /// From the indexing thread, until it's done
+    updates: Option<Receiver<Indexed>>,
+}
+
+/// The index of the workspace, shared by whatever searches it
+#[derive(Clone)]
+pub struct WorkspaceIndex {
+    root: PathBuf,
+    index: Rc<RefCell<Index>>,
+}
+
+impl WorkspaceIndex {
+    /// Start indexing the workspace at `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let (sender, updates) = mpsc::channel();
+        let walked = root.clone();
+        thread::spawn(move || {
+            let files: Arc<[PathBuf]> = workspace_files(&walked).into();
+            // Nobody is waiting anymore once the index is dropped
+            if sender.send(Indexed::Files(files.clone())).is_err() {
+                return;
+            }
+            let symbols = index_symbols(&walked, &files);
+            let _ = sender.send(Indexed::Symbols(symbols.into()));
+        });
+        Self {
+            root,
+            index: Rc::new(RefCell::new(Index {
+                files: None,
+                symbols: None,
+                updates: Some(updates),
+            })),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The files git doesn't ignore, relative to the root and sorted. `None` while they're still
+    /// being listed.
+    pub fn files(&self) -> Option<Arc<[PathBuf]>> {
+        self.receive();
+        self.index.borrow().files.clone()
+    }
+
+    /// The symbols defined in the Rust files, `None` while they're still being parsed
+    pub fn symbols(&self) -> Option<Arc<[SymbolLocation]>> {
+        self.receive();
+        self.index.borrow().symbols.clone()
+    }
+
+    fn receive(&self) {
+        let index = &mut *self.index.borrow_mut();
+        let Some(updates) = &index.updates else {
+            return;
+        };
+        loop {
+            match updates.try_recv() {
+                Ok(Indexed::Files(files)) => index.files = Some(files),
+                Ok(Indexed::Symbols(symbols)) => {
+                    index.symbols = Some(symbols);
+                    index.updates = None;
+                    return;
+                }
+                Err(TryRecvError::Empty) => return,
+                // The thread panicked, so searches get what was found instead of waiting forever
+                Err(TryRecvError::Disconnected) => {
+                    index.files.get_or_insert_with(|| Arc::from([]));
+                    index.symbols.get_or_insert_with(|| Arc::from([]));
+                    index.updates = None;
+                    return;
+                }
+            }
+        }
+    }
+}
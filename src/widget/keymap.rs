@@ -0,0 +1,676 @@
+// Key chords and mouse buttons bound to commands, by where the keyboard focus is. The defaults
+// can be changed from a TOML file like
+//
+//     [panel]
+//     "ctrl+k ctrl+w" = "close"
+//     "alt+]" = ""  # unbind
+//
+//     [mouse]
+//     pan = "right"
+//     drag = "middle"
+
+use std::{cell::RefCell, fmt, fs, io, path::Path, rc::Rc};
+
+use masonry::PointerButton;
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+use super::commands::{Command, CommandError, CommandRegistry};
+
+/// Where a binding applies. The canvas always does, the others only while their widget has the
+/// keyboard. Code gets the keys without ctrl, alt or super before the other contexts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyContext {
+    Canvas,
+    /// A panel is focused
+    Panel,
+    /// The focused panel shows code
+    Code,
+    /// A popup takes the typing, like the palette or renaming a group. Nothing else applies.
+    Popup,
+}
+
+impl KeyContext {
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyContext::Canvas => "canvas",
+            KeyContext::Panel => "panel",
+            KeyContext::Code => "code",
+            KeyContext::Popup => "popup",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            KeyContext::Canvas,
+            KeyContext::Panel,
+            KeyContext::Code,
+            KeyContext::Popup,
+        ]
+        .into_iter()
+        .find(|context| context.name() == name)
+    }
+}
+
+/// Names of the keys that don't type a char, as written in bindings
+const NAMED_KEYS: &[(&str, NamedKey)] = &[
+    ("Escape", NamedKey::Escape),
+    ("Enter", NamedKey::Enter),
+    ("Tab", NamedKey::Tab),
+    ("Space", NamedKey::Space),
+    ("Backspace", NamedKey::Backspace),
+    ("Delete", NamedKey::Delete),
+    ("Insert", NamedKey::Insert),
+    ("Home", NamedKey::Home),
+    ("End", NamedKey::End),
+    ("PageUp", NamedKey::PageUp),
+    ("PageDown", NamedKey::PageDown),
+    ("Left", NamedKey::ArrowLeft),
+    ("Right", NamedKey::ArrowRight),
+    ("Up", NamedKey::ArrowUp),
+    ("Down", NamedKey::ArrowDown),
+    ("F1", NamedKey::F1),
+    ("F2", NamedKey::F2),
+    ("F3", NamedKey::F3),
+    ("F4", NamedKey::F4),
+    ("F5", NamedKey::F5),
+    ("F6", NamedKey::F6),
+    ("F7", NamedKey::F7),
+    ("F8", NamedKey::F8),
+    ("F9", NamedKey::F9),
+    ("F10", NamedKey::F10),
+    ("F11", NamedKey::F11),
+    ("F12", NamedKey::F12),
+];
+
+/// One key with its modifiers, like `ctrl+k`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keystroke {
+    /// A lower case char, or one of [`NAMED_KEYS`]
+    key: String,
+    mods: ModifiersState,
+}
+
+impl Keystroke {
+    /// `None` for keys that can't be bound, like the modifiers themselves
+    pub fn from_key(key: &Key, mods: ModifiersState) -> Option<Self> {
+        let key = match key {
+            Key::Character(c) => c.to_lowercase(),
+            Key::Named(named) => NAMED_KEYS
+                .iter()
+                .find(|(_, n)| n == named)
+                .map(|(name, _)| name.to_string())?,
+            _ => return None,
+        };
+        Some(Self::new(key, mods))
+    }
+
+    /// Whether the focused code gets this key before the outer contexts, as it would to edit.
+    /// Function keys never go to the code.
+    fn reaches_code(&self) -> bool {
+        let command_mods = ModifiersState::CONTROL | ModifiersState::ALT | ModifiersState::SUPER;
+        let function_key = self.key.len() > 1 && self.key.starts_with('F');
+        !self.mods.intersects(command_mods) && !function_key
+    }
+
+    fn new(key: String, mut mods: ModifiersState) -> Self {
+        // Shift is already part of chars like `}`, so `shift+}` and `}` are the same key
+        if !key.chars().any(char::is_alphabetic) && key.chars().count() == 1 {
+            mods.remove(ModifiersState::SHIFT);
+        }
+        Self { key, mods }
+    }
+
+    /// Parse modifiers and a key joined by `+`, like `ctrl+shift+p` or `ctrl++`
+    pub fn parse(text: &str) -> Option<Self> {
+        let (modifiers, key) = match text.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None if text == "+" => ("", "+"),
+            None => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+        let mut mods = ModifiersState::empty();
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            mods |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => ModifiersState::CONTROL,
+                "alt" => ModifiersState::ALT,
+                "shift" => ModifiersState::SHIFT,
+                "super" | "meta" | "cmd" => ModifiersState::SUPER,
+                _ => return None,
+            };
+        }
+        let key = if key.chars().count() == 1 {
+            key.to_lowercase()
+        } else {
+            NAMED_KEYS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(name, _)| name.to_string())?
+        };
+        Some(Self::new(key, mods))
+    }
+}
+
+impl fmt::Display for Keystroke {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (ModifiersState::CONTROL, "Ctrl+"),
+            (ModifiersState::ALT, "Alt+"),
+            (ModifiersState::SHIFT, "Shift+"),
+            (ModifiersState::SUPER, "Super+"),
+        ] {
+            if self.mods.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        if self.key.chars().count() == 1 {
+            f.write_str(&self.key.to_uppercase())
+        } else {
+            f.write_str(&self.key)
+        }
+    }
+}
+
+/// Keystrokes pressed one after the other, like `ctrl+k ctrl+c`
+pub type Chord = Vec<Keystroke>;
+
+pub fn parse_chord(text: &str) -> Option<Chord> {
+    let chord: Option<Chord> = text.split_whitespace().map(Keystroke::parse).collect();
+    chord.filter(|chord| !chord.is_empty())
+}
+
+struct Binding {
+    context: KeyContext,
+    chord: Chord,
+    command: Command,
+}
+
+/// The mouse buttons that aren't for clicking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseBindings {
+    /// Dragging with it pans the canvas
+    pub pan: PointerButton,
+    /// Dragging a panel anywhere with it moves the panel
+    pub drag: PointerButton,
+}
+
+impl Default for MouseBindings {
+    fn default() -> Self {
+        Self {
+            pan: PointerButton::Auxiliary,
+            drag: PointerButton::Secondary,
+        }
+    }
+}
+
+/// The primary button selects and clicks, so it can't be bound
+fn parse_button(name: &str) -> Option<PointerButton> {
+    match name {
+        "right" | "secondary" => Some(PointerButton::Secondary),
+        "middle" | "auxiliary" => Some(PointerButton::Auxiliary),
+        "back" => Some(PointerButton::X1),
+        "forward" => Some(PointerButton::X2),
+        _ => None,
+    }
+}
+
+/// The default bindings, as they would be written in the keymap file
+const DEFAULT_BINDINGS: &[(KeyContext, &str, &str)] = &[
+    (KeyContext::Canvas, "ctrl+z", "undo"),
+    (KeyContext::Canvas, "ctrl+shift+z", "redo"),
+    (KeyContext::Canvas, "ctrl+y", "redo"),
+    (KeyContext::Canvas, "ctrl+shift+p", "palette"),
+    (KeyContext::Canvas, "ctrl+p", "quick-open"),
+    (KeyContext::Canvas, "ctrl+t", "symbols"),
+    (KeyContext::Canvas, "ctrl+a", "select-all"),
+    (KeyContext::Canvas, "ctrl+b", "bookmark"),
+    (KeyContext::Canvas, "ctrl+g", "grid"),
+    (KeyContext::Canvas, "ctrl+s", "save"),
+    (KeyContext::Canvas, "f5", "present"),
+    (KeyContext::Canvas, "left", "pan left"),
+    (KeyContext::Canvas, "right", "pan right"),
+    (KeyContext::Canvas, "up", "pan up"),
+    (KeyContext::Canvas, "down", "pan down"),
+    (KeyContext::Canvas, "a", "pan left"),
+    (KeyContext::Canvas, "d", "pan right"),
+    (KeyContext::Canvas, "w", "pan up"),
+    (KeyContext::Canvas, "s", "pan down"),
+    (KeyContext::Canvas, "+", "zoom-in"),
+    (KeyContext::Canvas, "=", "zoom-in"),
+    (KeyContext::Canvas, "-", "zoom-out"),
+    (KeyContext::Canvas, "_", "zoom-out"),
+    (KeyContext::Canvas, "escape", "clear-selection"),
+    (KeyContext::Canvas, "delete", "delete"),
+    (KeyContext::Canvas, "backspace", "delete"),
+    (KeyContext::Canvas, "alt+left", "align left"),
+    (KeyContext::Canvas, "alt+right", "align right"),
+    (KeyContext::Canvas, "alt+up", "align top"),
+    (KeyContext::Canvas, "alt+down", "align bottom"),
+    (KeyContext::Canvas, "alt+h", "distribute horizontal"),
+    (KeyContext::Canvas, "alt+v", "distribute vertical"),
+    (KeyContext::Canvas, "alt+c", "recolor"),
+    (KeyContext::Canvas, "alt+f", "arrange force"),
+    (KeyContext::Canvas, "alt+l", "arrange layered"),
+    (KeyContext::Canvas, "alt+g", "arrange grid"),
+    (KeyContext::Canvas, "alt+o", "overlap"),
+    (KeyContext::Canvas, "alt+u", "ungroup"),
+    (KeyContext::Panel, "ctrl+w", "close"),
+    (KeyContext::Panel, "alt+]", "raise"),
+    (KeyContext::Panel, "alt+[", "lower"),
+    (KeyContext::Panel, "alt+}", "front"),
+    (KeyContext::Panel, "alt+{", "back"),
+    (KeyContext::Code, "alt+shift+right", "expand-selection"),
+    (KeyContext::Code, "alt+shift+left", "shrink-selection"),
+    (KeyContext::Code, "ctrl+[", "fold"),
+    (KeyContext::Code, "ctrl+]", "unfold"),
+    // Switch to another palette without closing the open one first
+    (KeyContext::Popup, "ctrl+shift+p", "palette"),
+    (KeyContext::Popup, "ctrl+p", "quick-open"),
+    (KeyContext::Popup, "ctrl+t", "symbols"),
+];
+
+#[derive(Debug)]
+pub enum KeymapError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    UnknownContext(String),
+    BadChord(String),
+    BadButton(String),
+    /// Panning and dragging panels can't share a button
+    SameButton,
+    /// The value of a binding isn't a command line
+    NotACommand(String),
+    Command {
+        chord: String,
+        error: CommandError,
+    },
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::Io(error) => write!(f, "can't read the keymap: {error}"),
+            KeymapError::Toml(error) => write!(f, "malformed keymap: {error}"),
+            KeymapError::UnknownContext(name) => write!(f, "unknown keymap section `{name}`"),
+            KeymapError::BadChord(chord) => write!(f, "can't read the keys `{chord}`"),
+            KeymapError::BadButton(name) => write!(f, "can't bind the mouse button `{name}`"),
+            KeymapError::SameButton => write!(f, "pan and drag are bound to the same button"),
+            KeymapError::NotACommand(chord) => {
+                write!(f, "`{chord}` should be bound to a command as a string")
+            }
+            KeymapError::Command { chord, error } => write!(f, "`{chord}`: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+/// What a key press did
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyPress {
+    /// The key isn't bound, and goes to the widgets as usual
+    Unbound,
+    /// The key starts or continues a chord
+    Pending,
+    /// The key broke off a chord, and is used up
+    Cancelled,
+    Run(Command),
+}
+
+fn in_context(bindings: &[Binding], context: KeyContext) -> impl Iterator<Item = &Binding> {
+    bindings
+        .iter()
+        .filter(move |binding| binding.context == context)
+}
+
+struct Bindings {
+    bindings: Vec<Binding>,
+    mouse: MouseBindings,
+    /// The keystrokes of the chord being typed
+    pending: Chord,
+    /// The contexts of the content, most specific first, as told by the panel
+    content: Vec<KeyContext>,
+    /// A command for the content, left by the canvas along with the event it goes with
+    handed_over: Option<Command>,
+}
+
+/// The keymap, shared by the canvas which resolves the keys and the content it hands commands to
+#[derive(Clone)]
+pub struct Keymap(Rc<RefCell<Bindings>>);
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&CommandRegistry::default())
+    }
+}
+
+impl Keymap {
+    /// The default bindings
+    pub fn new(registry: &CommandRegistry) -> Self {
+        let keymap = Self(Rc::new(RefCell::new(Bindings {
+            bindings: Vec::new(),
+            mouse: MouseBindings::default(),
+            pending: Vec::new(),
+            content: Vec::new(),
+            handed_over: None,
+        })));
+        for (context, chord, line) in DEFAULT_BINDINGS {
+            keymap
+                .bind(*context, chord, line, registry)
+                .expect("Default binding is invalid");
+        }
+        keymap
+    }
+
+    /// The defaults changed by the file at `path`, if there is one
+    pub fn load(path: &Path, registry: &CommandRegistry) -> Result<Self, KeymapError> {
+        let keymap = Self::new(registry);
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(keymap),
+            Err(error) => return Err(KeymapError::Io(error)),
+        };
+        let table: toml::Table = text.parse().map_err(KeymapError::Toml)?;
+        for (section, entries) in &table {
+            let entries = entries
+                .as_table()
+                .ok_or_else(|| KeymapError::UnknownContext(section.clone()))?;
+            if section == "mouse" {
+                keymap.bind_mouse(entries)?;
+                continue;
+            }
+            let context = KeyContext::from_name(section)
+                .ok_or_else(|| KeymapError::UnknownContext(section.clone()))?;
+            for (chord, line) in entries {
+                let line = line
+                    .as_str()
+                    .ok_or_else(|| KeymapError::NotACommand(chord.clone()))?;
+                keymap.bind(context, chord, line, registry)?;
+            }
+        }
+        Ok(keymap)
+    }
+
+    fn bind_mouse(&self, entries: &toml::Table) -> Result<(), KeymapError> {
+        let mut mouse = self.mouse();
+        for (action, button) in entries {
+            let name = button.as_str().unwrap_or_default();
+            let button = parse_button(name).ok_or_else(|| KeymapError::BadButton(name.into()))?;
+            match action.as_str() {
+                "pan" => mouse.pan = button,
+                "drag" => mouse.drag = button,
+                _ => return Err(KeymapError::BadButton(action.clone())),
+            }
+        }
+        // A drag with the button would do both
+        if mouse.pan == mouse.drag {
+            return Err(KeymapError::SameButton);
+        }
+        self.0.borrow_mut().mouse = mouse;
+        Ok(())
+    }
+
+    /// Bind `chord` to the command `line` in `context`, replacing what it was bound to there.
+    /// An empty `line` unbinds the chord.
+    pub fn bind(
+        &self,
+        context: KeyContext,
+        chord: &str,
+        line: &str,
+        registry: &CommandRegistry,
+    ) -> Result<(), KeymapError> {
+        let keys = parse_chord(chord).ok_or_else(|| KeymapError::BadChord(chord.into()))?;
+        let mut bindings = self.0.borrow_mut();
+        bindings
+            .bindings
+            .retain(|binding| binding.context != context || binding.chord != keys);
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        let command = registry.parse(line).map_err(|error| KeymapError::Command {
+            chord: chord.into(),
+            error,
+        })?;
+        bindings.bindings.push(Binding {
+            context,
+            chord: keys,
+            command,
+        });
+        Ok(())
+    }
+
+    pub fn mouse(&self) -> MouseBindings {
+        self.0.borrow().mouse
+    }
+
+    /// Tell the canvas which contexts the content is in, most specific first
+    pub fn set_content_contexts(&self, contexts: Vec<KeyContext>) {
+        self.0.borrow_mut().content = contexts;
+    }
+
+    /// The contexts keys are resolved in, most specific first. A popup hides everything else.
+    pub fn contexts(&self, popup: bool) -> Vec<KeyContext> {
+        let content = &self.0.borrow().content;
+        if popup || content.contains(&KeyContext::Popup) {
+            return vec![KeyContext::Popup];
+        }
+        let mut contexts = content.clone();
+        contexts.push(KeyContext::Canvas);
+        contexts
+    }
+
+    /// Add a pressed key to the chord being typed, and see what it's bound to in `contexts`
+    pub fn press(&self, key: &Key, mods: ModifiersState, contexts: &[KeyContext]) -> KeyPress {
+        let Some(stroke) = Keystroke::from_key(key, mods) else {
+            // Holding down the modifiers of the next keystroke doesn't break a chord
+            return KeyPress::Unbound;
+        };
+        let Bindings {
+            bindings, pending, ..
+        } = &mut *self.0.borrow_mut();
+        pending.push(stroke);
+
+        // Code keeps the keys it would edit with from the panel and the canvas
+        let to_code = contexts.contains(&KeyContext::Code) && pending[0].reaches_code();
+        let contexts: Vec<KeyContext> = contexts
+            .iter()
+            .copied()
+            .filter(|&context| !to_code || context == KeyContext::Code)
+            .collect();
+
+        let exact = contexts.iter().find_map(|&context| {
+            in_context(bindings, context).find(|binding| binding.chord == *pending)
+        });
+        if let Some(binding) = exact {
+            let command = binding.command.clone();
+            pending.clear();
+            return KeyPress::Run(command);
+        }
+        let started = contexts
+            .iter()
+            .flat_map(|&context| in_context(bindings, context))
+            .any(|binding| {
+                binding.chord.len() > pending.len() && binding.chord.starts_with(pending)
+            });
+        if started {
+            return KeyPress::Pending;
+        }
+        let in_chord = pending.len() > 1;
+        pending.clear();
+        if in_chord {
+            KeyPress::Cancelled
+        } else {
+            KeyPress::Unbound
+        }
+    }
+
+    /// Leave `command` for the content to run when it gets the current event
+    pub fn hand_over(&self, command: Command) {
+        self.0.borrow_mut().handed_over = Some(command);
+    }
+
+    pub fn is_handing_over(&self) -> bool {
+        self.0.borrow().handed_over.is_some()
+    }
+
+    /// The command handed over with the current event, if nobody ran it yet
+    pub fn take(&self) -> Option<Command> {
+        self.0.borrow_mut().handed_over.take()
+    }
+
+    /// The chords bound to the command `name`, as shown in the palette
+    pub fn describe(&self, name: &str) -> Option<String> {
+        let bindings = self.0.borrow();
+        let chords: Vec<String> = bindings
+            .bindings
+            .iter()
+            .filter(|binding| {
+                binding.command.name() == name && binding.context != KeyContext::Popup
+            })
+            .map(|binding| {
+                let strokes: Vec<String> = binding.chord.iter().map(|s| s.to_string()).collect();
+                strokes.join(" ")
+            })
+            .collect();
+        (!chords.is_empty()).then(|| chords.join(" / "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::widget::canvas::PanDirection;
+
+    const CTRL: ModifiersState = ModifiersState::CONTROL;
+    const SHIFT: ModifiersState = ModifiersState::SHIFT;
+
+    fn char_key(c: &str) -> Key {
+        Key::Character(c.into())
+    }
+
+    #[test]
+    fn parses_keystrokes() {
+        let stroke = |key: &str, mods| Some(Keystroke::new(key.to_string(), mods));
+        assert_eq!(Keystroke::parse("Ctrl+Shift+P"), stroke("p", CTRL | SHIFT));
+        assert_eq!(Keystroke::parse("control+k"), stroke("k", CTRL));
+        assert_eq!(Keystroke::parse("ctrl++"), stroke("+", CTRL));
+        assert_eq!(Keystroke::parse("+"), stroke("+", ModifiersState::empty()));
+        assert_eq!(
+            Keystroke::parse("pagedown"),
+            stroke("PageDown", ModifiersState::empty())
+        );
+        assert_eq!(
+            Keystroke::parse("alt+F5"),
+            stroke("F5", ModifiersState::ALT)
+        );
+        assert_eq!(Keystroke::parse("hyper+k"), None);
+        assert_eq!(Keystroke::parse("ctrl+nope"), None);
+        assert_eq!(parse_chord("  "), None);
+    }
+
+    #[test]
+    fn shift_is_part_of_symbols() {
+        let brace = Keystroke::parse("}");
+        assert_eq!(Keystroke::parse("shift+}"), brace);
+        assert_eq!(Keystroke::from_key(&char_key("}"), SHIFT), brace);
+        // Letters keep it, since shift+a and a are different keys
+        assert_ne!(Keystroke::parse("shift+a"), Keystroke::parse("a"));
+        assert_eq!(
+            Keystroke::from_key(&char_key("A"), SHIFT),
+            Keystroke::parse("shift+a")
+        );
+    }
+
+    #[test]
+    fn presses_chords() {
+        let registry = CommandRegistry::default();
+        let keymap = Keymap::new(&registry);
+        keymap
+            .bind(KeyContext::Canvas, "ctrl+k ctrl+w", "grid", &registry)
+            .unwrap();
+        let contexts = keymap.contexts(false);
+        let press = |key: &str, mods| keymap.press(&char_key(key), mods, &contexts);
+
+        assert_eq!(press("k", CTRL), KeyPress::Pending);
+        assert_eq!(press("w", CTRL), KeyPress::Run(Command::ToggleGrid));
+        // A key that doesn't go on with the chord is used up, and the next one starts over
+        assert_eq!(press("k", CTRL), KeyPress::Pending);
+        assert_eq!(press("x", CTRL), KeyPress::Cancelled);
+        assert_eq!(press("g", CTRL), KeyPress::Run(Command::ToggleGrid));
+        assert_eq!(press("x", CTRL), KeyPress::Unbound);
+        // Holding a modifier down doesn't break a chord
+        assert_eq!(press("k", CTRL), KeyPress::Pending);
+        let ctrl = keymap.press(&Key::Named(NamedKey::Control), CTRL, &contexts);
+        assert_eq!(ctrl, KeyPress::Unbound);
+        assert_eq!(press("w", CTRL), KeyPress::Run(Command::ToggleGrid));
+    }
+
+    #[test]
+    fn code_keeps_editing_keys() {
+        let keymap = Keymap::default();
+        let backspace = Key::Named(NamedKey::Backspace);
+        let empty = ModifiersState::empty();
+
+        keymap.set_content_contexts(vec![KeyContext::Panel]);
+        let contexts = keymap.contexts(false);
+        let delete = KeyPress::Run(Command::DeleteSelection);
+        assert_eq!(keymap.press(&backspace, empty, &contexts), delete);
+
+        keymap.set_content_contexts(vec![KeyContext::Code, KeyContext::Panel]);
+        let contexts = keymap.contexts(false);
+        assert_eq!(
+            keymap.press(&backspace, empty, &contexts),
+            KeyPress::Unbound
+        );
+        let front = keymap.press(&char_key("}"), ModifiersState::ALT | SHIFT, &contexts);
+        assert_eq!(front, KeyPress::Run(Command::BringToFront));
+    }
+
+    #[test]
+    fn navigation_keys_can_be_rebound() {
+        let registry = CommandRegistry::default();
+        let keymap = Keymap::new(&registry);
+        let empty = ModifiersState::empty();
+        let left = Key::Named(NamedKey::ArrowLeft);
+        let pan_left = KeyPress::Run(Command::Pan(PanDirection::Left));
+
+        let contexts = keymap.contexts(false);
+        assert_eq!(keymap.press(&left, empty, &contexts), pan_left);
+        assert_eq!(keymap.press(&char_key("a"), empty, &contexts), pan_left);
+        let plus = keymap.press(&char_key("+"), SHIFT, &contexts);
+        assert_eq!(plus, KeyPress::Run(Command::ZoomIn));
+
+        // Focused code keeps them for moving and typing
+        keymap.set_content_contexts(vec![KeyContext::Code, KeyContext::Panel]);
+        let contexts = keymap.contexts(false);
+        assert_eq!(keymap.press(&left, empty, &contexts), KeyPress::Unbound);
+        let w = keymap.press(&char_key("w"), empty, &contexts);
+        assert_eq!(w, KeyPress::Unbound);
+
+        keymap.set_content_contexts(Vec::new());
+        let contexts = keymap.contexts(false);
+        keymap.bind(KeyContext::Canvas, "a", "", &registry).unwrap();
+        keymap
+            .bind(KeyContext::Canvas, "left", "zoom-out", &registry)
+            .unwrap();
+        let a = keymap.press(&char_key("a"), empty, &contexts);
+        assert_eq!(a, KeyPress::Unbound);
+        let zoom_out = KeyPress::Run(Command::ZoomOut);
+        assert_eq!(keymap.press(&left, empty, &contexts), zoom_out);
+    }
+
+    #[test]
+    fn pan_and_drag_need_different_buttons() {
+        let keymap = Keymap::default();
+        let entries: toml::Table = "pan = \"right\"\ndrag = \"right\"".parse().unwrap();
+        assert!(matches!(
+            keymap.bind_mouse(&entries),
+            Err(KeymapError::SameButton)
+        ));
+        assert_eq!(keymap.mouse(), MouseBindings::default());
+
+        let entries: toml::Table = "pan = \"right\"\ndrag = \"middle\"".parse().unwrap();
+        keymap.bind_mouse(&entries).unwrap();
+        let mouse = keymap.mouse();
+        assert_eq!(mouse.pan, PointerButton::Secondary);
+        assert_eq!(mouse.drag, PointerButton::Auxiliary);
+    }
+}
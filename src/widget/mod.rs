@@ -1,7 +1,26 @@
+pub mod brackets;
 pub mod canvas;
 pub mod code;
 pub mod colors;
+pub mod commands;
+pub mod edges;
+pub mod file_tree;
+pub mod folding;
+pub mod fuzzy;
+pub mod graph_layout;
+pub mod groups;
+pub mod history;
+pub mod index;
+pub mod keymap;
+pub mod palette;
 pub mod panels;
+pub mod placement;
+pub mod settings;
+pub mod snapping;
+pub mod symbols;
+pub mod tour;
+pub mod vim;
+pub mod workspace;
 
 pub use canvas::Canvas;
 pub use code::*;
@@ -0,0 +1,526 @@
+// A list of things to pick from by typing, floating over the canvas
+
+use std::{path::Path, sync::Arc, task::Poll};
+
+use masonry::{
+    paint_scene_helpers::{fill_color, stroke},
+    parley::{
+        style::{FontStack, FontWeight, StyleProperty},
+        FontContext, LayoutContext,
+    },
+    text::{TextBrush, TextLayout},
+    vello::{peniko::BlendMode, Scene},
+    Affine, Point, Rect, Size,
+};
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+use super::colors::palette;
+use super::commands::{Command, CommandRegistry};
+use super::fuzzy::fuzzy_match;
+use super::index::WorkspaceIndex;
+use super::keymap::Keymap;
+use super::symbols::SymbolLocation;
+use crate::lsp::{LanguageServer, Pending};
+
+const PALETTE_WIDTH: f64 = 560.0;
+/// Space between the top of the canvas and the palette
+const PALETTE_MARGIN: f64 = 48.0;
+const PALETTE_PADDING: f64 = 8.0;
+const QUERY_HEIGHT: f64 = 30.0;
+const ROW_HEIGHT: f64 = 24.0;
+const MAX_ROWS: usize = 12;
+const FONT_SIZE: f32 = 13.0;
+static FONT: FontStack = FontStack::Source("Source Code Pro");
+
+pub enum PaletteAction {
+    Run(Command),
+    /// Put this in the query, for a command that needs arguments
+    Complete(String),
+}
+
+pub struct PaletteItem {
+    pub label: String,
+    /// Shown on the right, like the keybinding of a command
+    pub detail: Option<String>,
+    pub action: PaletteAction,
+}
+
+impl PaletteItem {
+    pub fn new(label: impl Into<String>, action: PaletteAction) -> Self {
+        Self {
+            label: label.into(),
+            detail: None,
+            action,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// What a source of items has for a query
+pub enum Lookup {
+    /// All the items for the query
+    Done(Vec<PaletteItem>),
+    /// The items for now, with better ones on the way
+    Partial(Vec<PaletteItem>),
+    /// Keep the items shown, nothing more is coming
+    Unchanged,
+    /// Keep the items shown, better ones are still on the way
+    Waiting,
+}
+
+/// What the palette made of a key or a click
+pub enum PaletteResponse {
+    /// The palette used it and stays open
+    Handled,
+    Close,
+    /// Close and run this
+    Run(Command),
+    /// Enter on a query with arguments, to be parsed by whoever opened the palette
+    Submit(String),
+}
+
+struct Row {
+    label: TextLayout<Arc<str>>,
+    detail: TextLayout<Arc<str>>,
+}
+
+pub struct Palette {
+    prompt: &'static str,
+    query: String,
+    items: Vec<PaletteItem>,
+    /// Only the first word of the query is matched, the rest are arguments
+    takes_arguments: bool,
+    /// Looks up the items for each query, instead of having them all up front
+    source: Option<Box<dyn FnMut(&str) -> Lookup>>,
+    /// The source has better items on the way, see [`Palette::poll`]
+    pending: bool,
+    /// Indices into `items` matching the query, best first, with the matched bytes of the labels
+    matches: Vec<(usize, Vec<usize>)>,
+    selected: usize,
+    /// The first match shown
+    scroll: usize,
+    query_layout: TextLayout<Arc<str>>,
+    rows: Vec<Row>,
+    dirty: bool,
+}
+
+impl Palette {
+    pub fn new(prompt: &'static str, items: Vec<PaletteItem>) -> Self {
+        let mut query_layout = TextLayout::new("".into(), FONT_SIZE);
+        query_layout.set_font(FONT);
+
+        let mut palette = Self {
+            prompt,
+            query: String::new(),
+            items,
+            takes_arguments: false,
+            source: None,
+            pending: false,
+            matches: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            query_layout,
+            rows: Vec::new(),
+            dirty: true,
+        };
+        palette.refilter();
+        palette
+    }
+
+    /// Every registered command, with its keybinding
+    pub fn commands(registry: &CommandRegistry, keymap: &Keymap) -> Self {
+        let items = registry
+            .iter()
+            .map(|spec| {
+                let label = format!("{}: {}", spec.name, spec.description);
+                let action = if spec.takes_arguments() {
+                    PaletteAction::Complete(format!("{} ", spec.name))
+                } else {
+                    PaletteAction::Run(spec.build(&[]).expect("takes no arguments"))
+                };
+                let item = PaletteItem::new(label, action);
+                let keys = keymap
+                    .describe(spec.name)
+                    .or_else(|| spec.keys.map(str::to_string));
+                match (keys, spec.takes_arguments()) {
+                    (_, true) => item.with_detail(spec.usage),
+                    (Some(keys), false) => item.with_detail(keys),
+                    (None, false) => item,
+                }
+            })
+            .collect();
+        let mut palette = Self::new("Run a command", items);
+        palette.takes_arguments = true;
+        palette
+    }
+
+    /// A palette whose items come from `source` as the query changes
+    fn with_source(prompt: &'static str, source: impl FnMut(&str) -> Lookup + 'static) -> Self {
+        let mut palette = Self::new(prompt, Vec::new());
+        palette.source = Some(Box::new(source));
+        palette.refilter();
+        palette
+    }
+
+    /// Every file of the workspace, opened as a panel when picked. Empty until `index` listed
+    /// them.
+    pub fn files(index: WorkspaceIndex) -> Self {
+        let mut listed = false;
+        let source = move |_: &str| {
+            if listed {
+                return Lookup::Unchanged;
+            }
+            let Some(files) = index.files() else {
+                return Lookup::Waiting;
+            };
+            listed = true;
+            let items = files
+                .iter()
+                .map(|path| {
+                    let label = path.display().to_string();
+                    PaletteItem::new(label, PaletteAction::Run(Command::OpenFile(path.clone())))
+                })
+                .collect();
+            Lookup::Done(items)
+        };
+        Self::with_source("Go to file", source)
+    }
+
+    /// Workspace symbols from the language server if there is one, and from `index` until it
+    /// answers or if it can't
+    pub fn symbols(index: WorkspaceIndex, server: Option<LanguageServer>) -> Self {
+        let mut asked: Option<String> = None;
+        // Replacing it forgets what was asked for the previous query
+        let mut answer: Option<Pending<Vec<SymbolLocation>>> = None;
+        // The items are the server's answer to the query asked
+        let mut answered = false;
+        let mut shows_index = false;
+        let source = move |query: &str| {
+            if asked.as_deref() != Some(query) {
+                asked = Some(query.to_string());
+                answered = false;
+                answer = server
+                    .as_ref()
+                    .and_then(|server| server.workspace_symbols(query));
+            }
+            if let Some(request) = &mut answer {
+                match request.poll() {
+                    Poll::Ready(Some(found)) => {
+                        answer = None;
+                        answered = true;
+                        shows_index = false;
+                        return Lookup::Done(symbol_items(index.root(), &found));
+                    }
+                    Poll::Ready(None) => answer = None,
+                    Poll::Pending => {}
+                }
+            }
+            if answered {
+                return Lookup::Unchanged;
+            }
+            let waits_for_server = answer.is_some();
+            if !shows_index {
+                if let Some(symbols) = index.symbols() {
+                    shows_index = true;
+                    let items = symbol_items(index.root(), &symbols);
+                    return if waits_for_server {
+                        Lookup::Partial(items)
+                    } else {
+                        Lookup::Done(items)
+                    };
+                }
+                return Lookup::Waiting;
+            }
+            if waits_for_server {
+                Lookup::Waiting
+            } else {
+                Lookup::Unchanged
+            }
+        };
+        Self::with_source("Go to symbol", source)
+    }
+
+    /// The part of the query that is matched against the items
+    fn pattern(&self) -> &str {
+        if self.takes_arguments {
+            self.query.split_whitespace().next().unwrap_or_default()
+        } else {
+            &self.query
+        }
+    }
+
+    fn has_arguments(&self) -> bool {
+        self.takes_arguments && self.query.trim_start().contains(char::is_whitespace)
+    }
+
+    /// Ask the source for the items of the query, returning whether they changed
+    fn look_up(&mut self) -> bool {
+        let Some(source) = &mut self.source else {
+            return false;
+        };
+        let (items, pending) = match source(self.query.trim()) {
+            Lookup::Done(items) => (Some(items), false),
+            Lookup::Partial(items) => (Some(items), true),
+            Lookup::Unchanged => (None, false),
+            Lookup::Waiting => (None, true),
+        };
+        self.pending = pending;
+        let changed = items.is_some();
+        if let Some(items) = items {
+            self.items = items;
+        }
+        changed
+    }
+
+    /// Whether the source has better items on the way, which [`Palette::poll`] picks up
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Pick up the items the source was waiting for, returning whether they changed
+    pub fn poll(&mut self) -> bool {
+        if !self.pending || !self.look_up() {
+            return false;
+        }
+        self.rematch();
+        true
+    }
+
+    fn refilter(&mut self) {
+        self.look_up();
+        self.rematch();
+    }
+
+    fn rematch(&mut self) {
+        let pattern = self.pattern();
+        let mut matches: Vec<(i64, usize, Vec<usize>)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| {
+                let (score, matched) = fuzzy_match(pattern, &item.label)?;
+                Some((score, i, matched))
+            })
+            .collect();
+        // Best first, in the original order among equals
+        matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        self.matches = matches.into_iter().map(|(_, i, m)| (i, m)).collect();
+        self.selected = 0;
+        self.scroll = 0;
+        self.dirty = true;
+    }
+
+    fn set_query(&mut self, query: String) {
+        self.query = query;
+        self.refilter();
+    }
+
+    fn select(&mut self, selected: usize) {
+        self.selected = selected.min(self.matches.len().saturating_sub(1));
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + MAX_ROWS {
+            self.scroll = self.selected + 1 - MAX_ROWS;
+        }
+        self.dirty = true;
+    }
+
+    /// Run or complete the selected item
+    fn choose(&mut self) -> PaletteResponse {
+        if self.has_arguments() {
+            return PaletteResponse::Submit(self.query.trim().to_string());
+        }
+        let Some(index) = self.matches.get(self.selected).map(|(index, _)| *index) else {
+            return PaletteResponse::Submit(self.query.trim().to_string());
+        };
+        match &self.items[index].action {
+            PaletteAction::Run(command) => PaletteResponse::Run(command.clone()),
+            PaletteAction::Complete(text) => {
+                let text = text.clone();
+                self.set_query(text);
+                PaletteResponse::Handled
+            }
+        }
+    }
+
+    /// What Tab completes the query to
+    fn completion(&self) -> Option<String> {
+        let (index, _) = self.matches.get(self.selected)?;
+        match &self.items[*index].action {
+            PaletteAction::Complete(text) => Some(text.clone()),
+            PaletteAction::Run(_) => None,
+        }
+    }
+
+    pub fn key(&mut self, key: &Key, mods: ModifiersState) -> PaletteResponse {
+        match key {
+            Key::Named(NamedKey::Escape) => return PaletteResponse::Close,
+            Key::Named(NamedKey::Enter) => return self.choose(),
+            Key::Named(NamedKey::ArrowDown) => self.select(self.selected + 1),
+            Key::Named(NamedKey::ArrowUp) => self.select(self.selected.saturating_sub(1)),
+            Key::Named(NamedKey::PageDown) => self.select(self.selected + MAX_ROWS),
+            Key::Named(NamedKey::PageUp) => self.select(self.selected.saturating_sub(MAX_ROWS)),
+            Key::Named(NamedKey::Tab) => {
+                if let Some(completion) = self.completion() {
+                    self.set_query(completion);
+                }
+            }
+            Key::Named(NamedKey::Backspace) => {
+                let mut query = std::mem::take(&mut self.query);
+                query.pop();
+                self.set_query(query);
+            }
+            Key::Named(NamedKey::Space) => self.set_query(format!("{} ", self.query)),
+            Key::Character(c) if !mods.control_key() && !mods.alt_key() => {
+                self.set_query(format!("{}{c}", self.query));
+            }
+            _ => {}
+        }
+        PaletteResponse::Handled
+    }
+
+    /// Where the palette is on a canvas of `size`, in screen space
+    pub fn rect(&self, size: Size) -> Rect {
+        let width = PALETTE_WIDTH
+            .min(size.width - 2.0 * PALETTE_PADDING)
+            .max(0.0);
+        let rows = (self.matches.len() - self.scroll).min(MAX_ROWS);
+        let height = QUERY_HEIGHT + rows as f64 * ROW_HEIGHT + 2.0 * PALETTE_PADDING;
+        let origin = Point::new((size.width - width) / 2.0, PALETTE_MARGIN);
+        Rect::from_origin_size(origin, Size::new(width, height))
+    }
+
+    fn row_rect(&self, size: Size, row: usize) -> Rect {
+        let rect = self.rect(size).inset(-PALETTE_PADDING);
+        let y = rect.y0 + QUERY_HEIGHT + row as f64 * ROW_HEIGHT;
+        Rect::new(rect.x0, y, rect.x1, y + ROW_HEIGHT)
+    }
+
+    /// A click at `position` in screen space, on a canvas of `size`
+    pub fn click(&mut self, position: Point, size: Size) -> PaletteResponse {
+        if !self.rect(size).contains(position) {
+            return PaletteResponse::Close;
+        }
+        let rows = (self.matches.len() - self.scroll).min(MAX_ROWS);
+        match (0..rows).find(|&row| self.row_rect(size, row).contains(position)) {
+            Some(row) => {
+                self.select(self.scroll + row);
+                self.choose()
+            }
+            None => PaletteResponse::Handled,
+        }
+    }
+
+    pub fn rebuild(
+        &mut self,
+        font_ctx: &mut FontContext,
+        layout_ctx: &mut LayoutContext<TextBrush>,
+    ) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        let colors = palette();
+
+        let (query, color) = if self.query.is_empty() {
+            (self.prompt.to_string(), colors["vscGray"])
+        } else {
+            (self.query.clone(), colors["vscFront"])
+        };
+        self.query_layout.set_text(query.into());
+        self.query_layout.set_brush(color);
+        self.query_layout.rebuild(font_ctx, layout_ctx);
+
+        self.rows = self
+            .matches
+            .iter()
+            .skip(self.scroll)
+            .take(MAX_ROWS)
+            .map(|(index, matched)| {
+                let item = &self.items[*index];
+                let mut label = TextLayout::new(item.label.as_str().into(), FONT_SIZE);
+                label.set_font(FONT);
+                label.set_brush(colors["vscPopupFront"]);
+                label.rebuild_with_attributes(font_ctx, layout_ctx, |mut builder| {
+                    for &offset in matched {
+                        let len = item.label[offset..]
+                            .chars()
+                            .next()
+                            .map_or(1, char::len_utf8);
+                        let range = offset..offset + len;
+                        builder.push(
+                            &StyleProperty::Brush(colors["vscAccentBlue"].into()),
+                            range.clone(),
+                        );
+                        builder.push(&StyleProperty::FontWeight(FontWeight::BOLD), range);
+                    }
+                    builder
+                });
+
+                let detail = item.detail.clone().unwrap_or_default();
+                let mut detail = TextLayout::new(detail.into(), FONT_SIZE);
+                detail.set_font(FONT);
+                detail.set_brush(colors["vscGray"]);
+                detail.rebuild(font_ctx, layout_ctx);
+                Row { label, detail }
+            })
+            .collect();
+    }
+
+    /// Paint in screen space over a canvas of `size`
+    pub fn paint(&mut self, scene: &mut Scene, size: Size) {
+        let colors = palette();
+        let rect = self.rect(size);
+        let rounded = rect.to_rounded_rect(6.0);
+        fill_color(scene, &rounded, colors["vscPopupBack"]);
+        stroke(scene, &rounded, colors["vscSplitDark"], 1.0);
+
+        let inner = rect.inset(-PALETTE_PADDING);
+        let query_box = Rect::new(inner.x0, inner.y0, inner.x1, inner.y0 + QUERY_HEIGHT - 4.0);
+        fill_color(scene, &query_box.to_rounded_rect(3.0), colors["vscBack"]);
+        stroke(
+            scene,
+            &query_box.to_rounded_rect(3.0),
+            colors["vscAccentBlue"],
+            1.0,
+        );
+        let text_y = query_box.y0 + (query_box.height() - self.query_layout.size().height) / 2.0;
+        self.query_layout
+            .draw(scene, Point::new(query_box.x0 + PALETTE_PADDING, text_y));
+
+        scene.push_layer(BlendMode::default(), 1.0, Affine::IDENTITY, &inner);
+        for row in 0..self.rows.len() {
+            let row_rect = self.row_rect(size, row);
+            if self.scroll + row == self.selected {
+                fill_color(scene, &row_rect, colors["vscPopupHighlightBlue"]);
+            }
+            let Row { label, detail } = &mut self.rows[row];
+            let text_y = row_rect.y0 + (ROW_HEIGHT - label.size().height) / 2.0;
+            label.draw(scene, Point::new(row_rect.x0 + PALETTE_PADDING, text_y));
+            let detail_x = row_rect.x1 - PALETTE_PADDING - detail.size().width;
+            detail.draw(scene, Point::new(detail_x, text_y));
+        }
+        scene.pop_layer();
+    }
+}
+
+/// Items opening `symbols`, with paths shown relative to `root`
+fn symbol_items(root: &Path, symbols: &[SymbolLocation]) -> Vec<PaletteItem> {
+    symbols
+        .iter()
+        .map(|symbol| {
+            let path = symbol.path.strip_prefix(root).unwrap_or(&symbol.path);
+            let detail = format!("{}:{}", path.display(), symbol.line + 1);
+            let command = Command::OpenSymbol {
+                path: path.to_path_buf(),
+                symbol: symbol.name.clone(),
+                line: Some(symbol.line),
+            };
+            PaletteItem::new(symbol.name.clone(), PaletteAction::Run(command)).with_detail(detail)
+        })
+        .collect()
+}
@@ -1,73 +1,1901 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
 use accesskit::Role;
 use masonry::{
+    kurbo::Circle,
     paint_scene_helpers::{fill_color, stroke},
+    parley::style::FontStack,
+    text::TextLayout,
     vello::{peniko::BlendMode, Scene},
     widget::*,
-    AccessCtx, AccessEvent, Affine, BoxConstraints, Color, EventCtx, LayoutCtx, LifeCycle,
-    LifeCycleCtx, PaintCtx, Point, PointerEvent, Rect, Size, StatusChange, TextEvent, Widget,
-    WidgetId,
+    AccessCtx, AccessEvent, Affine, BoxConstraints, Color, CursorIcon, EventCtx, LayoutCtx,
+    LifeCycle, LifeCycleCtx, PaintCtx, Point, PointerEvent, PointerState, Rect, Size, StatusChange,
+    TextEvent, Vec2, Widget, WidgetId,
 };
 use smallvec::SmallVec;
 use tracing::{trace_span, Span};
-use winit::dpi::LogicalPosition;
+use winit::{
+    dpi::{LogicalPosition, PhysicalPosition},
+    event::ElementState,
+    keyboard::{Key, ModifiersState, NamedKey},
+};
+
+use super::canvas::{with_pointer_state, CanvasView, ScrollDelta};
+use super::code::{CodeBlock, SourceLocation};
+use super::colors::palette;
+use super::commands::{Command, Commands};
+use super::edges::{self, Edge, EdgeKind};
+use super::file_tree::FileTree;
+use super::graph_layout::GraphLayout;
+use super::groups::Group;
+use super::history::{self, History, Step};
+use super::keymap::{KeyContext, Keymap};
+use super::placement::OverlapAvoidance;
+use super::settings::Settings;
+use super::snapping;
+use super::symbols;
+use super::workspace::{GroupState, PanelKey, PanelState, Workspace};
+use crate::lsp::LanguageServer;
+
+const ZOOM_SENSITIVITY: f64 = 0.05;
+
+/// How far outside a panel its resize handles reach
+const HANDLE_SIZE: f64 = 12.0;
+const MIN_PANEL_SIZE: Size = Size::new(60.0, 40.0);
+const SCROLLBAR_WIDTH: f64 = 4.0;
+/// How far one wheel line scrolls the content of a panel
+const SCROLL_LINE_HEIGHT: f64 = 40.0;
+
+/// How long panels take to move to their arranged positions, in seconds
+const ARRANGE_DURATION: f64 = 0.5;
+
+/// How close dragged panels get to lining up before they snap, in screen pixels
+const SNAP_DISTANCE: f64 = 6.0;
+
+/// Roughly how much room a dropped file or directory takes, for the preview while hovering
+const DROP_PREVIEW_SIZE: Size = Size::new(480.0, 320.0);
+
+const TITLE_BAR_HEIGHT: f64 = 24.0;
+const TITLE_FONT_SIZE: f32 = 12.0;
+const TITLE_PADDING: f64 = 6.0;
+static TITLE_FONT: FontStack = FontStack::Source("Source Code Pro");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TitleButton {
+    Close,
+    Collapse,
+    Pin,
+}
+
+/// Background colors panels can be recolored with, by palette name
+const PANEL_COLORS: [&str; 6] = [
+    "vscBack",
+    "vscDarkBlue",
+    "vscDiffGreenDark",
+    "vscDiffRedDark",
+    "vscFoldBackground",
+    "vscLeftMid",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// Line up the horizontal centers
+    CenterHorizontal,
+    /// Line up the vertical centers
+    CenterVertical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// Title bar buttons, from right to left
+const TITLE_BUTTONS: [TitleButton; 3] =
+    [TitleButton::Close, TitleButton::Collapse, TitleButton::Pin];
+
+pub struct Child {
+    /// Top left of the content, below the title bar. In screen space when pinned
+    pub position: Point,
+    /// The visible size of the content
+    pub size: Size,
+    /// The size the user resized this panel to, if any
+    pub user_size: Option<Size>,
+    /// The size of the widget, which can be larger than the panel when resized
+    pub content_size: Size,
+    /// How far the content is scrolled inside the panel
+    pub scroll: Vec2,
+    pub widget: WidgetPod<Box<dyn Widget>>,
+    pub background_color: Color,
+    pub path: Option<PathBuf>,
+    pub symbol: Option<String>,
+    /// The content is a file rather than a directory, found once when the path is set
+    shows_code: bool,
+    /// Only the title bar is shown
+    pub collapsed: bool,
+    /// The panel stays put on screen regardless of the canvas transform
+    pub pinned: bool,
+    title_layout: TextLayout<Arc<str>>,
+}
+
+impl Child {
+    pub fn new(position: Point, widget: impl Widget + 'static) -> Self {
+        let mut title_layout = TextLayout::new("".into(), TITLE_FONT_SIZE);
+        title_layout.set_font(TITLE_FONT);
+        title_layout.set_brush(palette()["vscPopupFront"]);
+
+        let mut child = Self {
+            position,
+            widget: WidgetPod::new(widget).boxed(),
+            background_color: Color::parse("#1F1F1F").unwrap(),
+            size: Size::ZERO,
+            user_size: None,
+            content_size: Size::ZERO,
+            scroll: Vec2::ZERO,
+            path: None,
+            symbol: None,
+            shows_code: false,
+            collapsed: false,
+            pinned: false,
+            title_layout,
+        };
+        child.update_title();
+        child
+    }
+
+    pub fn with_size(mut self, size: Size) -> Self {
+        self.user_size = Some(size);
+        self
+    }
+
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        self.shows_code = !path.is_dir();
+        self.path = Some(path);
+        self.update_title();
+        self
+    }
+
+    pub fn with_symbol(mut self, symbol: impl Into<String>) -> Self {
+        self.symbol = Some(symbol.into());
+        self.update_title();
+        self
+    }
+
+    pub fn title(&self) -> String {
+        let path = self.path.as_ref().map(|p| p.display().to_string());
+        match (path, &self.symbol) {
+            (Some(path), Some(symbol)) => format!("{path} — {symbol}"),
+            (Some(path), None) => path,
+            (None, Some(symbol)) => symbol.clone(),
+            (None, None) => "untitled".to_string(),
+        }
+    }
+
+    /// What a saved workspace knows this panel by, if it shows a file
+    fn key(&self) -> Option<PanelKey> {
+        Some(PanelKey {
+            path: self.path.clone()?,
+            symbol: self.symbol.clone(),
+        })
+    }
+
+    fn update_title(&mut self) {
+        self.title_layout.set_text(self.title().into());
+    }
+
+    fn overlap(&self, position: Point) -> bool {
+        self.frame().contains(position)
+    }
+
+    fn to_local_space(&self, position: Point) -> Point {
+        let local_space = position - self.position;
+        Point::new(local_space.x, local_space.y)
+    }
+
+    /// The content area, empty when collapsed
+    fn rect(&self) -> Rect {
+        let height = if self.collapsed {
+            0.0
+        } else {
+            self.size.height
+        };
+        Rect::from_origin_size(self.position, Size::new(self.size.width, height))
+    }
+
+    fn title_bar(&self) -> Rect {
+        Rect::new(
+            self.position.x,
+            self.position.y - TITLE_BAR_HEIGHT,
+            self.position.x + self.size.width,
+            self.position.y,
+        )
+    }
+
+    /// The title bar and content together
+    fn frame(&self) -> Rect {
+        self.title_bar().union(self.rect())
+    }
+
+    fn set_frame(&mut self, frame: Rect) {
+        self.position = Point::new(frame.x0, frame.y0 + TITLE_BAR_HEIGHT);
+        self.user_size = Some(Size::new(frame.width(), frame.height() - TITLE_BAR_HEIGHT));
+    }
+
+    fn button_rect(&self, button: TitleButton) -> Rect {
+        let title_bar = self.title_bar();
+        let index = TITLE_BUTTONS.iter().position(|b| *b == button).unwrap() as f64;
+        let x1 = title_bar.x1 - index * TITLE_BAR_HEIGHT;
+        Rect::new(x1 - TITLE_BAR_HEIGHT, title_bar.y0, x1, title_bar.y1)
+    }
+
+    fn button_at(&self, position: Point) -> Option<TitleButton> {
+        TITLE_BUTTONS
+            .into_iter()
+            .find(|b| self.button_rect(*b).contains(position))
+    }
+
+    fn max_scroll(&self) -> Vec2 {
+        Vec2::new(
+            (self.content_size.width - self.size.width).max(0.0),
+            (self.content_size.height - self.size.height).max(0.0),
+        )
+    }
+
+    fn overflows(&self) -> bool {
+        self.max_scroll() != Vec2::ZERO
+    }
+
+    fn scroll_by(&mut self, delta: Vec2) {
+        let max = self.max_scroll();
+        self.scroll = Vec2::new(
+            (self.scroll.x + delta.x).clamp(0.0, max.x),
+            (self.scroll.y + delta.y).clamp(0.0, max.y),
+        );
+    }
+
+    /// Which edges of the panel `position` grabs, if it is on a resize handle
+    fn resize_handle(&self, position: Point) -> Option<ResizeEdges> {
+        if self.collapsed {
+            return None;
+        }
+        let frame = self.frame();
+        if !frame.inflate(HANDLE_SIZE, HANDLE_SIZE).contains(position) || frame.contains(position) {
+            return None;
+        }
+        Some(ResizeEdges {
+            left: position.x < frame.x0,
+            right: position.x > frame.x1,
+            top: position.y < frame.y0,
+            bottom: position.y > frame.y1,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ResizeEdges {
+    left: bool,
+    right: bool,
+    top: bool,
+    bottom: bool,
+}
+
+impl ResizeEdges {
+    fn cursor(&self) -> CursorIcon {
+        match (self.left || self.right, self.top || self.bottom) {
+            (true, false) => CursorIcon::EwResize,
+            (false, true) => CursorIcon::NsResize,
+            _ if (self.left && self.top) || (self.right && self.bottom) => CursorIcon::NwseResize,
+            _ => CursorIcon::NeswResize,
+        }
+    }
+
+    /// Apply a pointer movement of `delta` to the `start` frame of a panel
+    fn resize(&self, start: Rect, delta: Vec2) -> Rect {
+        let min_height = MIN_PANEL_SIZE.height + TITLE_BAR_HEIGHT;
+        let mut rect = start;
+        if self.left {
+            rect.x0 = (start.x0 + delta.x).min(start.x1 - MIN_PANEL_SIZE.width);
+        }
+        if self.right {
+            rect.x1 = (start.x1 + delta.x).max(start.x0 + MIN_PANEL_SIZE.width);
+        }
+        if self.top {
+            rect.y0 = (start.y0 + delta.y).min(start.y1 - min_height);
+        }
+        if self.bottom {
+            rect.y1 = (start.y1 + delta.y).max(start.y0 + min_height);
+        }
+        rect
+    }
+}
+
+enum DraggingState {
+    NotDragging,
+    Dragging {
+        offset: Point,
+        child: usize,
+    },
+    Resizing {
+        edges: ResizeEdges,
+        start_rect: Rect,
+        start_position: Point,
+        child: usize,
+    },
+    /// Rubber-band selection in world space
+    Selecting {
+        start: Point,
+        current: Point,
+        /// What was selected before, kept when extending the selection with shift
+        base: BTreeSet<usize>,
+    },
+    /// Dragging out a manual edge from a panel, with the pointer in world space
+    Linking {
+        from: usize,
+        current: Point,
+    },
+    /// Drawing the frame of a new group in world space
+    Framing {
+        start: Point,
+        current: Point,
+    },
+    /// Moving a group and all its members
+    DraggingGroup {
+        group: usize,
+        previous: Point,
+    },
+}
+
+/// Panels gliding to the positions computed by a [`GraphLayout`]
+struct ArrangeAnimation {
+    /// Child index, start position and end position
+    moves: Vec<(usize, Point, Point)>,
+    elapsed: f64,
+}
+
+/// The parts of a child that layout operations change
+#[derive(Clone, PartialEq)]
+struct ChildState {
+    position: Point,
+    user_size: Option<Size>,
+    scroll: Vec2,
+    collapsed: bool,
+    pinned: bool,
+    background_color: Color,
+}
+
+/// Everything about the layout that can be undone, apart from which children there are
+#[derive(Clone, PartialEq)]
+struct LayoutSnapshot {
+    children: Vec<ChildState>,
+    z_order: Vec<usize>,
+    edges: Vec<(usize, usize, EdgeKind, Option<Arc<str>>)>,
+    /// Name, members and whether it is collapsed
+    groups: Vec<(String, BTreeSet<usize>, bool)>,
+}
+
+/// What reverts an edit of the panel
+enum PanelEdit {
+    /// The layout before the edit
+    Layout(LayoutSnapshot),
+    /// A child that was removed from `index`, and the layout before
+    Removed {
+        index: usize,
+        child: Child,
+        layout: LayoutSnapshot,
+    },
+    /// A child that was added at `index`, and the layout before
+    Added {
+        index: usize,
+        layout: LayoutSnapshot,
+    },
+    /// Several edits, in the order they were made
+    Batch(Vec<PanelEdit>),
+}
+
+pub struct Panel {
+    pub children: Vec<Child>,
+    pub edges: Vec<Edge>,
+    pub groups: Vec<Group>,
+    /// The group last clicked, which keyboard commands for groups act on
+    active_group: Option<usize>,
+    /// Whether typing renames the active group
+    renaming_group: bool,
+    /// The whole name is selected when renaming starts, so typing replaces it
+    group_name_selected: bool,
+    /// Where the layout of the panels and the groups is saved
+    workspace_path: Option<PathBuf>,
+    history: History<PanelEdit>,
+    /// Commands waiting to run, submitted by file trees and the driver
+    commands: Commands,
+    /// Shared with the canvas, which resolves the keys and hands us the commands
+    keymap: Keymap,
+    language_server: Option<LanguageServer>,
+    settings: Settings,
+    /// The file being dragged over the panel from outside the app, and where it would land in
+    /// world space, sized like what it would open as
+    drop_preview: Option<(PathBuf, Rect)>,
+    /// The layout when the current drag started, recorded for undo once it ends
+    edit_start: Option<LayoutSnapshot>,
+    /// Indices into `children`, from the bottom of the stack to the top
+    z_order: Vec<usize>,
+    dragging_state: DraggingState,
+    focused: Option<usize>,
+    /// The child under the pointer, which receives pointer events
+    hovered: Option<usize>,
+    /// The child a button was pressed on, which keeps receiving pointer events until release
+    captured: Option<usize>,
+    selection: BTreeSet<usize>,
+    arrange_animation: Option<ArrangeAnimation>,
+    overlap_avoidance: OverlapAvoidance,
+    /// Alignment guides of the panel being dragged, in world space
+    guides: Vec<masonry::kurbo::Line>,
+    /// Children added since the last layout, placed once their size is known
+    pending_placement: Vec<usize>,
+    /// Children opened in the middle of the view, centered once their size is known
+    pending_center: Vec<usize>,
+    /// Where the layout origin of this widget is in world space. The panel grows in every
+    /// direction to fit its children, so this can be negative.
+    origin: Point,
+    view: CanvasView,
+}
+
+impl Panel {
+    pub fn new(children: Vec<Child>) -> Self {
+        // Earlier children start on top
+        let z_order = (0..children.len()).rev().collect();
+        Self {
+            children,
+            edges: Vec::new(),
+            groups: Vec::new(),
+            active_group: None,
+            renaming_group: false,
+            group_name_selected: false,
+            workspace_path: None,
+            history: History::new(Default::default()),
+            commands: Commands::default(),
+            keymap: Keymap::default(),
+            language_server: None,
+            settings: Settings::default(),
+            drop_preview: None,
+            edit_start: None,
+            z_order,
+            dragging_state: DraggingState::NotDragging,
+            focused: None,
+            hovered: None,
+            captured: None,
+            selection: BTreeSet::new(),
+            arrange_animation: None,
+            overlap_avoidance: OverlapAvoidance::Nudge,
+            guides: Vec::new(),
+            pending_placement: Vec::new(),
+            pending_center: Vec::new(),
+            origin: Point::ORIGIN,
+            view: CanvasView::default(),
+        }
+    }
+
+    /// Children from bottom to top as they are painted. Pinned panels are always above the rest,
+    /// and members of collapsed groups are left out
+    fn stacking(&self) -> impl DoubleEndedIterator<Item = usize> + '_ {
+        let visible = self.z_order.iter().filter(|&&i| !self.is_hidden(i));
+        let unpinned = visible.clone().filter(|&&i| !self.children[i].pinned);
+        let pinned = visible.filter(|&&i| self.children[i].pinned);
+        unpinned.chain(pinned).copied()
+    }
+
+    /// Move `index` to position `depth` in the stack, where 0 is the bottom
+    fn restack(&mut self, index: usize, depth: usize) {
+        let Some(current) = self.z_order.iter().position(|&i| i == index) else {
+            return;
+        };
+        self.z_order.remove(current);
+        let depth = depth.min(self.z_order.len());
+        self.z_order.insert(depth, index);
+    }
+
+    fn depth(&self, index: usize) -> usize {
+        self.z_order.iter().position(|&i| i == index).unwrap_or(0)
+    }
+
+    pub fn bring_to_front(&mut self, index: usize) {
+        self.restack(index, self.z_order.len());
+    }
+
+    pub fn send_to_back(&mut self, index: usize) {
+        self.restack(index, 0);
+    }
+
+    /// Move one step up the stack
+    pub fn raise(&mut self, index: usize) {
+        self.restack(index, self.depth(index) + 1);
+    }
+
+    /// Move one step down the stack
+    pub fn lower(&mut self, index: usize) {
+        self.restack(index, self.depth(index).saturating_sub(1));
+    }
+
+    pub fn with_overlap_avoidance(mut self, overlap_avoidance: OverlapAvoidance) -> Self {
+        self.overlap_avoidance = overlap_avoidance;
+        self
+    }
+
+    /// Add a child on top of the others. It gets moved out of the way of other panels on the next
+    /// layout, depending on the overlap avoidance.
+    ///
+    /// Callers need to tell masonry the children changed.
+    pub fn add_child(&mut self, child: Child) -> usize {
+        let layout = self.snapshot();
+        self.history.record(PanelEdit::Added {
+            index: self.children.len(),
+            layout,
+        });
+        self.children.push(child);
+        let index = self.children.len() - 1;
+        self.z_order.push(index);
+        self.pending_placement.push(index);
+        index
+    }
+
+    /// Open a file as a code panel, or a directory as a file tree, with its top left at
+    /// `position` in world space
+    pub fn open_path(&mut self, ctx: &mut EventCtx, path: &Path, position: Point) -> Option<usize> {
+        let position = position + Vec2::new(0.0, TITLE_BAR_HEIGHT);
+        let child = if path.is_dir() {
+            Child::new(position, FileTree::new(path, self.commands.clone()))
+        } else {
+            match std::fs::read_to_string(path) {
+                Ok(contents) => Child::new(
+                    position,
+                    self.code_block(contents, SourceLocation::file(path)),
+                ),
+                Err(err) => {
+                    tracing::warn!("Couldn't open {}: {err}", path.display());
+                    return None;
+                }
+            }
+        };
+
+        let index = self.add_child(child.with_path(path));
+        ctx.children_changed();
+        ctx.request_layout();
+        ctx.request_paint();
+        Some(index)
+    }
+
+    /// Connect panels, dropping edges to panels that don't exist
+    pub fn with_edges(mut self, edges: Vec<Edge>) -> Self {
+        for edge in edges {
+            self.add_edge(edge);
+        }
+        self
+    }
+
+    pub fn add_edge(&mut self, edge: Edge) {
+        let count = self.children.len();
+        if edge.from >= count || edge.to >= count {
+            tracing::warn!(
+                "Dropping the edge from panel {} to {}, there are only {count} panels",
+                edge.from,
+                edge.to
+            );
+            return;
+        }
+        self.edges.push(edge);
+    }
+
+    /// Connect two panels with a manual edge, or remove the manual edge already between them
+    fn toggle_manual_edge(&mut self, from: usize, to: usize) {
+        let existing = self
+            .edges
+            .iter()
+            .position(|e| e.kind == EdgeKind::Manual && e.connects(from, to));
+        match existing {
+            Some(i) => {
+                self.edges.remove(i);
+            }
+            None => self.add_edge(Edge::new(from, to, EdgeKind::Manual)),
+        }
+    }
+
+    /// Share the transform of the canvas this panel is in, needed for pinned panels
+    pub fn with_view(mut self, view: CanvasView) -> Self {
+        self.history = History::new(view.clock());
+        self.view = view;
+        self
+    }
+
+    /// Convert a point in world space into the space `child` is positioned in
+    fn to_child_space(&self, child: &Child, position: Point) -> Point {
+        if child.pinned {
+            self.view.world_to_screen(position)
+        } else {
+            position
+        }
+    }
+
+    /// The frame of a child in world space
+    fn world_frame(&self, child: &Child) -> Rect {
+        if child.pinned {
+            self.view
+                .transform()
+                .inverse()
+                .transform_rect_bbox(child.frame())
+        } else {
+            child.frame()
+        }
+    }
+
+    /// The topmost child under `position`
+    fn child_at(&self, position: Point) -> Option<usize> {
+        self.stacking().rev().find(|&i| {
+            let child = &self.children[i];
+            child.overlap(self.to_child_space(child, position))
+        })
+    }
+
+    fn resize_handle_at(&self, position: Point) -> Option<(usize, ResizeEdges)> {
+        self.stacking().rev().find_map(|i| {
+            let child = &self.children[i];
+            Some((
+                i,
+                child.resize_handle(self.to_child_space(child, position))?,
+            ))
+        })
+    }
+
+    fn close(&mut self, ctx: &mut EventCtx, index: usize) {
+        let edit = self.remove_child(index);
+        self.history.record(edit);
+        ctx.children_changed();
+        ctx.request_layout();
+        ctx.request_paint();
+    }
+
+    /// Remove a child and everything that refers to it, returning what puts it back. Callers need
+    /// to tell masonry the children changed.
+    fn remove_child(&mut self, index: usize) -> PanelEdit {
+        let layout = self.snapshot();
+        let child = self.children.remove(index);
+        self.hovered = None;
+        self.captured = None;
+        self.arrange_animation = None;
+        self.pending_placement.clear();
+        self.pending_center.clear();
+        self.selection = self
+            .selection
+            .iter()
+            .filter(|&&i| i != index)
+            .map(|&i| if i > index { i - 1 } else { i })
+            .collect();
+        self.z_order.retain(|&i| i != index);
+        for i in &mut self.z_order {
+            if *i > index {
+                *i -= 1;
+            }
+        }
+        for group in &mut self.groups {
+            group.remove_member(index);
+        }
+        self.groups.retain(|g| !g.members.is_empty());
+        self.active_group = None;
+        self.renaming_group = false;
+        self.edges.retain(|e| !e.touches(index));
+        for edge in &mut self.edges {
+            for end in [&mut edge.from, &mut edge.to] {
+                if *end > index {
+                    *end -= 1;
+                }
+            }
+        }
+        self.dragging_state = DraggingState::NotDragging;
+        self.focused = match self.focused {
+            Some(focused) if focused == index => None,
+            Some(focused) if focused > index => Some(focused - 1),
+            focused => focused,
+        };
+        PanelEdit::Removed {
+            index,
+            child,
+            layout,
+        }
+    }
+
+    fn toggle_pinned(&mut self, index: usize) {
+        let view = self.view.clone();
+        let child = &mut self.children[index];
+        child.position = if child.pinned {
+            view.screen_to_world(child.position)
+        } else {
+            view.world_to_screen(child.position)
+        };
+        child.pinned = !child.pinned;
+    }
+
+    fn press_button(&mut self, ctx: &mut EventCtx, index: usize, button: TitleButton) {
+        if button != TitleButton::Close {
+            self.record_layout();
+        }
+        match button {
+            TitleButton::Close => {
+                self.execute(ctx, Command::ClosePanel(Some(index)));
+                return;
+            }
+            TitleButton::Collapse => {
+                let child = &mut self.children[index];
+                child.collapsed = !child.collapsed;
+            }
+            TitleButton::Pin => self.toggle_pinned(index),
+        }
+        ctx.request_layout();
+        ctx.request_paint();
+    }
+
+    /// The nearest child from `from` whose center lies in `direction`
+    fn nearest_in_direction(&self, from: usize, direction: Vec2) -> Option<usize> {
+        let origin = self.world_frame(&self.children[from]).center();
+        self.children
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != from && !self.is_hidden(*i))
+            .filter_map(|(i, child)| {
+                let offset = self.world_frame(child).center() - origin;
+                let along = offset.dot(direction);
+                if along <= 0.0 {
+                    return None;
+                }
+                // Prefer panels that are straight ahead over ones that are off to the side
+                let across = offset.cross(direction).abs();
+                Some((i, along + 2.0 * across))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+    }
+
+    /// Children in reading order: top to bottom, then left to right
+    fn reading_order(&self) -> Vec<usize> {
+        let mut order: Vec<_> = (0..self.children.len())
+            .filter(|&i| !self.is_hidden(i))
+            .collect();
+        order.sort_by(|&a, &b| {
+            let a = self.world_frame(&self.children[a]).origin();
+            let b = self.world_frame(&self.children[b]).origin();
+            a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x))
+        });
+        order
+    }
+
+    /// Move focus to the next panel to the right (or below), or the previous one when `backwards`
+    fn cycle_focus(&mut self, ctx: &mut EventCtx, backwards: bool) {
+        let order = self.reading_order();
+        let (primary, secondary) = if backwards {
+            (Vec2::new(-1.0, 0.0), Vec2::new(0.0, -1.0))
+        } else {
+            (Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0))
+        };
+
+        let next = match self.focused {
+            Some(current) => self
+                .nearest_in_direction(current, primary)
+                .or_else(|| self.nearest_in_direction(current, secondary)),
+            None => None,
+        };
+        // Wrap around when there is nothing further in that direction
+        let next = next.or_else(|| {
+            if backwards {
+                order.last().copied()
+            } else {
+                order.first().copied()
+            }
+        });
+
+        self.set_focused(ctx, next);
+    }
+
+    fn set_focused(&mut self, ctx: &mut EventCtx, focused: Option<usize>) {
+        if self.focused == focused {
+            return;
+        }
+        self.focused = focused;
+        if let Some(child) = focused.map(|i| &self.children[i]) {
+            // Pinned panels are always on screen
+            if !child.pinned {
+                ctx.request_pan_to_child(child.frame() - self.origin.to_vec2());
+            }
+        }
+        ctx.request_paint();
+    }
+
+    /// Deliver a pointer event to one child, in the space that child is positioned in
+    fn send_pointer_event(&mut self, ctx: &mut EventCtx, index: usize, event: &PointerEvent) {
+        if !self.children[index].pinned {
+            self.children[index].widget.on_pointer_event(ctx, event);
+            return;
+        }
+
+        // Pinned children are laid out at their screen position instead of in world space
+        let window_origin = ctx.to_window(Point::ZERO).to_vec2();
+        let origin = self.origin.to_vec2();
+        let to_screen = |x: f64, y: f64| {
+            let world = Point::new(x, y) - window_origin + origin;
+            self.view.world_to_screen(world) + window_origin - origin
+        };
+        let state = event.pointer_state();
+        let position = to_screen(state.position.x, state.position.y);
+        let physical_position = to_screen(state.physical_position.x, state.physical_position.y);
+
+        let state = PointerState {
+            physical_position: PhysicalPosition::new(physical_position.x, physical_position.y),
+            position: LogicalPosition::new(position.x, position.y),
+            buttons: state.buttons.clone(),
+            mods: state.mods,
+            count: state.count,
+            focus: state.focus,
+        };
+        let event = with_pointer_state(event, state);
+        self.children[index].widget.on_pointer_event(ctx, &event);
+    }
+
+    /// Send `event` to the child under the pointer, or to the child holding the pointer capture
+    fn route_pointer_event(&mut self, ctx: &mut EventCtx, event: &PointerEvent) {
+        let position = self.logical_position_to_point(ctx, event.pointer_state().position);
+        let target = match event {
+            _ if self.captured.is_some() => self.captured,
+            PointerEvent::PointerLeave(_) => None,
+            _ => self.child_at(position),
+        };
+
+        if self.hovered != target {
+            if let Some(previous) = self.hovered.take() {
+                let leave = PointerEvent::PointerLeave(event.pointer_state().clone());
+                self.send_pointer_event(ctx, previous, &leave);
+            }
+            self.hovered = target;
+        }
+
+        let Some(target) = target else {
+            return;
+        };
+        if !ctx.is_handled() {
+            self.send_pointer_event(ctx, target, event);
+        }
+
+        match event {
+            PointerEvent::PointerDown(_, _) => self.captured = Some(target),
+            PointerEvent::PointerUp(_, _) => self.captured = None,
+            _ => {}
+        }
+    }
+
+    /// Where a pointer is in world space
+    fn logical_position_to_point(&self, ctx: &EventCtx, position: LogicalPosition<f64>) -> Point {
+        let position = Point::new(position.x, position.y);
+        let position = position - ctx.to_window(Point::ZERO);
+        let position = Point::new(position.x, position.y) + self.origin.to_vec2();
+        return position;
+    }
+}
+
+// Arranging
+impl Panel {
+    /// Keep the children in `placed`, which were just put down, from overlapping the others
+    fn avoid_overlaps(&mut self, placed: &[usize]) {
+        // Pinned panels live in screen space and can't collide with the rest
+        let movable: Vec<usize> = (0..self.children.len())
+            .filter(|&i| !self.children[i].pinned)
+            .collect();
+        let frames: Vec<Rect> = movable.iter().map(|&i| self.children[i].frame()).collect();
+        let is_placed: Vec<bool> = movable.iter().map(|i| placed.contains(i)).collect();
+
+        let resolved = self.overlap_avoidance.resolve(&frames, &is_placed);
+        for (&i, frame) in movable.iter().zip(resolved) {
+            self.children[i].position = frame.origin() + Vec2::new(0.0, TITLE_BAR_HEIGHT);
+        }
+    }
+
+    /// The children that move together with `child` when it is dragged
+    fn drag_group(&self, child: usize) -> Vec<usize> {
+        if self.selection.contains(&child) {
+            self.selection.iter().copied().collect()
+        } else {
+            vec![child]
+        }
+    }
+
+    /// Finish dragging, making room for the dropped panels
+    fn drop_dragged(&mut self) {
+        self.guides.clear();
+        if let DraggingState::Dragging { child, .. } = self.dragging_state {
+            let group = self.drag_group(child);
+            self.avoid_overlaps(&group);
+        }
+        self.dragging_state = DraggingState::NotDragging;
+        self.end_edit();
+    }
+
+    /// Animate every panel that isn't pinned to the positions computed by `layout`
+    pub fn arrange(&mut self, ctx: &mut EventCtx, layout: GraphLayout) {
+        self.record_layout();
+        let movable: Vec<usize> = (0..self.children.len())
+            .filter(|&i| !self.children[i].pinned)
+            .collect();
+        let local: HashMap<usize, usize> =
+            movable.iter().enumerate().map(|(l, &i)| (i, l)).collect();
+
+        let frames: Vec<Rect> = movable.iter().map(|&i| self.children[i].frame()).collect();
+        let edges: Vec<(usize, usize)> = self
+            .edges
+            .iter()
+            .filter_map(|e| Some((*local.get(&e.from)?, *local.get(&e.to)?)))
+            .collect();
+
+        let origins = layout.arrange(&frames, &edges);
+        let moves = movable
+            .iter()
+            .zip(origins)
+            .map(|(&i, origin)| {
+                let to = origin + Vec2::new(0.0, TITLE_BAR_HEIGHT);
+                (i, self.children[i].position, to)
+            })
+            .collect();
+
+        self.arrange_animation = Some(ArrangeAnimation {
+            moves,
+            elapsed: 0.0,
+        });
+        ctx.request_anim_frame();
+    }
+
+    /// Advance the arrange animation by `interval` nanoseconds, returning whether it is still running
+    fn step_arrange_animation(&mut self, interval: u64) -> bool {
+        let Some(animation) = &mut self.arrange_animation else {
+            return false;
+        };
+        animation.elapsed += interval as f64 * 1e-9;
+        let t = (animation.elapsed / ARRANGE_DURATION).min(1.0);
+        let eased = t * t * (3.0 - 2.0 * t);
+        for &(i, from, to) in &animation.moves {
+            self.children[i].position = from.lerp(to, eased);
+        }
+
+        if t >= 1.0 {
+            self.arrange_animation = None;
+            false
+        } else {
+            true
+        }
+    }
+}
+
+// Groups
+impl Panel {
+    /// The frame of a group in world space, see [`Group::frame`]
+    fn group_frame(&self, group: &Group) -> Option<Rect> {
+        let members = group.members.iter().map(|&i| &self.children[i]);
+        group.frame(members.filter(|c| !c.pinned).map(|c| c.frame()))
+    }
+
+    /// The collapsed group `index` is hidden in, if any
+    fn collapsed_group_of(&self, index: usize) -> Option<usize> {
+        self.groups
+            .iter()
+            .position(|g| g.collapsed && g.members.contains(&index))
+    }
+
+    fn is_hidden(&self, index: usize) -> bool {
+        self.collapsed_group_of(index).is_some()
+    }
+
+    /// Where a child shows up in world space, which is its collapsed group if it is in one
+    fn visible_frame(&self, index: usize) -> Rect {
+        self.collapsed_group_of(index)
+            .and_then(|g| self.group_frame(&self.groups[g]))
+            .unwrap_or_else(|| self.world_frame(&self.children[index]))
+    }
+
+    /// The topmost group whose frame is under `position`. Collapsed groups are above the rest
+    fn group_at(&self, position: Point) -> Option<usize> {
+        let contains = |i: &usize| {
+            self.group_frame(&self.groups[*i])
+                .is_some_and(|frame| frame.contains(position))
+        };
+        let collapsed = (0..self.groups.len()).filter(|&i| self.groups[i].collapsed);
+        let expanded = (0..self.groups.len()).filter(|&i| !self.groups[i].collapsed);
+        collapsed.chain(expanded.rev()).find(contains)
+    }
+
+    /// The group whose title bar is under `position`, with its frame. Collapsed groups can be
+    /// grabbed anywhere
+    fn group_handle_at(&self, position: Point) -> Option<(usize, Rect)> {
+        let group = self.group_at(position)?;
+        let frame = self.group_frame(&self.groups[group])?;
+        if self.groups[group].collapsed || Group::title_bar(frame).contains(position) {
+            Some((group, frame))
+        } else {
+            None
+        }
+    }
+
+    /// Group the panels inside `frame`, and start naming the new group
+    fn group_in_rect(&mut self, frame: Rect) {
+        let members: BTreeSet<usize> = (0..self.children.len())
+            .filter(|&i| !self.children[i].pinned && !self.is_hidden(i))
+            .filter(|&i| frame.union(self.children[i].frame()) == frame)
+            .collect();
+        if members.is_empty() {
+            return;
+        }
+        let name = format!("Group {}", self.groups.len() + 1);
+        self.groups.push(Group::new(name, members));
+        self.start_renaming(self.groups.len() - 1);
+    }
+
+    /// Make `group` active and take typing for its name, which starts out selected
+    fn start_renaming(&mut self, group: usize) {
+        self.active_group = Some(group);
+        self.renaming_group = true;
+        self.group_name_selected = true;
+    }
+
+    pub fn ungroup(&mut self, index: usize) {
+        self.groups.remove(index);
+        self.active_group = None;
+        self.renaming_group = false;
+    }
+
+    pub fn toggle_group_collapsed(&mut self, index: usize) {
+        let group = &mut self.groups[index];
+        group.collapsed = !group.collapsed;
+        group.update_title();
+
+        // Keyboard focus and the selection can't stay on panels that disappeared
+        if self.focused.is_some_and(|f| self.is_hidden(f)) {
+            self.focused = None;
+        }
+        let hidden: BTreeSet<usize> = self
+            .selection
+            .iter()
+            .copied()
+            .filter(|&i| self.is_hidden(i))
+            .collect();
+        self.selection.retain(|i| !hidden.contains(i));
+    }
+
+    fn move_group_by(&mut self, index: usize, delta: Vec2) {
+        let members: Vec<usize> = self.groups[index].members.iter().copied().collect();
+        for i in members {
+            if !self.children[i].pinned {
+                self.move_child_by(i, delta);
+            }
+        }
+    }
+
+    /// Typing while a group is being renamed, returning whether the key was used
+    fn rename_key(&mut self, key: &Key, mods: ModifiersState) -> bool {
+        let Some(group) = self.active_group.filter(|_| self.renaming_group) else {
+            return false;
+        };
+        let group = &mut self.groups[group];
+        // Typing replaces the selected name, and backspace erases all of it
+        let selected = self.group_name_selected;
+        let typed = match key {
+            Key::Named(NamedKey::Enter | NamedKey::Escape) => {
+                self.renaming_group = false;
+                ""
+            }
+            Key::Named(NamedKey::Backspace) => {
+                if selected {
+                    group.name.clear();
+                } else {
+                    group.name.pop();
+                }
+                ""
+            }
+            Key::Named(NamedKey::Space) => " ",
+            Key::Character(c) if !mods.control_key() && !mods.alt_key() => c.as_str(),
+            _ => return false,
+        };
+        if selected && !typed.is_empty() {
+            group.name.clear();
+        }
+        group.name.push_str(typed);
+        self.group_name_selected = false;
+        group.update_title();
+        true
+    }
+
+    /// Load the layout of the panels and the groups from `path` if it exists, and save there
+    /// with ctrl+s
+    pub fn with_workspace_file(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        if path.exists() {
+            match Workspace::load(&path) {
+                Ok(workspace) => self.apply_workspace(workspace),
+                Err(err) => tracing::warn!("Couldn't load workspace {}: {err}", path.display()),
+            }
+        }
+        self.workspace_path = Some(path);
+        self
+    }
+
+    /// The layout of the panels showing a file, which are the ones a later session can match
+    pub fn workspace(&self) -> Workspace {
+        let panels = self
+            .children
+            .iter()
+            .filter_map(|child| {
+                Some(PanelState {
+                    key: child.key()?,
+                    position: self.world_frame(child).origin() + Vec2::new(0.0, TITLE_BAR_HEIGHT),
+                    size: child.user_size,
+                    collapsed: child.collapsed,
+                })
+            })
+            .collect();
+        let groups = self
+            .groups
+            .iter()
+            .map(|group| GroupState {
+                name: group.name.clone(),
+                members: group
+                    .members
+                    .iter()
+                    .filter_map(|&i| self.children[i].key())
+                    .collect(),
+                collapsed: group.collapsed,
+            })
+            .collect();
+        Workspace { panels, groups }
+    }
+
+    /// Restore a saved layout onto the open panels with the same file and symbol. Saved panels
+    /// that aren't open are skipped.
+    pub fn apply_workspace(&mut self, workspace: Workspace) {
+        let mut restored = BTreeSet::new();
+        for state in workspace.panels {
+            // The same file can be open more than once, each copy takes the next saved state
+            let found = (0..self.children.len()).find(|i| {
+                !restored.contains(i) && self.children[*i].key().as_ref() == Some(&state.key)
+            });
+            let Some(index) = found else {
+                continue;
+            };
+            restored.insert(index);
+            let child = &mut self.children[index];
+            child.position = state.position;
+            child.user_size = state.size;
+            child.collapsed = state.collapsed;
+            child.pinned = false;
+        }
+        self.pending_placement.retain(|i| !restored.contains(i));
+        self.pending_center.retain(|i| !restored.contains(i));
+
+        self.groups = workspace
+            .groups
+            .into_iter()
+            .map(|state| {
+                let members = (0..self.children.len())
+                    .filter(|&i| {
+                        let key = self.children[i].key();
+                        key.is_some_and(|key| state.members.contains(&key))
+                    })
+                    .collect();
+                Group::new(state.name, members).with_collapsed(state.collapsed)
+            })
+            .filter(|group| !group.members.is_empty())
+            .collect();
+    }
+
+    fn save_workspace(&self, path: &Path) {
+        if let Err(err) = self.workspace().save(path) {
+            tracing::warn!("Couldn't save workspace {}: {err}", path.display());
+        }
+    }
+}
+
+// Selection
+impl Panel {
+    pub fn select_all(&mut self) {
+        self.selection = (0..self.children.len()).collect();
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    fn toggle_selected(&mut self, index: usize) {
+        if !self.selection.remove(&index) {
+            self.selection.insert(index);
+        }
+    }
+
+    /// Select everything the rubber band from `start` to `current` touches, on top of `base`
+    fn select_in_rect(&mut self, start: Point, current: Point, base: &BTreeSet<usize>) {
+        let band = Rect::from_points(start, current);
+        let touched = (0..self.children.len()).filter(|&i| {
+            !self.is_hidden(i)
+                && !self
+                    .world_frame(&self.children[i])
+                    .intersect(band)
+                    .is_empty()
+        });
+        self.selection = base.iter().copied().chain(touched).collect();
+    }
+
+    /// Move a child by `delta` in world space
+    fn move_child_by(&mut self, index: usize, delta: Vec2) {
+        let scale = self.view.transform().determinant().sqrt();
+        let child = &mut self.children[index];
+        child.position += if child.pinned { delta * scale } else { delta };
+    }
+
+    /// The selected panels that live in world space. Pinned panels don't take part in arranging
+    fn arrangeable_selection(&self) -> Vec<usize> {
+        self.selection
+            .iter()
+            .copied()
+            .filter(|&i| !self.children[i].pinned)
+            .collect()
+    }
+
+    pub fn align_selection(&mut self, alignment: Alignment) {
+        let selection = self.arrangeable_selection();
+        let frames: Vec<_> = selection
+            .iter()
+            .map(|&i| self.children[i].frame())
+            .collect();
+        let Some(bounds) = frames.iter().copied().reduce(|a, b| a.union(b)) else {
+            return;
+        };
+
+        for (&i, frame) in selection.iter().zip(frames) {
+            let delta = match alignment {
+                Alignment::Left => Vec2::new(bounds.x0 - frame.x0, 0.0),
+                Alignment::Right => Vec2::new(bounds.x1 - frame.x1, 0.0),
+                Alignment::Top => Vec2::new(0.0, bounds.y0 - frame.y0),
+                Alignment::Bottom => Vec2::new(0.0, bounds.y1 - frame.y1),
+                Alignment::CenterHorizontal => Vec2::new(bounds.center().x - frame.center().x, 0.0),
+                Alignment::CenterVertical => Vec2::new(0.0, bounds.center().y - frame.center().y),
+            };
+            self.move_child_by(i, delta);
+        }
+    }
+
+    /// Space the selected panels out so the gaps between them are equal
+    pub fn distribute_selection(&mut self, axis: Axis) {
+        let mut selection = self.arrangeable_selection();
+        if selection.len() < 3 {
+            return;
+        }
+
+        let start = |frame: Rect| match axis {
+            Axis::Horizontal => frame.x0,
+            Axis::Vertical => frame.y0,
+        };
+        let extent = |frame: Rect| match axis {
+            Axis::Horizontal => frame.width(),
+            Axis::Vertical => frame.height(),
+        };
+
+        selection.sort_by(|&a, &b| {
+            start(self.children[a].frame()).total_cmp(&start(self.children[b].frame()))
+        });
+        let frames: Vec<_> = selection
+            .iter()
+            .map(|&i| self.children[i].frame())
+            .collect();
+        let first = frames.first().unwrap();
+        let last = frames.last().unwrap();
+        let total_extent: f64 = frames.iter().map(|f| extent(*f)).sum();
+        let gap = (start(*last) + extent(*last) - start(*first) - total_extent)
+            / (frames.len() - 1) as f64;
+
+        let mut cursor = start(*first);
+        for (&i, frame) in selection.iter().zip(&frames) {
+            let offset = cursor - start(*frame);
+            let delta = match axis {
+                Axis::Horizontal => Vec2::new(offset, 0.0),
+                Axis::Vertical => Vec2::new(0.0, offset),
+            };
+            self.move_child_by(i, delta);
+            cursor += extent(*frame) + gap;
+        }
+    }
+
+    pub fn delete_selection(&mut self, ctx: &mut EventCtx) {
+        let selection: Vec<_> = self.selection.iter().rev().copied().collect();
+        let edits = selection
+            .into_iter()
+            .map(|i| self.remove_child(i))
+            .collect();
+        self.history.record(PanelEdit::Batch(edits));
+        ctx.children_changed();
+        ctx.request_layout();
+        ctx.request_paint();
+    }
+
+    /// Give the selected panels the next background color
+    pub fn recolor_selection(&mut self) {
+        let colors = palette();
+        let colors: Vec<_> = PANEL_COLORS.iter().map(|name| colors[name]).collect();
+        let Some(&first) = self.selection.first() else {
+            return;
+        };
+        let current = colors
+            .iter()
+            .position(|c| *c == self.children[first].background_color);
+        let next = colors[current.map_or(0, |i| (i + 1) % colors.len())];
+        for &i in &self.selection {
+            self.children[i].background_color = next;
+        }
+    }
+
+    /// Tell the keymap which bindings apply, from what has the keyboard
+    fn update_key_contexts(&self) {
+        let contexts = if self.renaming_group {
+            vec![KeyContext::Popup]
+        } else {
+            match self.focused.map(|i| &self.children[i]) {
+                Some(child) if child.shows_code => vec![KeyContext::Code, KeyContext::Panel],
+                Some(_) => vec![KeyContext::Panel],
+                None => Vec::new(),
+            }
+        };
+        self.keymap.set_content_contexts(contexts);
+    }
+}
+
+// Commands
+impl Panel {
+    /// Take commands from `commands` instead of a handle of our own, to share it with the driver
+    pub fn with_commands(mut self, commands: Commands) -> Self {
+        self.commands = commands;
+        self
+    }
+
+    /// Share `keymap` with the canvas, to get the commands it resolves keys to
+    pub fn with_keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = keymap;
+        self
+    }
 
-const ZOOM_SENSITIVITY: f64 = 0.05;
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
 
-pub struct Child {
-    pub position: Point,
-    pub size: Size,
-    pub widget: WidgetPod<Box<dyn Widget>>,
-    pub background_color: Color,
-}
+    /// Let the code blocks we open ask `server` about their files
+    pub fn with_language_server(mut self, server: LanguageServer) -> Self {
+        self.language_server = Some(server);
+        self
+    }
 
-impl Child {
-    pub fn new(position: Point, widget: impl Widget + 'static) -> Self {
-        Self {
-            position,
-            widget: WidgetPod::new(widget).boxed(),
-            background_color: Color::parse("#1F1F1F").unwrap(),
-            size: Size::ZERO,
+    /// A code block for `text` from `source`, getting its commands from our keymap and submitting
+    /// its own to our queue
+    fn code_block(&self, text: impl Into<Arc<str>>, source: SourceLocation) -> CodeBlock {
+        let code = CodeBlock::new(text)
+            .with_keymap(self.keymap.clone())
+            .with_commands(self.commands.clone())
+            .with_rainbow_brackets(self.settings.rainbow_brackets)
+            .with_vim(self.settings.vim_mode);
+        match &self.language_server {
+            Some(server) => code.with_language_server(server.clone(), source),
+            None => code,
         }
     }
 
-    fn overlap(&self, position: Point) -> bool {
-        let local_space = self.to_local_space(position);
-        local_space.x > 0.0
-            && local_space.x < self.size.width
-            && local_space.y > 0.0
-            && local_space.y < self.size.height
+    /// Run the submitted commands, opening files next to `from` or in the middle of the view
+    fn run_queued(&mut self, ctx: &mut EventCtx, from: Option<usize>) {
+        for command in self.commands.take() {
+            if !self.execute_from(ctx, command.clone(), from) {
+                tracing::debug!("Command {} did nothing", command.name());
+            }
+        }
     }
 
-    fn to_local_space(&self, position: Point) -> Point {
-        let local_space = position - self.position;
-        Point::new(local_space.x, local_space.y)
+    /// Run `command`, returning whether it did anything
+    pub fn execute(&mut self, ctx: &mut EventCtx, command: Command) -> bool {
+        self.execute_from(ctx, command, self.focused)
     }
-}
 
-enum DraggingState {
-    NotDragging,
-    Dragging { offset: Point, child: usize },
-}
+    fn execute_from(&mut self, ctx: &mut EventCtx, command: Command, from: Option<usize>) -> bool {
+        // Commands adding or removing panels record their own edits, the rest are recorded if they
+        // changed the layout
+        let before = self.snapshot();
+        match command {
+            Command::Undo => return self.step_history(ctx, Step::Undo),
+            Command::Redo => return self.step_history(ctx, Step::Redo),
+            Command::OpenFile(path) => {
+                let Some(index) = self.open_path(ctx, &path, self.open_position(from)) else {
+                    return false;
+                };
+                if from.is_none() {
+                    self.pending_center.push(index);
+                }
+                self.set_focused(ctx, Some(index));
+                return true;
+            }
+            Command::GoToDefinition { symbol } => return self.go_to_definition(ctx, &symbol, from),
+            Command::OpenSymbol { path, symbol, line } => {
+                return self.open_symbol(ctx, path, &symbol, line, from)
+            }
+            Command::ClosePanel(index) => {
+                let count = self.children.len();
+                let Some(index) = index.or(self.focused).filter(|&i| i < count) else {
+                    return false;
+                };
+                self.close(ctx, index);
+                return true;
+            }
+            Command::FocusPanel(index) => {
+                if index >= self.children.len() || self.is_hidden(index) {
+                    return false;
+                }
+                self.set_focused(ctx, Some(index));
+                return true;
+            }
+            Command::DeleteSelection => {
+                if self.selection.is_empty() {
+                    return false;
+                }
+                self.delete_selection(ctx);
+                return true;
+            }
+            Command::SelectAll => self.select_all(),
+            Command::ClearSelection => {
+                if self.selection.is_empty() {
+                    return false;
+                }
+                self.clear_selection();
+            }
+            Command::Align(alignment) => self.align_selection(alignment),
+            Command::Distribute(axis) => self.distribute_selection(axis),
+            Command::Recolor => self.recolor_selection(),
+            Command::Arrange(layout) => self.arrange(ctx, layout),
+            Command::CycleOverlapAvoidance => {
+                self.overlap_avoidance = self.overlap_avoidance.next()
+            }
+            Command::Raise | Command::Lower | Command::BringToFront | Command::SendToBack => {
+                let Some(focused) = self.focused else {
+                    return false;
+                };
+                match command {
+                    Command::Raise => self.raise(focused),
+                    Command::Lower => self.lower(focused),
+                    Command::BringToFront => self.bring_to_front(focused),
+                    _ => self.send_to_back(focused),
+                }
+            }
+            Command::Ungroup => match self.active_group {
+                Some(group) => self.ungroup(group),
+                None => return false,
+            },
+            Command::SaveWorkspace => {
+                let Some(path) = &self.workspace_path else {
+                    return false;
+                };
+                self.save_workspace(path);
+                return true;
+            }
+            // The canvas runs these
+            Command::ToggleGrid
+            | Command::AddBookmark
+            | Command::Present
+            | Command::Pan(_)
+            | Command::ZoomIn
+            | Command::ZoomOut
+            | Command::CommandPalette
+            | Command::QuickOpen
+            | Command::SymbolSearch => return false,
+            // The focused content runs these
+            Command::ExpandSelection
+            | Command::ShrinkSelection
+            | Command::Fold
+            | Command::Unfold => return false,
+        }
 
-pub struct Panel {
-    pub children: Vec<Child>,
-    dragging_state: DraggingState,
+        if before != self.snapshot() {
+            self.history.record(PanelEdit::Layout(before));
+        }
+        ctx.request_layout();
+        ctx.request_paint();
+        true
+    }
+
+    /// Where a new panel goes in world space: right of `from`, or in the middle of the view
+    fn open_position(&self, from: Option<usize>) -> Point {
+        match from.filter(|&i| i < self.children.len()) {
+            Some(from) => {
+                let frame = self.world_frame(&self.children[from]);
+                Point::new(frame.x1 + TITLE_BAR_HEIGHT * 2.0, frame.y0)
+            }
+            None => self.view.visible_rect().center() - DROP_PREVIEW_SIZE.to_vec2() / 2.0,
+        }
+    }
+
+    /// Move a child to the middle of the view, or to the top of it if it's too tall to fit
+    fn center_in_view(&mut self, index: usize) {
+        let view = self.view.visible_rect();
+        let child = &mut self.children[index];
+        let frame = child.frame();
+        let x = view.center().x - frame.width() / 2.0;
+        let y = if frame.height() < view.height() {
+            view.center().y - frame.height() / 2.0
+        } else {
+            view.y0 + TITLE_BAR_HEIGHT
+        };
+        child.position += Point::new(x, y) - frame.origin();
+    }
+
+    /// Focus the panel showing `symbol`, or find its definition in the files of the open panels
+    /// and open it next to `from`
+    fn go_to_definition(&mut self, ctx: &mut EventCtx, symbol: &str, from: Option<usize>) -> bool {
+        let shown = (0..self.children.len())
+            .find(|&i| self.children[i].symbol.as_deref() == Some(symbol) && !self.is_hidden(i));
+        if let Some(index) = shown {
+            self.set_focused(ctx, Some(index));
+            return true;
+        }
+
+        // The file of `from` first, it's the most likely place
+        let count = self.children.len();
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for i in from.filter(|&i| i < count).into_iter().chain(0..count) {
+            if let Some(path) = &self.children[i].path {
+                if !paths.contains(path) {
+                    paths.push(path.clone());
+                }
+            }
+        }
+        for path in paths {
+            let Ok(source) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let Some(definition) = symbols::find_definition(&source, symbol) else {
+                continue;
+            };
+            self.open_definition(ctx, path, &source, definition, from);
+            return true;
+        }
+        false
+    }
+
+    /// Open the item `symbol` of the file at `path`, the one around `line` if there are several
+    fn open_symbol(
+        &mut self,
+        ctx: &mut EventCtx,
+        path: PathBuf,
+        symbol: &str,
+        line: Option<usize>,
+        from: Option<usize>,
+    ) -> bool {
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                tracing::warn!("Couldn't open {}: {err}", path.display());
+                return false;
+            }
+        };
+        let definition = match line {
+            Some(line) => symbols::find_definition_near(&source, symbol, line),
+            None => symbols::find_definition(&source, symbol),
+        };
+        let Some(definition) = definition else {
+            return false;
+        };
+        self.open_definition(ctx, path, &source, definition, from);
+        true
+    }
+
+    /// Open a panel with just `definition` from `source`, next to `from` or in the middle of the
+    /// view
+    fn open_definition(
+        &mut self,
+        ctx: &mut EventCtx,
+        path: PathBuf,
+        source: &str,
+        definition: symbols::Symbol,
+        from: Option<usize>,
+    ) {
+        let position = self.open_position(from) + Vec2::new(0.0, TITLE_BAR_HEIGHT);
+        let start = definition.range.start;
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let location = SourceLocation {
+            path: path.clone(),
+            position: lsp_types::Position::new(
+                symbols::line_of(source, start) as u32,
+                source[line_start..start].encode_utf16().count() as u32,
+            ),
+        };
+        let code = self.code_block(&source[definition.range], location);
+        let child = Child::new(position, code)
+            .with_path(path)
+            .with_symbol(definition.name);
+        let index = self.add_child(child);
+        if from.is_none() {
+            self.pending_center.push(index);
+        }
+        ctx.children_changed();
+        ctx.request_layout();
+        self.set_focused(ctx, Some(index));
+    }
 }
 
+// Undo
 impl Panel {
-    pub fn new(children: Vec<Child>) -> Self {
-        Self {
+    fn snapshot(&self) -> LayoutSnapshot {
+        let children = self
+            .children
+            .iter()
+            .map(|child| ChildState {
+                position: child.position,
+                user_size: child.user_size,
+                scroll: child.scroll,
+                collapsed: child.collapsed,
+                pinned: child.pinned,
+                background_color: child.background_color,
+            })
+            .collect();
+        let edges = self
+            .edges
+            .iter()
+            .map(|e| (e.from, e.to, e.kind, e.label().map(Arc::from)))
+            .collect();
+        let groups = self
+            .groups
+            .iter()
+            .map(|g| (g.name.clone(), g.members.clone(), g.collapsed))
+            .collect();
+        LayoutSnapshot {
             children,
-            dragging_state: DraggingState::NotDragging,
+            z_order: self.z_order.clone(),
+            edges,
+            groups,
         }
     }
 
-    fn logical_position_to_point(&self, ctx: &EventCtx, position: LogicalPosition<f64>) -> Point {
-        let position = Point::new(position.x, position.y);
-        let position = position - ctx.to_window(Point::ZERO);
-        let position = Point::new(position.x, position.y);
-        return position;
+    /// Go back to `layout`, which has to be of the children there are now
+    fn restore(&mut self, layout: LayoutSnapshot) {
+        for (child, state) in self.children.iter_mut().zip(layout.children) {
+            child.position = state.position;
+            child.user_size = state.user_size;
+            child.scroll = state.scroll;
+            child.collapsed = state.collapsed;
+            child.pinned = state.pinned;
+            child.background_color = state.background_color;
+        }
+        self.z_order = layout.z_order;
+        self.edges = layout
+            .edges
+            .into_iter()
+            .map(|(from, to, kind, label)| {
+                let edge = Edge::new(from, to, kind);
+                match label {
+                    Some(label) => edge.with_label(label),
+                    None => edge,
+                }
+            })
+            .collect();
+        self.groups = layout
+            .groups
+            .into_iter()
+            .map(|(name, members, collapsed)| Group::new(name, members).with_collapsed(collapsed))
+            .collect();
+
+        let count = self.children.len();
+        self.selection.retain(|&i| i < count);
+        if self.focused.is_some_and(|f| f >= count) {
+            self.focused = None;
+        }
+        self.hovered = None;
+        self.captured = None;
+        self.dragging_state = DraggingState::NotDragging;
+        self.arrange_animation = None;
+        self.pending_placement.clear();
+        self.pending_center.clear();
+        self.guides.clear();
+        self.active_group = None;
+        self.renaming_group = false;
+    }
+
+    /// Record the current layout, before changing it
+    fn record_layout(&mut self) {
+        let layout = self.snapshot();
+        self.history.record(PanelEdit::Layout(layout));
+    }
+
+    /// Remember the layout at the start of a drag, so the whole drag is undone at once
+    fn begin_edit(&mut self) {
+        self.edit_start = Some(self.snapshot());
+    }
+
+    /// Record the drag started with [`Panel::begin_edit`], if it changed anything
+    fn end_edit(&mut self) {
+        if let Some(before) = self.edit_start.take() {
+            if before != self.snapshot() {
+                self.history.record(PanelEdit::Layout(before));
+            }
+        }
+    }
+
+    /// Apply `edit`, returning what reverts it in turn
+    fn revert(&mut self, ctx: &mut EventCtx, edit: PanelEdit) -> PanelEdit {
+        match edit {
+            PanelEdit::Layout(layout) => {
+                let current = self.snapshot();
+                self.restore(layout);
+                PanelEdit::Layout(current)
+            }
+            PanelEdit::Removed {
+                index,
+                child,
+                layout,
+            } => {
+                let current = self.snapshot();
+                self.children.insert(index, child);
+                self.restore(layout);
+                ctx.children_changed();
+                PanelEdit::Added {
+                    index,
+                    layout: current,
+                }
+            }
+            PanelEdit::Added { index, layout } => {
+                let current = self.snapshot();
+                let child = self.children.remove(index);
+                self.restore(layout);
+                ctx.children_changed();
+                PanelEdit::Removed {
+                    index,
+                    child,
+                    layout: current,
+                }
+            }
+            PanelEdit::Batch(edits) => PanelEdit::Batch(
+                edits
+                    .into_iter()
+                    .rev()
+                    .map(|edit| self.revert(ctx, edit))
+                    .collect(),
+            ),
+        }
     }
+
+    /// Undo or redo the latest edit of the panel, unless a change of the canvas viewport is
+    /// more recent. Returns whether there was anything to step through.
+    fn step_history(&mut self, ctx: &mut EventCtx, step: Step) -> bool {
+        let (viewport_undo, viewport_redo) = self.view.viewport_edits();
+        let popped = match step {
+            Step::Undo if history::undo_first(self.history.undo_number(), viewport_undo) => {
+                self.history.pop_undo()
+            }
+            Step::Redo if history::redo_first(self.history.redo_number(), viewport_redo) => {
+                self.history.pop_redo()
+            }
+            _ => None,
+        };
+        let Some((number, edit)) = popped else {
+            return false;
+        };
+
+        let reverted = self.revert(ctx, edit);
+        match step {
+            Step::Undo => self.history.push_redo(number, reverted),
+            Step::Redo => self.history.push_undo(number, reverted),
+        }
+        ctx.request_layout();
+        ctx.request_paint();
+        true
+    }
+}
+
+/// Thin indicators of how far a resized panel's content is scrolled
+fn paint_scrollbars(scene: &mut Scene, child: &Child) {
+    let rect = child.rect();
+    let color = palette()["vscSplitThumb"];
+
+    if child.content_size.height > child.size.height {
+        let ratio = child.size.height / child.content_size.height;
+        let thumb = Rect::from_origin_size(
+            Point::new(rect.x1 - SCROLLBAR_WIDTH, rect.y0 + child.scroll.y * ratio),
+            Size::new(SCROLLBAR_WIDTH, rect.height() * ratio),
+        );
+        fill_color(scene, &thumb, color);
+    }
+
+    if child.content_size.width > child.size.width {
+        let ratio = child.size.width / child.content_size.width;
+        let thumb = Rect::from_origin_size(
+            Point::new(rect.x0 + child.scroll.x * ratio, rect.y1 - SCROLLBAR_WIDTH),
+            Size::new(rect.width() * ratio, SCROLLBAR_WIDTH),
+        );
+        fill_color(scene, &thumb, color);
+    }
+}
+
+fn paint_title_bar(scene: &mut Scene, child: &mut Child) {
+    let colors = palette();
+    let title_bar = child.title_bar();
+    fill_color(scene, &title_bar, colors["vscTabOther"]);
+
+    let text_y = (TITLE_BAR_HEIGHT - child.title_layout.size().height) / 2.0;
+    child.title_layout.draw(
+        scene,
+        Point::new(title_bar.x0 + TITLE_PADDING, title_bar.y0 + text_y),
+    );
+
+    let glyph_color = colors["vscPopupFront"];
+    for button in TITLE_BUTTONS {
+        let glyph = child.button_rect(button).inset(-7.0);
+        match button {
+            TitleButton::Close => {
+                let a = masonry::kurbo::Line::new(glyph.origin(), Point::new(glyph.x1, glyph.y1));
+                let b = masonry::kurbo::Line::new(
+                    Point::new(glyph.x0, glyph.y1),
+                    Point::new(glyph.x1, glyph.y0),
+                );
+                stroke(scene, &a, glyph_color, 1.5);
+                stroke(scene, &b, glyph_color, 1.5);
+            }
+            TitleButton::Collapse if child.collapsed => {
+                stroke(scene, &glyph, glyph_color, 1.5);
+            }
+            TitleButton::Collapse => {
+                let y = glyph.center().y;
+                let line =
+                    masonry::kurbo::Line::new(Point::new(glyph.x0, y), Point::new(glyph.x1, y));
+                stroke(scene, &line, glyph_color, 1.5);
+            }
+            TitleButton::Pin => {
+                let circle = Circle::new(glyph.center(), glyph.width() / 2.0);
+                if child.pinned {
+                    fill_color(scene, &circle, colors["vscAccentBlue"]);
+                } else {
+                    stroke(scene, &circle, glyph_color, 1.5);
+                }
+            }
+        }
+    }
+}
+
+/// Paint a child into a scene in world space. `origin` is the layout origin of the panel, which
+/// the child widget is painted relative to.
+fn paint_child(
+    ctx: &mut PaintCtx,
+    scene: &mut Scene,
+    child: &mut Child,
+    border_color: Color,
+    origin: Vec2,
+) {
+    let path = child.frame().inflate(10., 10.);
+
+    stroke(scene, &path, border_color, 10.0);
+
+    fill_color(scene, &path, child.background_color);
+
+    if !child.collapsed {
+        scene.push_layer(BlendMode::default(), 1.0, Affine::IDENTITY, &path);
+        scene.push_layer(BlendMode::default(), 1.0, Affine::IDENTITY, &child.rect());
+        let mut widget_scene = Scene::new();
+        child.widget.paint(ctx, &mut widget_scene);
+        scene.append(&widget_scene, Some(Affine::translate(origin)));
+        scene.pop_layer();
+        scene.pop_layer();
+
+        paint_scrollbars(scene, child);
+    }
+
+    paint_title_bar(scene, child);
 }
 
 // If this widget has any child widgets it should call its event, update and layout
@@ -75,51 +1903,345 @@ impl Panel {
 // but a general rule is to just pass it through unless you really know you don't want it.
 impl Widget for Panel {
     fn on_pointer_event(&mut self, ctx: &mut EventCtx, event: &PointerEvent) {
+        // Commands from outside the panel, like dropped files, open where the user is looking
+        self.run_queued(ctx, None);
+
+        // A command picked with this click in the palette
+        if let Some(command) = self.keymap.take() {
+            if self.execute_from(ctx, command.clone(), None) {
+                ctx.set_handled();
+            } else {
+                self.keymap.hand_over(command);
+                if let Some(focused) = self.focused {
+                    self.children[focused].widget.on_pointer_event(ctx, event);
+                }
+            }
+            self.update_key_contexts();
+            return;
+        }
+
+        if let PointerEvent::PointerDown(_, state) = event {
+            let position = self.logical_position_to_point(ctx, state.position);
+            let clicked = self
+                .child_at(position)
+                .or_else(|| self.resize_handle_at(position).map(|(i, _)| i));
+            if let Some(clicked) = clicked {
+                self.bring_to_front(clicked);
+            }
+            self.set_focused(ctx, clicked);
+            if clicked.is_some() || self.group_handle_at(position).is_none() {
+                self.active_group = None;
+                self.renaming_group = false;
+            }
+        }
+
         match event {
-            PointerEvent::PointerDown(masonry::PointerButton::Secondary, state) => {
+            PointerEvent::PointerDown(masonry::PointerButton::Primary, state) => {
+                let position = self.logical_position_to_point(ctx, state.position);
+
+                if let Some((i, edges)) = self.resize_handle_at(position) {
+                    ctx.set_active(true);
+                    ctx.set_handled();
+                    ctx.set_cursor(&edges.cursor());
+
+                    self.begin_edit();
+                    let child = &self.children[i];
+                    self.dragging_state = DraggingState::Resizing {
+                        edges,
+                        start_rect: child.frame(),
+                        start_position: self.to_child_space(child, position),
+                        child: i,
+                    };
+                } else if let Some(i) = self.child_at(position) {
+                    let child = &self.children[i];
+                    let local = self.to_child_space(child, position);
+                    let extend = state.mods.state().shift_key();
+
+                    if let Some(button) = child.button_at(local) {
+                        ctx.set_handled();
+                        self.press_button(ctx, i, button);
+                    } else if state.mods.state().control_key() && child.title_bar().contains(local)
+                    {
+                        // Ctrl+click on the content is left to the content, for go to definition
+                        ctx.set_active(true);
+                        ctx.set_handled();
+                        self.begin_edit();
+                        self.dragging_state = DraggingState::Linking {
+                            from: i,
+                            current: position,
+                        };
+                    } else if extend {
+                        ctx.set_handled();
+                        self.toggle_selected(i);
+                        ctx.request_paint();
+                    } else if child.title_bar().contains(local) {
+                        if !self.selection.contains(&i) {
+                            self.selection = BTreeSet::from([i]);
+                        }
+                        ctx.set_active(true);
+                        ctx.set_handled();
+
+                        let offset = self.children[i].to_local_space(local);
+                        self.begin_edit();
+                        self.dragging_state = DraggingState::Dragging { offset, child: i };
+                    }
+                } else if let Some((group, frame)) = self.group_handle_at(position) {
+                    ctx.set_handled();
+                    self.active_group = Some(group);
+                    self.renaming_group = false;
+                    if state.count >= 2 {
+                        self.start_renaming(group);
+                    }
+
+                    if Group::collapse_button(frame).contains(position) {
+                        self.record_layout();
+                        self.toggle_group_collapsed(group);
+                        ctx.request_layout();
+                    } else {
+                        ctx.set_active(true);
+                        self.begin_edit();
+                        if !self.groups[group].collapsed {
+                            self.selection = self.groups[group].members.clone();
+                        }
+                        self.dragging_state = DraggingState::DraggingGroup {
+                            group,
+                            previous: position,
+                        };
+                    }
+                    ctx.request_paint();
+                } else if state.mods.state().alt_key() {
+                    ctx.set_active(true);
+                    ctx.set_handled();
+                    self.begin_edit();
+                    self.dragging_state = DraggingState::Framing {
+                        start: position,
+                        current: position,
+                    };
+                } else {
+                    ctx.set_active(true);
+                    ctx.set_handled();
+
+                    let base = if state.mods.state().shift_key() {
+                        self.selection.clone()
+                    } else {
+                        BTreeSet::new()
+                    };
+                    self.selection = base.clone();
+                    self.dragging_state = DraggingState::Selecting {
+                        start: position,
+                        current: position,
+                        base,
+                    };
+                    ctx.request_paint();
+                }
+            }
+            PointerEvent::PointerUp(masonry::PointerButton::Primary, state) => {
+                if let DraggingState::Linking { from, .. } = self.dragging_state {
+                    let position = self.logical_position_to_point(ctx, state.position);
+                    if let Some(to) = self.child_at(position).filter(|&to| to != from) {
+                        self.toggle_manual_edge(from, to);
+                    }
+                }
+
+                if let DraggingState::Framing { start, current } = self.dragging_state {
+                    self.group_in_rect(Rect::from_points(start, current));
+                }
+
+                if !matches!(self.dragging_state, DraggingState::NotDragging) {
+                    self.drop_dragged();
+
+                    if ctx.is_active() {
+                        ctx.set_handled();
+                        ctx.set_active(false);
+                        ctx.clear_cursor();
+                    }
+                    ctx.request_layout();
+                    ctx.request_paint();
+                }
+            }
+            PointerEvent::HoverFile(path, state) => {
+                let position = self.logical_position_to_point(ctx, state.position);
+                // Only look at the file system once per file
+                let size = match &self.drop_preview {
+                    Some((previous, ghost)) if previous == path => ghost.size(),
+                    _ if path.is_dir() => {
+                        Size::new(DROP_PREVIEW_SIZE.width / 2.0, DROP_PREVIEW_SIZE.height)
+                    }
+                    _ => DROP_PREVIEW_SIZE,
+                };
+                let ghost = Rect::from_origin_size(position, size);
+                self.drop_preview = Some((path.clone(), ghost));
+                ctx.set_handled();
+                ctx.request_paint();
+            }
+            PointerEvent::HoverFileCancel(_) => {
+                self.drop_preview = None;
+                ctx.set_handled();
+                ctx.request_paint();
+            }
+            PointerEvent::DropFile(path, state) => {
+                self.drop_preview = None;
+                let position = self.logical_position_to_point(ctx, state.position);
+                if let Some(index) = self.open_path(ctx, path, position) {
+                    self.set_focused(ctx, Some(index));
+                }
+                ctx.set_handled();
+            }
+            PointerEvent::MouseWheel(delta, state) => {
+                // Only the focused panel scrolls, so the wheel still zooms over the rest of the canvas
+                let position = self.logical_position_to_point(ctx, state.position);
+                if let Some(i) = self.focused {
+                    let position = self.to_child_space(&self.children[i], position);
+                    let child = &mut self.children[i];
+                    if child.overflows() && child.rect().contains(position) {
+                        let delta = Vec2::new(-delta.x, -delta.y);
+                        child.scroll_by(match ScrollDelta::new(delta, self.settings.scroll_mode) {
+                            ScrollDelta::Lines(lines) => lines * SCROLL_LINE_HEIGHT,
+                            ScrollDelta::Pixels(pixels) => pixels,
+                        });
+                        ctx.set_handled();
+                        ctx.request_layout();
+                        ctx.request_paint();
+                    }
+                }
+            }
+            PointerEvent::PointerDown(button, state) if *button == self.keymap.mouse().drag => {
                 println!("Event");
                 let position = self.logical_position_to_point(ctx, state.position);
 
-                if let Some((i, child)) = self
-                    .children
-                    .iter()
-                    .enumerate()
-                    .find(|(_, c)| c.overlap(position))
-                {
+                if let Some(i) = self.child_at(position) {
                     ctx.set_active(true);
                     ctx.set_handled();
 
-                    let offset = child.to_local_space(position);
+                    self.begin_edit();
+                    let child = &self.children[i];
+                    let offset = child.to_local_space(self.to_child_space(child, position));
                     self.dragging_state = DraggingState::Dragging { offset, child: i }
                 }
                 // ctx.request_layout();
             }
-            PointerEvent::PointerUp(masonry::PointerButton::Secondary, state) => {
-                self.dragging_state = DraggingState::NotDragging;
+            PointerEvent::PointerUp(button, state) if *button == self.keymap.mouse().drag => {
+                self.drop_dragged();
 
                 if ctx.is_active() {
                     ctx.set_handled();
                     ctx.set_active(false);
                 }
+                ctx.request_layout();
+                ctx.request_paint();
             }
             PointerEvent::PointerMove(state) => {
+                let position = self.logical_position_to_point(ctx, state.position);
+
+                if !ctx.is_active() {
+                    match self.resize_handle_at(position) {
+                        Some((_, edges)) => ctx.set_cursor(&edges.cursor()),
+                        None => ctx.clear_cursor(),
+                    }
+                }
+
                 if ctx.is_active() {
-                    let position = self.logical_position_to_point(ctx, state.position);
+                    if let DraggingState::Resizing {
+                        edges,
+                        start_rect,
+                        start_position,
+                        child,
+                    } = self.dragging_state
+                    {
+                        let position = self.to_child_space(&self.children[child], position);
+                        let frame = edges.resize(start_rect, position - start_position);
+                        self.children[child].set_frame(frame);
+
+                        ctx.request_layout();
+                        ctx.request_paint();
+                    }
+
+                    if let DraggingState::Selecting { start, base, .. } = &self.dragging_state {
+                        let (start, base) = (*start, base.clone());
+                        self.select_in_rect(start, position, &base);
+                        self.dragging_state = DraggingState::Selecting {
+                            start,
+                            current: position,
+                            base,
+                        };
+                        ctx.request_paint();
+                    }
+
+                    if let DraggingState::Linking { from, .. } = self.dragging_state {
+                        self.dragging_state = DraggingState::Linking {
+                            from,
+                            current: position,
+                        };
+                        ctx.request_paint();
+                    }
+
+                    if let DraggingState::Framing { start, .. } = self.dragging_state {
+                        self.dragging_state = DraggingState::Framing {
+                            start,
+                            current: position,
+                        };
+                        ctx.request_paint();
+                    }
+
+                    if let DraggingState::DraggingGroup { group, previous } = self.dragging_state {
+                        self.move_group_by(group, position - previous);
+                        self.dragging_state = DraggingState::DraggingGroup {
+                            group,
+                            previous: position,
+                        };
+                        ctx.request_layout();
+                        ctx.request_paint();
+                    }
 
                     if let DraggingState::Dragging { offset, child } = self.dragging_state {
+                        let position = self.to_child_space(&self.children[child], position);
                         let mut new_position =
                             Point::new(position.x - offset.x, position.y - offset.y);
 
-                        new_position.x = new_position
-                            .x
-                            .clamp(0.0, (ctx.size().width - self.children[child].size.width).max(0.0));
-                        new_position.y = new_position
-                            .y
-                            .clamp(0.0, (ctx.size().height - self.children[child].size.height).max(0.0));
+                        // Line up with other panels or the grid, unless alt is held
+                        self.guides.clear();
+                        if !self.children[child].pinned && !state.mods.state().alt_key() {
+                            let frame = self.children[child].frame()
+                                + (new_position - self.children[child].position);
+                            let group = self.drag_group(child);
+                            let others: Vec<Rect> = (0..self.children.len())
+                                .filter(|i| !group.contains(i) && !self.children[*i].pinned)
+                                .map(|i| self.children[i].frame())
+                                .collect();
+                            let scale = self.view.transform().determinant().sqrt();
+                            let snap = snapping::snap(
+                                frame,
+                                &others,
+                                SNAP_DISTANCE / scale,
+                                self.view.grid(),
+                            );
+                            new_position += snap.offset;
+                            self.guides = snap.guides;
+                        }
 
                         // println!("{position:?}");
+                        let moved = new_position - self.children[child].position;
                         self.children[child].position = new_position;
 
+                        // The rest of the selection follows along
+                        if self.selection.contains(&child) {
+                            let scale = self.view.transform().determinant().sqrt();
+                            let moved = if self.children[child].pinned {
+                                moved / scale
+                            } else {
+                                moved
+                            };
+                            let others: Vec<_> = self
+                                .selection
+                                .iter()
+                                .copied()
+                                .filter(|&i| i != child)
+                                .collect();
+                            for i in others {
+                                self.move_child_by(i, moved);
+                            }
+                        }
+
                         ctx.request_layout();
                         ctx.request_paint();
                     }
@@ -128,15 +2250,60 @@ impl Widget for Panel {
             _ => {}
         }
 
-        for child in &mut self.children {
-            child.widget.on_pointer_event(ctx, event);
-        }
+        self.route_pointer_event(ctx, event);
+
+        // Clicking a file in a file tree or a name in code opens it next to that panel
+        self.run_queued(ctx, self.hovered);
+        self.update_key_contexts();
     }
 
     fn on_text_event(&mut self, ctx: &mut EventCtx, event: &TextEvent) {
-        for child in &mut self.children {
-            child.widget.on_text_event(ctx, event);
+        self.run_queued(ctx, None);
+
+        // The canvas resolved the key to a command, which the focused content may run if we don't.
+        // Files it opens go in the middle of the view.
+        if let Some(command) = self.keymap.take() {
+            if self.execute_from(ctx, command.clone(), None) {
+                ctx.set_handled();
+            } else {
+                self.keymap.hand_over(command);
+                if let Some(focused) = self.focused {
+                    self.children[focused].widget.on_text_event(ctx, event);
+                }
+            }
+            self.update_key_contexts();
+            return;
+        }
+
+        if let TextEvent::KeyboardKey(key, mods) = event {
+            if key.state == ElementState::Pressed && self.renaming_group {
+                let before = self.snapshot();
+                if self.rename_key(&key.logical_key, *mods) {
+                    // Typing a name is undone in one go
+                    if before != self.snapshot() {
+                        self.history
+                            .record_coalesced("rename", || PanelEdit::Layout(before));
+                    }
+                    ctx.set_handled();
+                    ctx.request_layout();
+                    ctx.request_paint();
+                    self.update_key_contexts();
+                    return;
+                }
+            }
+
+            if key.state == ElementState::Pressed && key.logical_key == Key::Named(NamedKey::Tab) {
+                self.cycle_focus(ctx, mods.shift_key());
+                ctx.set_handled();
+                self.update_key_contexts();
+                return;
+            }
         }
+
+        if let Some(focused) = self.focused {
+            self.children[focused].widget.on_text_event(ctx, event);
+        }
+        self.update_key_contexts();
     }
 
     fn on_access_event(&mut self, ctx: &mut EventCtx, event: &AccessEvent) {
@@ -146,6 +2313,16 @@ impl Widget for Panel {
     }
 
     fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle) {
+        if let LifeCycle::AnimFrame(interval) = event {
+            if self.arrange_animation.is_some() {
+                if self.step_arrange_animation(*interval) {
+                    ctx.request_anim_frame();
+                }
+                ctx.request_layout();
+                ctx.request_paint();
+            }
+        }
+
         for child in &mut self.children {
             child.widget.lifecycle(ctx, event);
         }
@@ -155,37 +2332,171 @@ impl Widget for Panel {
 
     fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints) -> Size {
         for child in &mut self.children {
-            child.size = child.widget.layout(ctx, &BoxConstraints::UNBOUNDED);
-            ctx.place_child(&mut child.widget, child.position);
+            // Resized panels lay their content out within their width, and scroll what's taller
+            let content_bc = match child.user_size {
+                Some(size) => BoxConstraints::new(Size::ZERO, Size::new(size.width, f64::INFINITY)),
+                None => BoxConstraints::UNBOUNDED,
+            };
+            child.content_size = child.widget.layout(ctx, &content_bc);
+            child.size = child.user_size.unwrap_or(child.content_size);
+            // Keep the scroll in range if the panel grew or the content shrank
+            child.scroll_by(Vec2::ZERO);
         }
 
-        if bc.is_width_bounded() && bc.is_height_bounded() {
-            bc.max()
-        } else {
-            let size = Size::new(10_000.0, 10_000.0);
-            bc.constrain(size)
+        // New children only have a size now, so this is the first chance to find them a spot
+        for index in std::mem::take(&mut self.pending_center) {
+            self.center_in_view(index);
+        }
+        if !self.pending_placement.is_empty() {
+            let placed = std::mem::take(&mut self.pending_placement);
+            self.avoid_overlaps(&placed);
+        }
+
+        // Cover every child and everything on screen, so pointer events anywhere reach us
+        let margin = HANDLE_SIZE + 10.0;
+        let group_frames = self.groups.iter().filter_map(|g| self.group_frame(g));
+        let bounds = self
+            .children
+            .iter()
+            .map(|child| self.world_frame(child).inflate(margin, margin))
+            .chain(group_frames)
+            .fold(self.view.visible_rect(), |a, b| a.union(b));
+        self.origin = bounds.origin();
+        self.view.set_content_origin(self.origin);
+
+        let origin = self.origin.to_vec2();
+        for child in &mut self.children {
+            ctx.place_child(&mut child.widget, child.position - child.scroll - origin);
+
+            // Leave room for the title bar buttons
+            let title_width = child.size.width
+                - TITLE_BUTTONS.len() as f64 * TITLE_BAR_HEIGHT
+                - 2.0 * TITLE_PADDING;
+            child
+                .title_layout
+                .set_max_advance(Some(title_width.max(0.0) as f32));
+            if child.title_layout.needs_rebuild() {
+                let (font_ctx, layout_ctx) = ctx.text_contexts();
+                child.title_layout.rebuild(font_ctx, layout_ctx);
+            }
+        }
+
+        for edge in &mut self.edges {
+            let (font_ctx, layout_ctx) = ctx.text_contexts();
+            edge.rebuild_label(font_ctx, layout_ctx);
         }
+        for group in &mut self.groups {
+            let (font_ctx, layout_ctx) = ctx.text_contexts();
+            group.rebuild_title(font_ctx, layout_ctx);
+        }
+
+        bc.constrain(bounds.size())
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, scene: &mut Scene) {
-        for child in self.children.iter_mut().rev() {
-            let path = Rect::from_origin_size(child.position, child.size).inflate(10., 10.);
+        let colors = palette();
+        let focus_color = colors["vscAccentBlue"];
+        let selection_color = colors["vscMediumBlue"];
+        let view_inverse = self.view.transform().inverse();
+        let origin = self.origin.to_vec2();
+
+        // Everything is painted in world space, then moved to our layout origin
+        let mut world_scene = Scene::new();
+        // Pinned panels are painted into their own scene, on top of everything else
+        let mut pinned_scene = Scene::new();
+
+        // Expanded groups sit below every panel, collapsed ones replace their members
+        let group_frames: Vec<_> = self.groups.iter().map(|g| self.group_frame(g)).collect();
+        let name_selected = self.renaming_group && self.group_name_selected;
+        for (i, group) in self.groups.iter_mut().enumerate() {
+            if let (false, Some(frame)) = (group.collapsed, group_frames[i]) {
+                let active = self.active_group == Some(i);
+                group.paint(&mut world_scene, frame, active, active && name_selected);
+            }
+        }
 
-            stroke(scene, &path, Color::WHITE, 10.0);
+        let stacking: Vec<_> = self.stacking().collect();
+        for i in stacking {
+            let child = &mut self.children[i];
+            let border_color = if self.focused == Some(i) {
+                focus_color
+            } else if self.selection.contains(&i) {
+                selection_color
+            } else {
+                Color::WHITE
+            };
 
-            fill_color(scene, &path, child.background_color);
+            if child.pinned {
+                paint_child(ctx, &mut pinned_scene, child, border_color, origin);
+            } else {
+                paint_child(ctx, &mut world_scene, child, border_color, origin);
+            }
+        }
 
-            scene.push_layer(BlendMode::default(), 1.0, Affine::IDENTITY, &path);
-            child.widget.paint(ctx, scene);
-            scene.pop_layer();
+        for (i, group) in self.groups.iter_mut().enumerate() {
+            if let (true, Some(frame)) = (group.collapsed, group_frames[i]) {
+                let active = self.active_group == Some(i);
+                group.paint(&mut world_scene, frame, active, active && name_selected);
+            }
         }
 
-        for slice in self.children.windows(2) {
-            if let [child1, child2] = slice {
-                let path = masonry::kurbo::Line::new(child1.position, child2.position);
-                stroke(scene, &path, Color::WHITE, 2.0);
+        // Edges inside a collapsed group are hidden, the rest attach to the group instead
+        let frames: Vec<Rect> = (0..self.children.len())
+            .map(|i| self.visible_frame(i))
+            .collect();
+        let collapsed: Vec<_> = (0..self.children.len())
+            .map(|i| self.collapsed_group_of(i))
+            .collect();
+        for edge in &mut self.edges {
+            if collapsed[edge.from].is_some() && collapsed[edge.from] == collapsed[edge.to] {
+                continue;
             }
+            edge.paint(&mut world_scene, frames[edge.from], frames[edge.to]);
+        }
+        if let DraggingState::Linking { from, current } = self.dragging_state {
+            let line = edges::route_to_point(frames[from], current);
+            edges::paint_arrow(
+                &mut world_scene,
+                line,
+                EdgeKind::Manual.color(),
+                &[6.0, 4.0],
+            );
+        }
+
+        let scale = self.view.transform().determinant().sqrt();
+        for guide in &self.guides {
+            stroke(&mut world_scene, guide, colors["vscPink"], 1.0 / scale);
         }
+
+        // Undo the canvas transform so pinned panels stay fixed on screen
+        world_scene.append(&pinned_scene, Some(view_inverse));
+
+        if let Some((_, ghost)) = &self.drop_preview {
+            let ghost = ghost.to_rounded_rect(6.0);
+            fill_color(
+                &mut world_scene,
+                &ghost,
+                colors["vscAccentBlue"].with_alpha_factor(0.15),
+            );
+            stroke(&mut world_scene, &ghost, colors["vscAccentBlue"], 2.0);
+        }
+
+        if let DraggingState::Framing { start, current } = &self.dragging_state {
+            let frame = Rect::from_points(*start, *current).to_rounded_rect(8.0);
+            stroke(&mut world_scene, &frame, colors["vscSplitLight"], 2.0);
+        }
+
+        if let DraggingState::Selecting { start, current, .. } = &self.dragging_state {
+            let band = Rect::from_points(*start, *current);
+            fill_color(
+                &mut world_scene,
+                &band,
+                colors["vscSelection"].with_alpha_factor(0.4),
+            );
+            stroke(&mut world_scene, &band, colors["vscSelection"], 1.0);
+        }
+
+        scene.append(&world_scene, Some(Affine::translate(-origin)));
     }
 
     fn accessibility(&mut self, ctx: &mut AccessCtx) {
@@ -199,7 +2510,10 @@ impl Widget for Panel {
     }
 
     fn children_ids(&self) -> SmallVec<[WidgetId; 16]> {
-        SmallVec::new()
+        self.children
+            .iter()
+            .map(|child| child.widget.id())
+            .collect()
     }
 
     fn make_trace_span(&self) -> Span {
@@ -0,0 +1,225 @@
+// Keeping panels from overlapping when they are spawned or dropped
+
+use std::collections::VecDeque;
+
+use masonry::{Point, Rect, Vec2};
+
+/// Space kept between panels, enough for their borders
+const MARGIN: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapAvoidance {
+    /// Panels may overlap
+    Off,
+    /// Neighbours are pushed aside to make room
+    Nudge,
+    /// The placed panel moves to the nearest free spot
+    FreeSpot,
+}
+
+impl OverlapAvoidance {
+    /// The next mode, for toggling through them
+    pub fn next(&self) -> Self {
+        match self {
+            OverlapAvoidance::Off => OverlapAvoidance::Nudge,
+            OverlapAvoidance::Nudge => OverlapAvoidance::FreeSpot,
+            OverlapAvoidance::FreeSpot => OverlapAvoidance::Off,
+        }
+    }
+
+    /// Resolve overlaps after the frames marked as `placed` were put down. Returns the new frames.
+    ///
+    /// The canvas grows to cover its panels, so frames can go anywhere.
+    pub fn resolve(&self, frames: &[Rect], placed: &[bool]) -> Vec<Rect> {
+        match self {
+            OverlapAvoidance::Off => frames.to_vec(),
+            OverlapAvoidance::Nudge => nudge(frames, placed),
+            OverlapAvoidance::FreeSpot => free_spots(frames, placed),
+        }
+    }
+}
+
+fn overlaps(a: Rect, b: Rect) -> bool {
+    let a = a.inflate(MARGIN / 2.0, MARGIN / 2.0);
+    let b = b.inflate(MARGIN / 2.0, MARGIN / 2.0);
+    a.x0 < b.x1 && b.x0 < a.x1 && a.y0 < b.y1 && b.y0 < a.y1
+}
+
+/// Push frames that weren't placed out of the way. Frames settle one at a time, starting with the
+/// placed ones: a frame that overlaps a settled one moves out of it in whichever of the four
+/// directions takes it clear of every settled frame soonest, then settles in turn. Settled frames
+/// never move again, so neighbours can't push each other back and forth.
+fn nudge(frames: &[Rect], placed: &[bool]) -> Vec<Rect> {
+    let mut frames = frames.to_vec();
+    let mut settled = placed.to_vec();
+    let mut queue: VecDeque<usize> = (0..frames.len()).filter(|&i| placed[i]).collect();
+
+    while let Some(a) = queue.pop_front() {
+        for b in 0..frames.len() {
+            if settled[b] || !overlaps(frames[a], frames[b]) {
+                continue;
+            }
+
+            let obstacles: Vec<Rect> = (0..frames.len())
+                .filter(|&i| settled[i])
+                .map(|i| frames[i])
+                .collect();
+            let start = frames[b];
+            frames[b] = DIRECTIONS
+                .iter()
+                .map(|&direction| push_clear(start, direction, &obstacles))
+                .min_by(|x, y| {
+                    let dx = (x.origin() - start.origin()).hypot2();
+                    let dy = (y.origin() - start.origin()).hypot2();
+                    dx.total_cmp(&dy)
+                })
+                .unwrap_or(start);
+            settled[b] = true;
+            queue.push_back(b);
+        }
+    }
+
+    frames
+}
+
+const DIRECTIONS: [Vec2; 4] = [
+    Vec2::new(1.0, 0.0),
+    Vec2::new(-1.0, 0.0),
+    Vec2::new(0.0, 1.0),
+    Vec2::new(0.0, -1.0),
+];
+
+/// Move `frame` along `direction` until it overlaps none of `obstacles`
+fn push_clear(mut frame: Rect, direction: Vec2, obstacles: &[Rect]) -> Rect {
+    while let Some(o) = obstacles.iter().find(|o| overlaps(frame, **o)) {
+        let depth = if direction.x > 0.0 {
+            o.x1 + MARGIN - frame.x0
+        } else if direction.x < 0.0 {
+            frame.x1 + MARGIN - o.x0
+        } else if direction.y > 0.0 {
+            o.y1 + MARGIN - frame.y0
+        } else {
+            frame.y1 + MARGIN - o.y0
+        };
+        frame = frame + direction * depth;
+    }
+    frame
+}
+
+/// Move every placed frame to the closest spot where it doesn't overlap anything
+fn free_spots(frames: &[Rect], placed: &[bool]) -> Vec<Rect> {
+    let mut frames = frames.to_vec();
+
+    for i in (0..frames.len()).filter(|&i| placed[i]) {
+        let frame = frames[i];
+        let obstacles: Vec<Rect> = frames
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, f)| *f)
+            .collect();
+
+        let is_free = |candidate: Rect| !obstacles.iter().any(|o| overlaps(candidate, *o));
+
+        if is_free(frame) {
+            continue;
+        }
+
+        // Free spots always touch some obstacle, so try every side of every obstacle
+        let (width, height) = (frame.width(), frame.height());
+        let mut candidates = Vec::new();
+        for o in &obstacles {
+            let xs = [o.x1 + MARGIN, o.x0 - MARGIN - width, frame.x0];
+            let ys = [o.y1 + MARGIN, o.y0 - MARGIN - height, frame.y0];
+            for x in xs {
+                for y in ys {
+                    candidates.push(Rect::from_origin_size(Point::new(x, y), frame.size()));
+                }
+            }
+        }
+
+        let best = candidates
+            .into_iter()
+            .filter(|c| is_free(*c))
+            .min_by(|a, b| {
+                let da = (a.origin() - frame.origin()).hypot2();
+                let db = (b.origin() - frame.origin()).hypot2();
+                da.total_cmp(&db)
+            });
+
+        if let Some(best) = best {
+            frames[i] = best;
+        }
+    }
+
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(x: f64, y: f64) -> Rect {
+        Rect::from_origin_size(Point::new(x, y), (100.0, 80.0))
+    }
+
+    fn assert_apart(frames: &[Rect]) {
+        for (i, a) in frames.iter().enumerate() {
+            for b in &frames[i + 1..] {
+                assert!(!overlaps(*a, *b), "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    /// A tightly packed block of panels with one more dropped onto its middle
+    fn dense() -> (Vec<Rect>, Vec<bool>) {
+        let mut frames: Vec<Rect> = (0..25)
+            .map(|i| frame((i % 5) as f64 * 130.0, (i / 5) as f64 * 110.0))
+            .collect();
+        frames.push(frame(270.0, 230.0));
+        let mut placed = vec![false; frames.len()];
+        placed[25] = true;
+        (frames, placed)
+    }
+
+    #[test]
+    fn nudges_apart_dense_panels() {
+        let (frames, placed) = dense();
+        let resolved = OverlapAvoidance::Nudge.resolve(&frames, &placed);
+        assert_eq!(resolved[25], frames[25]);
+        assert_apart(&resolved);
+    }
+
+    #[test]
+    fn finds_free_spots_among_dense_panels() {
+        let (frames, placed) = dense();
+        let resolved = OverlapAvoidance::FreeSpot.resolve(&frames, &placed);
+        assert_eq!(resolved[..25], frames[..25]);
+        assert_ne!(resolved[25], frames[25]);
+        assert_apart(&resolved);
+    }
+
+    #[test]
+    fn works_far_from_the_origin() {
+        // Beyond the 10,000 pixels the canvas used to be limited to, and before its origin
+        for (x, y) in [(10_000.0, 10_000.0), (-25_000.0, 40_000.0)] {
+            let frames = [frame(x, y), frame(x + 50.0, y + 20.0)];
+            let placed = [false, true];
+            for mode in [OverlapAvoidance::Nudge, OverlapAvoidance::FreeSpot] {
+                let resolved = mode.resolve(&frames, &placed);
+                assert_apart(&resolved);
+                // Only one of the frames moves, and only as far as needed
+                let moved = (resolved[0].origin() - frames[0].origin())
+                    + (resolved[1].origin() - frames[1].origin());
+                assert!(moved.hypot() <= 100.0 + MARGIN, "{mode:?} moved {moved:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn off_leaves_overlaps() {
+        let frames = [frame(0.0, 0.0), frame(10.0, 10.0)];
+        let resolved = OverlapAvoidance::Off.resolve(&frames, &[false, true]);
+        assert_eq!(resolved, frames);
+    }
+}
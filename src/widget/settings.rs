@@ -0,0 +1,160 @@
+// Preferences read from a TOML file like
+//
+//     scroll-mode = "touchpad"  # or "mouse", or "auto" to tell them apart per event
+//     rainbow-brackets = false
+//     vim-mode = true
+
+use std::{fmt, fs, io, path::Path};
+
+use super::canvas::ScrollMode;
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Whether scroll deltas are wheel lines or touchpad pixels, found per event by default
+    pub scroll_mode: ScrollMode,
+    /// Color the brackets of code by how deep they're nested
+    pub rainbow_brackets: bool,
+    /// Move around and select code with Vim keys
+    pub vim_mode: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            scroll_mode: ScrollMode::Auto,
+            rainbow_brackets: true,
+            vim_mode: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    UnknownSetting(String),
+    /// The setting exists, but not with this value
+    BadValue(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Io(error) => write!(f, "can't read the settings: {error}"),
+            SettingsError::Toml(error) => write!(f, "malformed settings: {error}"),
+            SettingsError::UnknownSetting(name) => write!(f, "unknown setting `{name}`"),
+            SettingsError::BadValue(name) => write!(f, "bad value for the setting `{name}`"),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    /// Read the settings at `path`, the defaults if there is no file. `PLAYGROUND_SCROLL_MODE`
+    /// overrides the scroll mode of the file.
+    pub fn load(path: &Path) -> Result<Self, SettingsError> {
+        let scroll_mode = std::env::var("PLAYGROUND_SCROLL_MODE").ok();
+        Self::load_with_scroll_mode(path, scroll_mode.as_deref())
+    }
+
+    /// Like [`Settings::load`], with `scroll_mode` standing in for `PLAYGROUND_SCROLL_MODE`
+    fn load_with_scroll_mode(
+        path: &Path,
+        scroll_mode: Option<&str>,
+    ) -> Result<Self, SettingsError> {
+        let mut settings = Self::default();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(SettingsError::Io(error)),
+        };
+        let table: toml::Table = text.parse().map_err(SettingsError::Toml)?;
+        for (name, value) in &table {
+            let bad_value = || SettingsError::BadValue(name.clone());
+            match name.as_str() {
+                "scroll-mode" => {
+                    let mode = value.as_str().and_then(ScrollMode::from_name);
+                    settings.scroll_mode = mode.ok_or_else(bad_value)?;
+                }
+                "rainbow-brackets" => {
+                    settings.rainbow_brackets = value.as_bool().ok_or_else(bad_value)?;
+                }
+                "vim-mode" => settings.vim_mode = value.as_bool().ok_or_else(bad_value)?,
+                _ => return Err(SettingsError::UnknownSetting(name.clone())),
+            }
+        }
+        if let Some(mode) = scroll_mode.and_then(ScrollMode::from_name) {
+            settings.scroll_mode = mode;
+        }
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A settings file with `text`, unique to the calling test
+    fn settings_file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "playground-settings-{}-{name}.toml",
+            std::process::id()
+        ));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn load(name: &str, text: &str, scroll_mode: Option<&str>) -> Result<Settings, SettingsError> {
+        let path = settings_file(name, text);
+        let settings = Settings::load_with_scroll_mode(&path, scroll_mode);
+        fs::remove_file(path).unwrap();
+        settings
+    }
+
+    #[test]
+    fn missing_file_gives_defaults() {
+        let path = std::env::temp_dir().join("playground-settings-that-does-not-exist.toml");
+        let settings = Settings::load_with_scroll_mode(&path, None).unwrap();
+        assert_eq!(settings.scroll_mode, ScrollMode::Auto);
+        assert!(settings.rainbow_brackets);
+        assert!(!settings.vim_mode);
+    }
+
+    #[test]
+    fn loads_every_setting() {
+        let text = "scroll-mode = \"touchpad\"\nrainbow-brackets = false\nvim-mode = true\n";
+        let settings = load("every", text, None).unwrap();
+        assert_eq!(settings.scroll_mode, ScrollMode::Touchpad);
+        assert!(!settings.rainbow_brackets);
+        assert!(settings.vim_mode);
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        let error = load("unknown", "scroll-speed = 2\n", None).unwrap_err();
+        assert!(matches!(error, SettingsError::UnknownSetting(name) if name == "scroll-speed"));
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        let error = load("bad-mode", "scroll-mode = \"trackball\"\n", None).unwrap_err();
+        assert!(matches!(error, SettingsError::BadValue(name) if name == "scroll-mode"));
+        let error = load("bad-bool", "rainbow-brackets = \"yes\"\n", None).unwrap_err();
+        assert!(matches!(error, SettingsError::BadValue(name) if name == "rainbow-brackets"));
+        let error = load("malformed", "scroll-mode = \n", None).unwrap_err();
+        assert!(matches!(error, SettingsError::Toml(_)));
+    }
+
+    #[test]
+    fn environment_overrides_scroll_mode() {
+        let text = "scroll-mode = \"touchpad\"\n";
+        let settings = load("env", text, Some("mouse")).unwrap();
+        assert_eq!(settings.scroll_mode, ScrollMode::Mouse);
+        // Unknown names leave the file's choice
+        let settings = load("env-unknown", text, Some("trackball")).unwrap();
+        assert_eq!(settings.scroll_mode, ScrollMode::Touchpad);
+    }
+}
@@ -0,0 +1,76 @@
+// Snapping dragged panels to the edges and centers of other panels, or to the canvas grid
+
+use masonry::{kurbo::Line, Rect, Vec2};
+
+/// Positions closer than this are considered lined up, when comparing snapped frames
+const EPSILON: f64 = 0.5;
+
+pub struct Snap {
+    /// How far to move the frame
+    pub offset: Vec2,
+    /// Lines through every edge or center the moved frame lines up with
+    pub guides: Vec<Line>,
+}
+
+/// Left, center and right
+fn vertical_lines(frame: Rect) -> [f64; 3] {
+    [frame.x0, frame.center().x, frame.x1]
+}
+
+/// Top, center and bottom
+fn horizontal_lines(frame: Rect) -> [f64; 3] {
+    [frame.y0, frame.center().y, frame.y1]
+}
+
+/// The smallest move within `threshold` that lines one of `lines` up with one of `targets`
+fn closest(
+    lines: [f64; 3],
+    targets: impl Iterator<Item = f64> + Clone,
+    threshold: f64,
+) -> Option<f64> {
+    lines
+        .iter()
+        .flat_map(|&line| targets.clone().map(move |target| target - line))
+        .filter(|delta| delta.abs() <= threshold)
+        .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+}
+
+/// How to move `frame` so it lines up with `others`, falling back to snapping its top left corner
+/// to a grid of `grid` spacing on axes where nothing lines up
+pub fn snap(frame: Rect, others: &[Rect], threshold: f64, grid: Option<f64>) -> Snap {
+    let to_grid = |value: f64| grid.map_or(0.0, |grid| (value / grid).round() * grid - value);
+
+    let xs = others.iter().flat_map(|o| vertical_lines(*o));
+    let ys = others.iter().flat_map(|o| horizontal_lines(*o));
+    let offset = Vec2::new(
+        closest(vertical_lines(frame), xs, threshold).unwrap_or_else(|| to_grid(frame.x0)),
+        closest(horizontal_lines(frame), ys, threshold).unwrap_or_else(|| to_grid(frame.y0)),
+    );
+
+    let frame = frame + offset;
+    let mut guides = Vec::new();
+    for other in others {
+        for x in vertical_lines(frame) {
+            if vertical_lines(*other)
+                .iter()
+                .any(|o| (o - x).abs() < EPSILON)
+            {
+                let y0 = frame.y0.min(other.y0);
+                let y1 = frame.y1.max(other.y1);
+                guides.push(Line::new((x, y0), (x, y1)));
+            }
+        }
+        for y in horizontal_lines(frame) {
+            if horizontal_lines(*other)
+                .iter()
+                .any(|o| (o - y).abs() < EPSILON)
+            {
+                let x0 = frame.x0.min(other.x0);
+                let x1 = frame.x1.max(other.x1);
+                guides.push(Line::new((x0, y), (x1, y)));
+            }
+        }
+    }
+
+    Snap { offset, guides }
+}
@@ -0,0 +1,142 @@
+// Definitions of Rust items, found with tree-sitter
+
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use tree_sitter::{Node, Parser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Struct,
+    Enum,
+    Trait,
+    Type,
+    Const,
+    Static,
+    Module,
+    Macro,
+}
+
+impl SymbolKind {
+    /// The kind of item a tree-sitter node of kind `kind` defines, if any
+    fn of(kind: &str) -> Option<Self> {
+        Some(match kind {
+            "function_item" | "function_signature_item" => SymbolKind::Function,
+            "struct_item" => SymbolKind::Struct,
+            "enum_item" => SymbolKind::Enum,
+            "trait_item" => SymbolKind::Trait,
+            "type_item" => SymbolKind::Type,
+            "const_item" => SymbolKind::Const,
+            "static_item" => SymbolKind::Static,
+            "mod_item" => SymbolKind::Module,
+            "macro_definition" => SymbolKind::Macro,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Bytes of the whole item in the source
+    pub range: Range<usize>,
+}
+
+/// Where a symbol of the workspace is defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolLocation {
+    pub name: String,
+    pub path: PathBuf,
+    /// Starting from 0
+    pub line: usize,
+}
+
+/// The symbols defined in the Rust files among `files`, which are relative to `root`
+pub fn index_symbols(root: &Path, files: &[PathBuf]) -> Vec<SymbolLocation> {
+    let mut index = Vec::new();
+    for path in files {
+        if path.extension().map_or(true, |extension| extension != "rs") {
+            continue;
+        }
+        let Ok(source) = fs::read_to_string(root.join(&path)) else {
+            continue;
+        };
+        index.extend(
+            definitions(&source)
+                .into_iter()
+                .map(|symbol| SymbolLocation {
+                    line: line_of(&source, symbol.range.start),
+                    name: symbol.name,
+                    path: path.to_path_buf(),
+                }),
+        );
+    }
+    index
+}
+
+/// The line `offset` is on, starting from 0
+pub fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count()
+}
+
+/// The definition of `name` in `source` closest around `line`, or the first one if none is
+pub fn find_definition_near(source: &str, name: &str, line: usize) -> Option<Symbol> {
+    let candidates: Vec<Symbol> = definitions(source)
+        .into_iter()
+        .filter(|symbol| symbol.name == name)
+        .collect();
+    let around = candidates
+        .iter()
+        .filter(|symbol| {
+            let lines = line_of(source, symbol.range.start)..=line_of(source, symbol.range.end);
+            lines.contains(&line)
+        })
+        .min_by_key(|symbol| symbol.range.len());
+    around.or(candidates.first()).cloned()
+}
+
+/// Every item defined in `source`, including those nested in modules, impls and functions, in
+/// the order they appear
+pub fn definitions(source: &str) -> Vec<Symbol> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&tree_sitter_rust::language())
+        .expect("Error loading Rust grammar");
+    let Some(tree) = parser.parse(source, None) else {
+        return Vec::new();
+    };
+    let mut symbols = Vec::new();
+    collect(tree.root_node(), source, &mut symbols);
+    symbols
+}
+
+/// The first definition of `name` in `source`
+pub fn find_definition(source: &str, name: &str) -> Option<Symbol> {
+    definitions(source)
+        .into_iter()
+        .find(|symbol| symbol.name == name)
+}
+
+fn collect(node: Node, source: &str, symbols: &mut Vec<Symbol>) {
+    if let Some(kind) = SymbolKind::of(node.kind()) {
+        let name = node
+            .child_by_field_name("name")
+            .and_then(|name| name.utf8_text(source.as_bytes()).ok());
+        if let Some(name) = name {
+            symbols.push(Symbol {
+                name: name.to_string(),
+                kind,
+                range: node.byte_range(),
+            });
+        }
+    }
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect(child, source, symbols);
+    }
+}
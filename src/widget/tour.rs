@@ -0,0 +1,317 @@
+use std::sync::Arc;
+
+use masonry::{
+    parley::{
+        style::{FontWeight, StyleProperty},
+        FontContext, LayoutContext,
+    },
+    text::TextLayout,
+    Affine, Color, Point, Size, Vec2,
+};
+
+use super::colors::palette;
+
+/// How long it takes to fly from one stop to the next, in seconds
+const TRANSITION_DURATION: f64 = 0.6;
+
+const NOTE_FONT_SIZE: f32 = 16.0;
+const HEADING_FONT_SIZE: f32 = 22.0;
+
+/// A region of the canvas in world space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub center: Point,
+    pub scale: f64,
+}
+
+impl Viewport {
+    pub fn new(center: Point, scale: f64) -> Self {
+        Self { center, scale }
+    }
+
+    /// The viewport a canvas of `size` is currently showing with `transform`
+    pub fn from_transform(transform: Affine, size: Size) -> Self {
+        let scale = transform.determinant().sqrt();
+        let screen_center = Vec2::new(size.width / 2.0, size.height / 2.0);
+        let center = (screen_center - transform.translation()) / scale;
+        Self {
+            center: center.to_point(),
+            scale,
+        }
+    }
+
+    /// The canvas transform that shows this viewport on a canvas of `size`
+    pub fn to_transform(&self, size: Size) -> Affine {
+        let screen_center = Vec2::new(size.width / 2.0, size.height / 2.0);
+        let translation = screen_center - self.center.to_vec2() * self.scale;
+        Affine::IDENTITY
+            .then_scale(self.scale)
+            .then_translate(translation)
+    }
+
+    fn lerp(&self, other: &Viewport, t: f64) -> Viewport {
+        // Interpolate scale geometrically so zooming feels uniform
+        let scale = self.scale * (other.scale / self.scale).powf(t);
+        Viewport {
+            center: self.center.lerp(other.center, t),
+            scale,
+        }
+    }
+}
+
+/// A saved viewport, optionally narrated with a Markdown note
+#[derive(Debug, Clone)]
+pub struct Bookmark {
+    pub viewport: Viewport,
+    pub note: Option<Arc<str>>,
+}
+
+impl Bookmark {
+    pub fn new(viewport: Viewport) -> Self {
+        Self {
+            viewport,
+            note: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<Arc<str>>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+}
+
+pub struct ViewportAnimation {
+    from: Viewport,
+    to: Viewport,
+    elapsed: f64,
+}
+
+impl ViewportAnimation {
+    pub fn new(from: Viewport, to: Viewport) -> Self {
+        Self {
+            from,
+            to,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Advance by `interval` nanoseconds, returning the viewport to show and whether we are done
+    pub fn advance(&mut self, interval: u64) -> (Viewport, bool) {
+        self.elapsed += interval as f64 * 1e-9;
+        let t = (self.elapsed / TRANSITION_DURATION).min(1.0);
+        // Smoothstep
+        let eased = t * t * (3.0 - 2.0 * t);
+        (self.from.lerp(&self.to, eased), t >= 1.0)
+    }
+
+    pub fn target(&self) -> Viewport {
+        self.to
+    }
+}
+
+/// State of a running presentation over the canvas bookmarks
+pub struct Presentation {
+    pub current: usize,
+    /// The note of the current stop, laid out in screen space
+    pub note: Option<TextLayout<Arc<str>>>,
+    /// Whether `note` needs to be rebuilt for the current stop
+    pub note_dirty: bool,
+}
+
+impl Presentation {
+    pub fn new() -> Self {
+        Self {
+            current: 0,
+            note: None,
+            note_dirty: true,
+        }
+    }
+
+    /// Move to `index` within `stops` bookmarks, returning true if the stop changed
+    pub fn go_to(&mut self, index: usize, stops: usize) -> bool {
+        if stops == 0 {
+            return false;
+        }
+        let index = index.min(stops - 1);
+        if index == self.current {
+            return false;
+        }
+        self.current = index;
+        self.note_dirty = true;
+        true
+    }
+
+    pub fn rebuild_note(
+        &mut self,
+        bookmark: &Bookmark,
+        max_width: f64,
+        font_ctx: &mut FontContext,
+        layout_ctx: &mut LayoutContext<masonry::text::TextBrush>,
+    ) {
+        self.note_dirty = false;
+        self.note = bookmark.note.as_ref().map(|note| {
+            let note = parse_markdown(note);
+            let mut layout = TextLayout::new(note.text.clone(), NOTE_FONT_SIZE);
+            layout.set_brush(note_color());
+            layout.set_max_advance(Some(max_width as f32));
+            layout.rebuild_with_attributes(font_ctx, layout_ctx, |mut builder| {
+                for (range, style) in &note.spans {
+                    match style {
+                        NoteStyle::Heading => {
+                            builder
+                                .push(&StyleProperty::FontSize(HEADING_FONT_SIZE), range.clone());
+                            builder
+                                .push(&StyleProperty::FontWeight(FontWeight::BOLD), range.clone());
+                        }
+                        NoteStyle::Bold => {
+                            builder
+                                .push(&StyleProperty::FontWeight(FontWeight::BOLD), range.clone());
+                        }
+                        NoteStyle::Code => {
+                            builder.push(&StyleProperty::Brush(code_color().into()), range.clone());
+                        }
+                    }
+                }
+                builder
+            });
+            layout
+        });
+    }
+}
+
+fn note_color() -> Color {
+    palette()["vscFront"]
+}
+
+fn code_color() -> Color {
+    palette()["vscOrange"]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NoteStyle {
+    Heading,
+    Bold,
+    Code,
+}
+
+struct ParsedNote {
+    text: Arc<str>,
+    spans: Vec<(std::ops::Range<usize>, NoteStyle)>,
+}
+
+/// A small subset of Markdown: `#` headings, `**bold**` and `` `code` ``
+fn parse_markdown(source: &str) -> ParsedNote {
+    let mut text = String::new();
+    let mut spans = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        if i > 0 {
+            text.push('\n');
+        }
+
+        let trimmed = line.trim_start();
+        let heading = trimmed.starts_with('#');
+        let line = if heading {
+            trimmed.trim_start_matches('#').trim_start()
+        } else {
+            line
+        };
+
+        let line_start = text.len();
+        let mut rest = line;
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("**") {
+                if let Some(end) = after.find("**") {
+                    let start = text.len();
+                    text.push_str(&after[..end]);
+                    spans.push((start..text.len(), NoteStyle::Bold));
+                    rest = &after[end + 2..];
+                    continue;
+                }
+            } else if let Some(after) = rest.strip_prefix('`') {
+                if let Some(end) = after.find('`') {
+                    let start = text.len();
+                    text.push_str(&after[..end]);
+                    spans.push((start..text.len(), NoteStyle::Code));
+                    rest = &after[end + 1..];
+                    continue;
+                }
+            }
+
+            let c = rest.chars().next().unwrap();
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+
+        if heading && text.len() > line_start {
+            spans.push((line_start..text.len(), NoteStyle::Heading));
+        }
+    }
+
+    ParsedNote {
+        text: text.into(),
+        spans,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn viewport_round_trips_through_transform() {
+        let size = Size::new(800.0, 600.0);
+        let viewport = Viewport::new(Point::new(100.0, -50.0), 2.0);
+        let transform = viewport.to_transform(size);
+        // The center of the viewport is in the middle of the screen
+        let center = transform * viewport.center;
+        assert_close(center.x, 400.0);
+        assert_close(center.y, 300.0);
+
+        let back = Viewport::from_transform(transform, size);
+        assert_close(back.center.x, viewport.center.x);
+        assert_close(back.center.y, viewport.center.y);
+        assert_close(back.scale, viewport.scale);
+    }
+
+    #[test]
+    fn zoom_interpolates_geometrically() {
+        let from = Viewport::new(Point::ZERO, 1.0);
+        let to = Viewport::new(Point::new(10.0, 20.0), 4.0);
+        let halfway = from.lerp(&to, 0.5);
+        assert_close(halfway.scale, 2.0);
+        assert_eq!(halfway.center, Point::new(5.0, 10.0));
+
+        let mut animation = ViewportAnimation::new(from, to);
+        let (_, done) = animation.advance(1);
+        assert!(!done);
+        let (end, done) = animation.advance((TRANSITION_DURATION * 1e9) as u64);
+        assert!(done);
+        assert_eq!(end, to);
+    }
+
+    #[test]
+    fn parses_headings_bold_and_code() {
+        let note = parse_markdown("# Title\nSome **bold** and `code`");
+        assert_eq!(&*note.text, "Title\nSome bold and code");
+        assert_eq!(
+            note.spans,
+            [
+                (0..5, NoteStyle::Heading),
+                (11..15, NoteStyle::Bold),
+                (20..24, NoteStyle::Code),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_markers_stay_text() {
+        let note = parse_markdown("a **b `c\n#");
+        assert_eq!(&*note.text, "a **b `c\n");
+        assert!(note.spans.is_empty());
+    }
+}
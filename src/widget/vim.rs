@@ -0,0 +1,885 @@
+// A read-only subset of Vim for moving around and selecting in code: normal, visual and visual
+// line modes, motions with counts, text objects and `:q`. Nothing edits the text, so there is no
+// insert mode, no operators and no registers.
+
+use std::ops::Range;
+
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+use super::commands::Command;
+
+/// Counts past this are cut down, nobody means them
+const MAX_COUNT: usize = 99_999;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Normal,
+    /// Selecting chars
+    Visual,
+    /// Selecting whole lines
+    VisualLine,
+}
+
+/// How `f`, `F`, `t` and `T` look for a char on the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Find {
+    forward: bool,
+    /// Stop next to the char rather than on it
+    till: bool,
+}
+
+/// A key that needs the next one to mean anything
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prefix {
+    /// `g`, as in `gg`
+    G,
+    Find(Find),
+    /// `i` or `a` in visual mode, picking a text object
+    Object {
+        inner: bool,
+    },
+}
+
+pub enum VimResponse {
+    /// Not a key Vim uses, like one held with Ctrl
+    Ignored,
+    Handled,
+    /// The key finished a command line that runs `Command`
+    Run(Command),
+}
+
+#[derive(Default)]
+pub struct Vim {
+    mode: Mode,
+    cursor: usize,
+    /// Where the visual selection started
+    anchor: usize,
+    count: Option<usize>,
+    prefix: Option<Prefix>,
+    /// What was typed after `:`, until Enter or Escape
+    command_line: Option<String>,
+    /// The column `j` and `k` keep to, in chars, `usize::MAX` after `$`
+    column: Option<usize>,
+    /// The last `f`, `F`, `t` or `T`, repeated by `;` and `,`
+    last_find: Option<(Find, char)>,
+}
+
+impl Vim {
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The byte offset of the cursor in the text
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The command line being typed after `:`
+    pub fn command_line(&self) -> Option<&str> {
+        self.command_line.as_deref()
+    }
+
+    /// What is selected in `text`, empty at the cursor in normal mode
+    pub fn selection(&self, text: &str) -> Range<usize> {
+        let start = self.anchor.min(self.cursor);
+        let end = self.anchor.max(self.cursor);
+        match self.mode {
+            Mode::Normal => self.cursor..self.cursor,
+            Mode::Visual => start..next_char(text, end),
+            Mode::VisualLine => {
+                let lines = Lines::new(text);
+                let first = lines.range(lines.of(start)).start;
+                let last = lines.range(lines.of(end)).end;
+                // Take the newline along
+                first..next_char(text, last)
+            }
+        }
+    }
+
+    /// Take over a selection made some other way, like with the mouse
+    pub fn select(&mut self, text: &str, selection: Range<usize>) {
+        let lines = Lines::new(text);
+        self.count = None;
+        self.prefix = None;
+        self.column = None;
+        if selection.is_empty() {
+            self.mode = Mode::Normal;
+            self.cursor = lines.clamp(selection.start);
+        } else {
+            self.mode = Mode::Visual;
+            self.anchor = selection.start;
+            self.cursor = previous_char(text, selection.end);
+        }
+    }
+
+    pub fn key(&mut self, text: &str, key: &Key, mods: ModifiersState) -> VimResponse {
+        if mods.control_key() || mods.alt_key() || mods.super_key() {
+            return VimResponse::Ignored;
+        }
+        if self.command_line.is_some() {
+            return self.command_line_key(text, key);
+        }
+
+        let c = match key {
+            Key::Character(typed) => {
+                let mut chars = typed.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return VimResponse::Ignored,
+                }
+            }
+            Key::Named(NamedKey::Escape) => {
+                self.count = None;
+                self.prefix = None;
+                self.mode = Mode::Normal;
+                return VimResponse::Handled;
+            }
+            Key::Named(NamedKey::Space) => ' ',
+            Key::Named(NamedKey::ArrowLeft) => 'h',
+            Key::Named(NamedKey::ArrowRight) => 'l',
+            Key::Named(NamedKey::ArrowUp) => 'k',
+            Key::Named(NamedKey::ArrowDown) => 'j',
+            Key::Named(NamedKey::Home) => '0',
+            Key::Named(NamedKey::End) => '$',
+            _ => return VimResponse::Ignored,
+        };
+
+        let lines = Lines::new(text);
+        if let Some(prefix) = self.prefix.take() {
+            let count = self.count.take();
+            self.after_prefix(&lines, prefix, c, count);
+            return VimResponse::Handled;
+        }
+
+        match c {
+            '1'..='9' => self.push_digit(c),
+            '0' if self.count.is_some() => self.push_digit(c),
+            'g' => self.prefix = Some(Prefix::G),
+            'f' | 'F' | 't' | 'T' => {
+                self.prefix = Some(Prefix::Find(Find {
+                    forward: c.is_lowercase(),
+                    till: c.eq_ignore_ascii_case(&'t'),
+                }))
+            }
+            'i' | 'a' if self.mode != Mode::Normal => {
+                self.prefix = Some(Prefix::Object { inner: c == 'i' })
+            }
+            ':' => {
+                self.count = None;
+                self.command_line = Some(String::new());
+            }
+            'v' | 'V' => {
+                let mode = if c == 'v' {
+                    Mode::Visual
+                } else {
+                    Mode::VisualLine
+                };
+                if self.mode == Mode::Normal {
+                    self.anchor = self.cursor;
+                }
+                self.mode = if self.mode == mode {
+                    Mode::Normal
+                } else {
+                    mode
+                };
+                self.count = None;
+            }
+            'o' if self.mode != Mode::Normal => {
+                std::mem::swap(&mut self.anchor, &mut self.cursor);
+                self.column = None;
+                self.count = None;
+            }
+            c => {
+                let count = self.count.take();
+                if let Some(target) = self.motion(&lines, c, count) {
+                    self.cursor = lines.clamp(target);
+                }
+            }
+        }
+        VimResponse::Handled
+    }
+
+    fn push_digit(&mut self, digit: char) {
+        let digit = digit.to_digit(10).unwrap_or_default() as usize;
+        let count = self.count.unwrap_or(0).saturating_mul(10) + digit;
+        self.count = Some(count.min(MAX_COUNT));
+    }
+
+    /// Where the motion `c` goes from the cursor, `None` if `c` isn't a motion
+    fn motion(&mut self, lines: &Lines, c: char, count: Option<usize>) -> Option<usize> {
+        let text = lines.text;
+        let times = count.unwrap_or(1);
+        let cursor = self.cursor;
+        let line = lines.of(cursor);
+
+        // Vertical motions keep to the column they started from, the others set it anew
+        if !matches!(c, 'j' | 'k') {
+            self.column = None;
+        }
+
+        let target = match c {
+            'h' => {
+                let start = lines.range(line).start;
+                (0..times).fold(cursor, |at, _| previous_char(text, at).max(start))
+            }
+            'l' | ' ' => (0..times).fold(cursor, |at, _| lines.clamp(next_char(text, at))),
+            'j' | 'k' => {
+                let column = *self.column.get_or_insert_with(|| lines.column(cursor));
+                let line = if c == 'j' {
+                    (line + times).min(lines.count() - 1)
+                } else {
+                    line.saturating_sub(times)
+                };
+                lines.at_column(line, column)
+            }
+            'w' => (0..times).fold(cursor, |at, _| next_word_start(text, at)),
+            'b' => (0..times).fold(cursor, |at, _| previous_word_start(text, at)),
+            'e' => (0..times).fold(cursor, |at, _| word_end(text, at)),
+            '0' => lines.range(line).start,
+            '^' => lines.first_non_blank(line),
+            '$' => {
+                self.column = Some(usize::MAX);
+                let line = (line + times - 1).min(lines.count() - 1);
+                lines.range(line).end
+            }
+            'G' => {
+                let line = count.map_or(lines.count(), |line| line.min(lines.count()));
+                lines.first_non_blank(line.saturating_sub(1))
+            }
+            '}' => lines.range(lines.paragraph(line, times, true)).start,
+            '{' => lines.range(lines.paragraph(line, times, false)).start,
+            '%' => matching_bracket(text, cursor, lines.range(line).end)?,
+            ';' | ',' => {
+                let (find, wanted) = self.last_find?;
+                let find = Find {
+                    forward: find.forward == (c == ';'),
+                    ..find
+                };
+                self.find(lines, find, wanted, times, true)?
+            }
+            _ => return None,
+        };
+        Some(target)
+    }
+
+    /// Finish the command started by `prefix` with `c`
+    fn after_prefix(&mut self, lines: &Lines, prefix: Prefix, c: char, count: Option<usize>) {
+        match prefix {
+            Prefix::G => {
+                if c == 'g' {
+                    self.column = None;
+                    let line = count.unwrap_or(1).clamp(1, lines.count());
+                    self.cursor = lines.first_non_blank(line - 1);
+                }
+            }
+            Prefix::Find(find) => {
+                self.last_find = Some((find, c));
+                self.column = None;
+                if let Some(target) = self.find(lines, find, c, count.unwrap_or(1), false) {
+                    self.cursor = target;
+                }
+            }
+            Prefix::Object { inner } => {
+                let Some(object) = text_object(lines, self.cursor, c, inner, count) else {
+                    return;
+                };
+                if object.is_empty() {
+                    return;
+                }
+                self.mode = Mode::Visual;
+                self.anchor = object.start;
+                self.cursor = previous_char(lines.text, object.end);
+                self.column = None;
+            }
+        }
+    }
+
+    /// The `times`th `wanted` char on the cursor's line, or next to it. Repeating a `t` or `T`
+    /// skips the char the cursor is already next to.
+    fn find(
+        &self,
+        lines: &Lines,
+        find: Find,
+        wanted: char,
+        times: usize,
+        repeat: bool,
+    ) -> Option<usize> {
+        let text = lines.text;
+        let line = lines.range(lines.of(self.cursor));
+        let mut at = self.cursor;
+        if find.till && repeat {
+            at = if find.forward {
+                next_char(text, at)
+            } else {
+                previous_char(text, at)
+            };
+        }
+        for _ in 0..times {
+            at = if find.forward {
+                let after = next_char(text, at).min(line.end);
+                after + text[after..line.end].find(wanted)?
+            } else {
+                text[line.start..at].rfind(wanted)? + line.start
+            };
+        }
+        Some(match (find.till, find.forward) {
+            (false, _) => at,
+            (true, true) => previous_char(text, at),
+            (true, false) => next_char(text, at),
+        })
+    }
+
+    fn command_line_key(&mut self, text: &str, key: &Key) -> VimResponse {
+        let Some(line) = &mut self.command_line else {
+            return VimResponse::Ignored;
+        };
+        match key {
+            Key::Named(NamedKey::Escape) => self.command_line = None,
+            // Backspace past the `:` leaves the command line
+            Key::Named(NamedKey::Backspace) if line.is_empty() => self.command_line = None,
+            Key::Named(NamedKey::Backspace) => {
+                line.pop();
+            }
+            Key::Named(NamedKey::Space) => line.push(' '),
+            Key::Character(typed) => line.push_str(typed),
+            Key::Named(NamedKey::Enter) => {
+                let line = self.command_line.take().unwrap_or_default();
+                return self.run_command_line(text, line.trim());
+            }
+            _ => {}
+        }
+        VimResponse::Handled
+    }
+
+    /// `:q` closes the panel and `:<line>` goes to the line. Writing has no meaning here.
+    fn run_command_line(&mut self, text: &str, line: &str) -> VimResponse {
+        match line {
+            "q" | "q!" | "quit" | "quit!" | "clo" | "close" => {
+                VimResponse::Run(Command::ClosePanel(None))
+            }
+            line => {
+                if let Ok(number) = line.parse::<usize>() {
+                    let lines = Lines::new(text);
+                    self.column = None;
+                    self.cursor = lines.first_non_blank(number.clamp(1, lines.count()) - 1);
+                }
+                VimResponse::Handled
+            }
+        }
+    }
+}
+
+/// Where the lines of a text start. A newline at the very end doesn't start another line.
+struct Lines<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        if starts.len() > 1 && starts.last() == Some(&text.len()) {
+            starts.pop();
+        }
+        Self { text, starts }
+    }
+
+    fn count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The line `offset` is on
+    fn of(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    /// The bytes of `line`, without its newline
+    fn range(&self, line: usize) -> Range<usize> {
+        let start = self.starts[line];
+        let end = self.text[start..]
+            .find('\n')
+            .map_or(self.text.len(), |i| start + i);
+        start..end
+    }
+
+    fn is_empty(&self, line: usize) -> bool {
+        self.range(line).is_empty()
+    }
+
+    /// The nearest offset on a char, which in normal mode is where the cursor may be: the last
+    /// char of a line at most, or the start of an empty line
+    fn clamp(&self, offset: usize) -> usize {
+        let range = self.range(self.of(offset.min(self.text.len())));
+        if range.is_empty() {
+            range.start
+        } else {
+            offset.clamp(range.start, previous_char(self.text, range.end))
+        }
+    }
+
+    /// How many chars `offset` is from the start of its line
+    fn column(&self, offset: usize) -> usize {
+        let start = self.range(self.of(offset)).start;
+        self.text[start..offset].chars().count()
+    }
+
+    /// The char `column` chars into `line`, or its last char if it's shorter
+    fn at_column(&self, line: usize, column: usize) -> usize {
+        let range = self.range(line);
+        let offset = self.text[range.clone()]
+            .char_indices()
+            .nth(column)
+            .map_or(range.end, |(i, _)| range.start + i);
+        self.clamp(offset)
+    }
+
+    fn first_non_blank(&self, line: usize) -> usize {
+        let range = self.range(line);
+        let blanks = self.text[range.clone()]
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(range.len());
+        self.clamp(range.start + blanks)
+    }
+
+    /// The empty line `times` paragraphs down or up from `line`, or the last or first line
+    fn paragraph(&self, mut line: usize, times: usize, down: bool) -> usize {
+        let last = self.count() - 1;
+        let step = |line: usize| if down { line + 1 } else { line - 1 };
+        let at_end = |line: usize| if down { line == last } else { line == 0 };
+        for _ in 0..times {
+            // Leave the empty lines we're on, then go through the paragraph
+            while !at_end(line) && self.is_empty(line) {
+                line = step(line);
+            }
+            while !at_end(line) && !self.is_empty(line) {
+                line = step(line);
+            }
+        }
+        line
+    }
+}
+
+fn next_char(text: &str, offset: usize) -> usize {
+    offset + text[offset..].chars().next().map_or(0, char::len_utf8)
+}
+
+fn previous_char(text: &str, offset: usize) -> usize {
+    offset - text[..offset].chars().next_back().map_or(0, char::len_utf8)
+}
+
+/// What makes up a word: runs of letters, digits and `_`, or runs of other non-blank chars
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> Class {
+    if c.is_whitespace() {
+        Class::Blank
+    } else if c.is_alphanumeric() || c == '_' {
+        Class::Word
+    } else {
+        Class::Punctuation
+    }
+}
+
+/// The start of the next word after `offset`. Empty lines count as words.
+fn next_word_start(text: &str, offset: usize) -> usize {
+    let mut chars = text[offset..].char_indices().map(|(i, c)| (offset + i, c));
+    let Some((_, first)) = chars.next() else {
+        return offset;
+    };
+    let start_class = class(first);
+    let mut left_word = start_class == Class::Blank;
+    let mut previous = first;
+    for (i, c) in chars {
+        let empty_line = c == '\n' && previous == '\n';
+        if class(c) != start_class {
+            left_word = true;
+        }
+        if left_word && (class(c) != Class::Blank || empty_line) {
+            return i;
+        }
+        previous = c;
+    }
+    text.len()
+}
+
+/// The start of the word before `offset`, or of the one it's in
+fn previous_word_start(text: &str, offset: usize) -> usize {
+    let mut chars = text[..offset].char_indices().rev().peekable();
+    let mut word = None;
+    for (i, c) in chars.by_ref() {
+        if class(c) != Class::Blank {
+            word = Some((i, class(c)));
+            break;
+        }
+        if c == '\n' && (i == 0 || text[..i].ends_with('\n')) {
+            return i;
+        }
+    }
+    let Some((mut start, word_class)) = word else {
+        return 0;
+    };
+    while let Some(&(i, c)) = chars.peek() {
+        if class(c) != word_class {
+            break;
+        }
+        start = i;
+        chars.next();
+    }
+    start
+}
+
+/// The last char of the word after `offset`, or of the one it's in
+fn word_end(text: &str, offset: usize) -> usize {
+    let mut chars = text[offset..]
+        .char_indices()
+        .map(|(i, c)| (offset + i, c))
+        .skip(1)
+        .skip_while(|&(_, c)| class(c) == Class::Blank)
+        .peekable();
+    let Some((mut end, first)) = chars.next() else {
+        return offset;
+    };
+    while let Some(&(i, c)) = chars.peek() {
+        if class(c) != class(first) {
+            break;
+        }
+        end = i;
+        chars.next();
+    }
+    end
+}
+
+const BRACKETS: [(u8, u8); 4] = [(b'(', b')'), (b'[', b']'), (b'{', b'}'), (b'<', b'>')];
+
+/// The bracket matching the first one from `offset` to `line_end`, like `%`
+fn matching_bracket(text: &str, offset: usize, line_end: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let (at, &bracket) = bytes[offset..line_end]
+        .iter()
+        .enumerate()
+        .find(|(_, b)| BRACKETS[..3].iter().any(|&(o, c)| **b == o || **b == c))?;
+    let at = offset + at;
+    let &(open, close) = BRACKETS
+        .iter()
+        .find(|&&(o, c)| bracket == o || bracket == c)?;
+    if bracket == open {
+        closing_bracket(bytes, at, open, close)
+    } else {
+        opening_bracket(bytes, at, open, close, 1)
+    }
+}
+
+/// The `count`th unmatched `open` around `offset`. A closing bracket at `offset` is inside.
+fn opening_bracket(
+    bytes: &[u8],
+    offset: usize,
+    open: u8,
+    close: u8,
+    count: usize,
+) -> Option<usize> {
+    let mut depth = 0;
+    let mut left = count;
+    let last = offset.min(bytes.len().checked_sub(1)?);
+    for i in (0..=last).rev() {
+        if bytes[i] == close && i != offset {
+            depth += 1;
+        } else if bytes[i] == open {
+            if depth == 0 {
+                left -= 1;
+                if left == 0 {
+                    return Some(i);
+                }
+            } else {
+                depth -= 1;
+            }
+        }
+    }
+    None
+}
+
+/// The `close` that matches the `open` at `offset`
+fn closing_bracket(bytes: &[u8], offset: usize, open: u8, close: u8) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &b) in bytes.iter().enumerate().skip(offset + 1) {
+        if b == open {
+            depth += 1;
+        } else if b == close {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// The text object `c` around `offset`, like `w` for a word or `(` for what's in parentheses.
+/// `inner` leaves out the surrounding brackets, quotes or blanks.
+fn text_object(
+    lines: &Lines,
+    offset: usize,
+    c: char,
+    inner: bool,
+    count: Option<usize>,
+) -> Option<Range<usize>> {
+    let text = lines.text;
+    match c {
+        'w' => Some(word_object(lines, offset, inner)),
+        '"' | '\'' | '`' => quote_object(lines, offset, c, inner),
+        c => {
+            let (open, close) = match c {
+                '(' | ')' | 'b' => (b'(', b')'),
+                '[' | ']' => (b'[', b']'),
+                '{' | '}' | 'B' => (b'{', b'}'),
+                '<' | '>' => (b'<', b'>'),
+                _ => return None,
+            };
+            let bytes = text.as_bytes();
+            let start = opening_bracket(bytes, offset, open, close, count.unwrap_or(1))?;
+            let end = closing_bracket(bytes, start, open, close)?;
+            if !inner {
+                return Some(start..end + 1);
+            }
+            // Blocks spanning lines leave out the line breaks around their content
+            let mut inside = start + 1..end;
+            if text[inside.clone()].starts_with('\n') {
+                inside.start += 1;
+            }
+            let close_line = lines.range(lines.of(end));
+            if close_line.start > inside.start && text[close_line.start..end].trim().is_empty() {
+                inside.end = close_line.start;
+            }
+            Some(inside)
+        }
+    }
+}
+
+/// The run of word, punctuation or blank chars at `offset`, and with `a` the blanks after it,
+/// or before it if there are none after
+fn word_object(lines: &Lines, offset: usize, inner: bool) -> Range<usize> {
+    let text = lines.text;
+    let line = lines.range(lines.of(offset));
+    let Some(at) = text[offset..line.end].chars().next() else {
+        return offset..offset;
+    };
+    let same = |c: char| class(c) == class(at);
+    let run_start = |from: usize, same: &dyn Fn(char) -> bool| {
+        text[line.start..from]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| same(c))
+            .last()
+            .map_or(from, |(i, _)| line.start + i)
+    };
+    let run_end = |from: usize, same: &dyn Fn(char) -> bool| {
+        text[from..line.end]
+            .char_indices()
+            .find(|&(_, c)| !same(c))
+            .map_or(line.end, |(i, _)| from + i)
+    };
+    let start = run_start(offset, &same);
+    let end = run_end(offset, &same);
+    if inner || class(at) == Class::Blank {
+        return start..end;
+    }
+    let blank = |c: char| class(c) == Class::Blank;
+    let after = run_end(end, &blank);
+    if after > end {
+        start..after
+    } else {
+        run_start(start, &blank)..end
+    }
+}
+
+/// The quoted string on the line around `offset`, or the next one after it
+fn quote_object(lines: &Lines, offset: usize, quote: char, inner: bool) -> Option<Range<usize>> {
+    let text = lines.text;
+    let line = lines.range(lines.of(offset));
+    let quotes: Vec<usize> = text[line.clone()]
+        .char_indices()
+        .filter(|&(i, c)| c == quote && !text[line.start..line.start + i].ends_with('\\'))
+        .map(|(i, _)| line.start + i)
+        .collect();
+    let (start, end) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, end)| offset <= end)?;
+    Some(if inner {
+        start + 1..end
+    } else {
+        start..end + 1
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Press every key of `keys` in turn, Escape written as `<esc>` and Enter as `<cr>`
+    fn press(vim: &mut Vim, text: &str, keys: &str) -> Option<Command> {
+        let mut run = None;
+        let mut rest = keys;
+        while let Some(c) = rest.chars().next() {
+            let (key, len) = if rest.starts_with("<esc>") {
+                (Key::Named(NamedKey::Escape), 5)
+            } else if rest.starts_with("<cr>") {
+                (Key::Named(NamedKey::Enter), 4)
+            } else {
+                (Key::Character(c.to_string().into()), c.len_utf8())
+            };
+            rest = &rest[len..];
+            if let VimResponse::Run(command) = vim.key(text, &key, ModifiersState::empty()) {
+                run = Some(command);
+            }
+        }
+        run
+    }
+
+    /// Where the cursor is after `keys`, from the start of `text`
+    fn cursor_after(text: &str, keys: &str) -> usize {
+        let mut vim = Vim::default();
+        press(&mut vim, text, keys);
+        vim.cursor()
+    }
+
+    /// What is selected after `keys`, from the start of `text`
+    fn selected_after<'a>(text: &'a str, keys: &str) -> &'a str {
+        let mut vim = Vim::default();
+        press(&mut vim, text, keys);
+        &text[vim.selection(text)]
+    }
+
+    const CODE: &str = "fn main() {\n    let x = foo(1, bar(2));\n\n    println!(\"a b\");\n}\n";
+
+    #[test]
+    fn moves_by_chars_and_lines() {
+        assert_eq!(cursor_after("abc\ndef", "ll"), 2);
+        assert_eq!(cursor_after("abc\ndef", "lllll"), 2);
+        assert_eq!(cursor_after("abc\ndef", "lhhh"), 0);
+        assert_eq!(cursor_after("abc\ndef", "lj"), 5);
+        // The column is kept across shorter lines
+        assert_eq!(cursor_after("abcd\n\nabcd", "lllj"), 5);
+        assert_eq!(cursor_after("abcd\n\nabcd", "llljj"), 9);
+        assert_eq!(cursor_after("abcd\nab\nabcd", "$jj"), 11);
+        assert_eq!(cursor_after("ab\ncd\nef", "jjj"), 6);
+    }
+
+    #[test]
+    fn counts_repeat_motions() {
+        assert_eq!(cursor_after("abcdef", "3l"), 3);
+        assert_eq!(cursor_after("a\nb\nc\nd", "2j"), 4);
+        assert_eq!(cursor_after("one two three four", "2w"), 8);
+        assert_eq!(cursor_after("a\nb\nc\nd", "3G"), 4);
+        assert_eq!(cursor_after("a\nb\nc\nd", "G2gg"), 2);
+        assert_eq!(cursor_after("a\nb\nc\nd", "10j"), 6);
+        // Zero is a motion unless it's part of a count
+        assert_eq!(cursor_after("abcdefghijkl", "10l"), 10);
+        assert_eq!(cursor_after("abcdef", "$0"), 0);
+    }
+
+    #[test]
+    fn moves_by_words() {
+        let text = "foo.bar  baz\n\nqux";
+        assert_eq!(cursor_after(text, "w"), 3);
+        assert_eq!(cursor_after(text, "ww"), 4);
+        assert_eq!(cursor_after(text, "www"), 9);
+        // Empty lines are words
+        assert_eq!(cursor_after(text, "wwww"), 13);
+        assert_eq!(cursor_after(text, "wwwww"), 14);
+        assert_eq!(cursor_after(text, "Gb"), 13);
+        assert_eq!(cursor_after(text, "Gbb"), 9);
+        assert_eq!(cursor_after(text, "e"), 2);
+        assert_eq!(cursor_after(text, "ee"), 3);
+        assert_eq!(cursor_after(text, "eee"), 6);
+    }
+
+    #[test]
+    fn moves_on_the_line() {
+        let text = "    let x = (a, (b));";
+        assert_eq!(cursor_after(text, "^"), 4);
+        assert_eq!(cursor_after(text, "$"), 20);
+        assert_eq!(cursor_after(text, "f("), 12);
+        assert_eq!(cursor_after(text, "2f("), 16);
+        assert_eq!(cursor_after(text, "f(;"), 16);
+        assert_eq!(cursor_after(text, "t("), 11);
+        assert_eq!(cursor_after(text, "t(;"), 15);
+        assert_eq!(cursor_after(text, "$F("), 16);
+        assert_eq!(cursor_after(text, "$T(;"), 13);
+        assert_eq!(cursor_after(text, "f(;,"), 12);
+        assert_eq!(cursor_after(text, "fz"), 0);
+        // From the first bracket on the line to its match and back
+        assert_eq!(cursor_after(text, "%"), 19);
+        assert_eq!(cursor_after(text, "%%"), 12);
+    }
+
+    #[test]
+    fn moves_by_paragraphs() {
+        assert_eq!(cursor_after(CODE, "}"), 40);
+        assert_eq!(cursor_after(CODE, "}}"), 62);
+        assert_eq!(cursor_after(CODE, "G{"), 40);
+        assert_eq!(cursor_after(CODE, "G2{"), 0);
+    }
+
+    #[test]
+    fn selects_chars_and_lines() {
+        assert_eq!(selected_after("abc def", "vl"), "ab");
+        assert_eq!(selected_after("abc def", "wvh"), " d");
+        assert_eq!(selected_after("abc def", "vwo"), "abc d");
+        assert_eq!(selected_after("ab\ncd\nef", "jV"), "cd\n");
+        assert_eq!(selected_after("ab\ncd\nef", "Vj"), "ab\ncd\n");
+        assert_eq!(selected_after("ab\ncd\nef", "VG"), "ab\ncd\nef");
+        assert_eq!(selected_after("ab\ncd", "vl<esc>"), "");
+        assert_eq!(selected_after("ab\ncd", "vlv"), "");
+    }
+
+    #[test]
+    fn selects_text_objects() {
+        assert_eq!(selected_after(CODE, "jwwwwviw"), "foo");
+        // Without blanks after it, `aw` takes those before
+        assert_eq!(selected_after(CODE, "jwwwwvaw"), " foo");
+        assert_eq!(selected_after(CODE, "jwvaw"), "let ");
+        assert_eq!(selected_after(CODE, "jf2vi("), "2");
+        assert_eq!(selected_after(CODE, "jf2va)"), "(2)");
+        assert_eq!(selected_after(CODE, "jf2v2ib"), "1, bar(2)");
+        assert_eq!(selected_after(CODE, "jvi{"), &CODE[12..62]);
+        assert_eq!(selected_after(CODE, "jvaB"), &CODE[10..63]);
+        assert_eq!(selected_after(CODE, "3jvi\""), "a b");
+        assert_eq!(selected_after(CODE, "3jva\""), "\"a b\"");
+        // Nothing to select leaves visual mode as it was
+        assert_eq!(selected_after(CODE, "vi["), "f");
+    }
+
+    #[test]
+    fn takes_over_other_selections() {
+        let mut vim = Vim::default();
+        vim.select("abc def", 4..7);
+        assert_eq!(vim.mode(), Mode::Visual);
+        assert_eq!(vim.selection("abc def"), 4..7);
+        vim.select("abc\n", 4..4);
+        assert_eq!(vim.mode(), Mode::Normal);
+        assert_eq!(vim.cursor(), 2);
+    }
+
+    #[test]
+    fn runs_command_lines() {
+        let mut vim = Vim::default();
+        assert_eq!(
+            press(&mut vim, CODE, ":q<cr>"),
+            Some(Command::ClosePanel(None))
+        );
+        assert_eq!(press(&mut vim, CODE, ":4<cr>"), None);
+        assert_eq!(vim.cursor(), 45);
+        assert_eq!(press(&mut vim, CODE, ":q<esc>"), None);
+        assert_eq!(vim.command_line(), None);
+        assert_eq!(press(&mut vim, CODE, ":w<cr>"), None);
+        let ctrl = vim.key(CODE, &Key::Character("q".into()), ModifiersState::CONTROL);
+        assert!(matches!(ctrl, VimResponse::Ignored));
+    }
+}
@@ -0,0 +1,131 @@
+// The workspace file, which keeps the layout of the panels and their groups between sessions
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use masonry::{Point, Size};
+use serde_json::{json, Value};
+
+/// Which panel saved state belongs to: the file it shows, and the symbol in it if it shows one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanelKey {
+    pub path: PathBuf,
+    pub symbol: Option<String>,
+}
+
+impl PanelKey {
+    fn to_json(&self) -> Value {
+        json!({ "path": self.path, "symbol": self.symbol })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            path: PathBuf::from(value["path"].as_str()?),
+            symbol: value["symbol"].as_str().map(str::to_string),
+        })
+    }
+}
+
+/// Where a panel was, matched to the panels of the next session by its key
+pub struct PanelState {
+    pub key: PanelKey,
+    pub position: Point,
+    /// The size the user resized the panel to, if any
+    pub size: Option<Size>,
+    pub collapsed: bool,
+}
+
+pub struct GroupState {
+    pub name: String,
+    pub members: Vec<PanelKey>,
+    pub collapsed: bool,
+}
+
+#[derive(Default)]
+pub struct Workspace {
+    pub panels: Vec<PanelState>,
+    pub groups: Vec<GroupState>,
+}
+
+impl Workspace {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let value: Value = serde_json::from_str(&text)?;
+        Self::from_json(&value)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed workspace file"))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(&self.to_json())?;
+        fs::write(path, text)
+    }
+
+    fn to_json(&self) -> Value {
+        let panels: Vec<Value> = self
+            .panels
+            .iter()
+            .map(|panel| {
+                json!({
+                    "path": panel.key.path,
+                    "symbol": panel.key.symbol,
+                    "x": panel.position.x,
+                    "y": panel.position.y,
+                    "size": panel.size.map(|size| json!([size.width, size.height])),
+                    "collapsed": panel.collapsed,
+                })
+            })
+            .collect();
+        let groups: Vec<Value> = self
+            .groups
+            .iter()
+            .map(|group| {
+                json!({
+                    "name": group.name,
+                    "members": group.members.iter().map(PanelKey::to_json).collect::<Vec<_>>(),
+                    "collapsed": group.collapsed,
+                })
+            })
+            .collect();
+        json!({ "panels": panels, "groups": groups })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let panels = value["panels"]
+            .as_array()?
+            .iter()
+            .map(|panel| {
+                let size = match panel["size"].as_array() {
+                    Some(size) => Some(Size::new(size.first()?.as_f64()?, size.get(1)?.as_f64()?)),
+                    None => None,
+                };
+                Some(PanelState {
+                    key: PanelKey::from_json(panel)?,
+                    position: Point::new(panel["x"].as_f64()?, panel["y"].as_f64()?),
+                    size,
+                    collapsed: panel["collapsed"].as_bool().unwrap_or(false),
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let groups = value["groups"]
+            .as_array()?
+            .iter()
+            .map(|group| {
+                let members = group["members"]
+                    .as_array()?
+                    .iter()
+                    .map(PanelKey::from_json)
+                    .collect::<Option<Vec<_>>>()?;
+                Some(GroupState {
+                    name: group["name"].as_str()?.to_string(),
+                    members,
+                    collapsed: group["collapsed"].as_bool().unwrap_or(false),
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self { panels, groups })
+    }
+}